@@ -1,6 +1,8 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use image::DynamicImage;
 use img_squeeze::processing::{
     load_image_with_metadata, process_and_save_image, resize_image, CompressionOptions,
+    ResizeFilter,
 };
 use std::fs::File;
 use std::io::Write;
@@ -25,9 +27,7 @@ fn create_test_image(width: u32, height: u32) -> (PathBuf, TempDir) {
     buffer.extend_from_slice(&[0x00, 0x00]); // Y thumbnail
 
     // Fill with fake image data
-    for _ in 0..(width * height * 3) {
-        buffer.push(0xFF);
-    }
+    buffer.resize(buffer.len() + (width * height * 3) as usize, 0xFF);
 
     let mut file = File::create(&test_file).unwrap();
     file.write_all(&buffer).unwrap();
@@ -88,6 +88,36 @@ fn bench_image_resizing(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_resize_filters(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resize_filters");
+    let img = DynamicImage::new_rgb8(1920, 1080);
+
+    for filter in [
+        ResizeFilter::Nearest,
+        ResizeFilter::Triangle,
+        ResizeFilter::CatmullRom,
+        ResizeFilter::Gaussian,
+        ResizeFilter::Lanczos3,
+    ] {
+        let options = CompressionOptions::new(Some(80), Some(960), Some(540), None)
+            .unwrap()
+            .with_filter(filter);
+
+        group.bench_with_input(
+            BenchmarkId::new("filter", format!("{:?}", filter)),
+            &options,
+            |b, options| {
+                b.iter(|| {
+                    let mut img = img.clone();
+                    resize_image(black_box(&mut img), black_box(options));
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
 fn bench_image_processing(c: &mut Criterion) {
     let (test_file, _temp_dir) = create_test_image(1920, 1080);
     let output_dir = TempDir::new().unwrap();
@@ -152,6 +182,7 @@ criterion_group!(
     bench_compression_options_creation,
     bench_image_loading,
     bench_image_resizing,
+    bench_resize_filters,
     bench_image_processing,
     bench_batch_processing
 );
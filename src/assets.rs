@@ -0,0 +1,356 @@
+use crate::constants::{ASSET_MANIFEST_FILE, ASSET_MANIFEST_VERSION};
+use crate::error::Result;
+use crate::incremental::hash_file;
+use crate::processing::CompressionReport;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Formats tried, in order, for the `<img>` fallback of a `<picture>`
+const FALLBACK_FORMATS: [&str; 4] = ["jpeg", "png", "webp", "avif"];
+/// Formats offered as `<source>` elements before any other, most efficient first
+const SOURCE_FORMATS: [&str; 4] = ["avif", "webp", "png", "jpeg"];
+
+/// Files describing a batch's outputs for static site generators
+#[derive(Debug, Clone, Default)]
+pub struct AssetOptions {
+    /// Write `ASSET_MANIFEST_FILE` into the output directory
+    pub manifest: bool,
+    /// Write a `<picture>` snippet next to the outputs of each source
+    pub html: bool,
+    /// `sizes` attribute of the snippets; empty omits it
+    pub sizes: String,
+    /// Prepended to the output-relative paths in the snippets, e.g. `/static/img/`
+    pub base_url: String,
+}
+
+/// Maps every source of a batch to the files written for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetManifest {
+    pub version: u32,
+    /// Keyed by the source's path relative to the output directory, e.g. `photos/hero.png`
+    pub sources: BTreeMap<String, SourceAssets>,
+}
+
+/// Everything written for one source image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceAssets {
+    /// Source path as the batch read it
+    pub source: PathBuf,
+    /// In the order they were written, i.e. by width then format for renditions
+    pub outputs: Vec<AssetOutput>,
+    /// Output-relative path of the `<picture>` snippet, if one was written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+}
+
+/// One written file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetOutput {
+    /// Path relative to the output directory, with `/` separators
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: String,
+    pub bytes: u64,
+    /// Hex SHA-256 of the file contents
+    pub sha256: String,
+}
+
+impl AssetOptions {
+    /// Whether any asset file is requested
+    pub fn is_enabled(&self) -> bool {
+        self.manifest || self.html
+    }
+}
+
+impl AssetManifest {
+    /// Groups compression reports by source and hashes their outputs
+    ///
+    /// # Arguments
+    /// * `files` - Reports of the files written by a batch
+    /// * `output_dir` - Batch output directory the paths are made relative to
+    ///
+    /// # Returns
+    /// * `Ok(AssetManifest)` - One entry per source
+    /// * `Err(CompressionError)` - If an output cannot be read
+    pub fn collect(files: &[CompressionReport], output_dir: &Path) -> Result<Self> {
+        let mut sources: BTreeMap<String, SourceAssets> = BTreeMap::new();
+        for file in files {
+            let path = relative_url(&file.output, output_dir);
+            let source_name = file
+                .input
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let key = match path.rsplit_once('/') {
+                Some((dir, _)) => format!("{}/{}", dir, source_name),
+                None => source_name,
+            };
+
+            let (width, height) = file.encode.dimensions;
+            sources
+                .entry(key)
+                .or_insert_with(|| SourceAssets {
+                    source: file.input.clone(),
+                    outputs: Vec::new(),
+                    html: None,
+                })
+                .outputs
+                .push(AssetOutput {
+                    sha256: hash_file(&file.output)?,
+                    path,
+                    width,
+                    height,
+                    format: file.format.clone(),
+                    bytes: file.encode.compressed_size,
+                });
+        }
+
+        Ok(Self {
+            version: ASSET_MANIFEST_VERSION,
+            sources,
+        })
+    }
+}
+
+/// Writes the requested asset manifest and HTML snippets for a finished batch.
+///
+/// Snippets are named after their source (`hero.png` -> `hero.png.html`) and
+/// placed next to its outputs.
+///
+/// # Arguments
+/// * `files` - Reports of the files written by the batch
+/// * `output_dir` - Batch output directory
+/// * `options` - Which files to write and how to link the outputs
+///
+/// # Returns
+/// * `Ok(paths)` - The files written, snippets first and the manifest last
+/// * `Err(CompressionError)` - If an output cannot be read or a file cannot be written
+pub fn write_assets(
+    files: &[CompressionReport],
+    output_dir: &Path,
+    options: &AssetOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut manifest = AssetManifest::collect(files, output_dir)?;
+    let mut written = Vec::new();
+
+    if options.html {
+        for (key, assets) in manifest.sources.iter_mut() {
+            let html_key = format!("{}.html", key);
+            let html_path = output_dir.join(&html_key);
+            fs::write(
+                &html_path,
+                picture_html(assets, &options.sizes, &options.base_url),
+            )?;
+            assets.html = Some(html_key);
+            written.push(html_path);
+        }
+    }
+
+    if options.manifest {
+        let path = output_dir.join(ASSET_MANIFEST_FILE);
+        fs::write(&path, serde_json::to_vec_pretty(&manifest)?)?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Renders a `<picture>` element offering every output of a source.
+///
+/// Each format gets a `srcset` of its widths. The most widely supported format
+/// becomes the `<img>` fallback, sized after its widest output; the others
+/// become `<source>` elements, most efficient first.
+///
+/// # Arguments
+/// * `assets` - Outputs of one source
+/// * `sizes` - `sizes` attribute; empty omits it
+/// * `base_url` - Prepended to every output path, which is percent-encoded
+///   segment by segment so spaces and commas cannot break a `srcset`
+pub fn picture_html(assets: &SourceAssets, sizes: &str, base_url: &str) -> String {
+    let mut formats: Vec<&str> = Vec::new();
+    for output in &assets.outputs {
+        if !formats.contains(&output.format.as_str()) {
+            formats.push(&output.format);
+        }
+    }
+    let fallback = FALLBACK_FORMATS
+        .into_iter()
+        .find(|format| formats.contains(format))
+        .or_else(|| formats.first().copied())
+        .unwrap_or_default();
+    let mut source_formats: Vec<&str> = SOURCE_FORMATS
+        .into_iter()
+        .filter(|format| formats.contains(format))
+        .collect();
+    source_formats.extend(formats.iter().filter(|f| !SOURCE_FORMATS.contains(f)));
+    source_formats.retain(|format| *format != fallback);
+
+    let sizes_attr = if sizes.is_empty() {
+        String::new()
+    } else {
+        format!(" sizes=\"{}\"", escape_attribute(sizes))
+    };
+    let srcset = |format: &str| {
+        assets
+            .outputs
+            .iter()
+            .filter(|output| output.format == format)
+            .map(|output| {
+                format!(
+                    "{}{} {}w",
+                    base_url,
+                    encode_url_path(&output.path),
+                    output.width
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut html = String::from("<picture>\n");
+    for format in source_formats {
+        html.push_str(&format!(
+            "  <source type=\"{}\" srcset=\"{}\"{}>\n",
+            mime_type(format),
+            escape_attribute(&srcset(format)),
+            sizes_attr
+        ));
+    }
+    if let Some(largest) = assets
+        .outputs
+        .iter()
+        .filter(|output| output.format == fallback)
+        .max_by_key(|output| output.width)
+    {
+        html.push_str(&format!(
+            "  <img src=\"{}\" srcset=\"{}\"{} width=\"{}\" height=\"{}\" alt=\"\">\n",
+            escape_attribute(&format!("{}{}", base_url, encode_url_path(&largest.path))),
+            escape_attribute(&srcset(fallback)),
+            sizes_attr,
+            largest.width,
+            largest.height
+        ));
+    }
+    html.push_str("</picture>\n");
+    html
+}
+
+fn mime_type(format: &str) -> String {
+    match format {
+        "jpeg" | "jpg" => "image/jpeg".to_string(),
+        other => format!("image/{}", other),
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Percent-encodes every `/`-separated segment of a relative path, keeping
+/// only RFC 3986 unreserved characters as they are
+fn encode_url_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            segment
+                .bytes()
+                .map(|byte| match byte {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        (byte as char).to_string()
+                    }
+                    _ => format!("%{:02X}", byte),
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `path` relative to `output_dir`, with `/` separators
+pub(crate) fn relative_url(path: &Path, output_dir: &Path) -> String {
+    let relative = path.strip_prefix(output_dir).unwrap_or(path);
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(path: &str, width: u32, format: &str) -> AssetOutput {
+        AssetOutput {
+            path: path.to_string(),
+            width,
+            height: width / 2,
+            format: format.to_string(),
+            bytes: 100,
+            sha256: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_picture_html_prefers_jpeg_fallback() {
+        let assets = SourceAssets {
+            source: PathBuf::from("hero.png"),
+            outputs: vec![
+                output("a/hero-320w.jpg", 320, "jpeg"),
+                output("a/hero-320w.webp", 320, "webp"),
+                output("a/hero-320w.avif", 320, "avif"),
+                output("a/hero-640w.jpg", 640, "jpeg"),
+                output("a/hero-640w.webp", 640, "webp"),
+                output("a/hero-640w.avif", 640, "avif"),
+            ],
+            html: None,
+        };
+        let html = picture_html(&assets, "(max-width: 640px) 100vw, 640px", "/img/");
+        assert_eq!(
+            html,
+            "<picture>\n\
+             \x20 <source type=\"image/avif\" srcset=\"/img/a/hero-320w.avif 320w, /img/a/hero-640w.avif 640w\" sizes=\"(max-width: 640px) 100vw, 640px\">\n\
+             \x20 <source type=\"image/webp\" srcset=\"/img/a/hero-320w.webp 320w, /img/a/hero-640w.webp 640w\" sizes=\"(max-width: 640px) 100vw, 640px\">\n\
+             \x20 <img src=\"/img/a/hero-640w.jpg\" srcset=\"/img/a/hero-320w.jpg 320w, /img/a/hero-640w.jpg 640w\" sizes=\"(max-width: 640px) 100vw, 640px\" width=\"640\" height=\"320\" alt=\"\">\n\
+             </picture>\n"
+        );
+    }
+
+    #[test]
+    fn test_picture_html_single_modern_format() {
+        let assets = SourceAssets {
+            source: PathBuf::from("logo.png"),
+            outputs: vec![output("logo \"x\".avif", 200, "avif")],
+            html: None,
+        };
+        let html = picture_html(&assets, "", "");
+        assert!(!html.contains("<source"));
+        assert!(html.contains("src=\"logo%20%22x%22.avif\""));
+        assert!(!html.contains("sizes="));
+    }
+
+    #[test]
+    fn test_picture_html_encodes_spaces_and_commas() {
+        let assets = SourceAssets {
+            source: PathBuf::from("a,b.png"),
+            outputs: vec![
+                output("summer trip/a,b-320w.jpg", 320, "jpeg"),
+                output("summer trip/a,b-640w.jpg", 640, "jpeg"),
+                output("summer trip/héro 1.webp", 640, "webp"),
+            ],
+            html: None,
+        };
+        let html = picture_html(&assets, "", "/img/");
+        assert!(html.contains(
+            "srcset=\"/img/summer%20trip/a%2Cb-320w.jpg 320w, /img/summer%20trip/a%2Cb-640w.jpg 640w\""
+        ));
+        assert!(html.contains("src=\"/img/summer%20trip/a%2Cb-640w.jpg\""));
+        assert!(html.contains("srcset=\"/img/summer%20trip/h%C3%A9ro%201.webp 640w\""));
+    }
+}
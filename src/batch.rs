@@ -1,16 +1,26 @@
+use crate::assets::{write_assets, AssetOptions};
 use crate::constants::{
-    LARGE_IMAGE_THRESHOLD_MIB, MAX_BATCH_FILES, MAX_BATCH_MEMORY_MIB, MAX_CONCURRENT_LARGE_IMAGES,
-    MIN_AVAILABLE_MEMORY_MIB,
+    EXIT_PARTIAL_FAILURE, EXIT_TOTAL_FAILURE, INCREMENTAL_SAVE_INTERVAL, LARGE_IMAGE_THRESHOLD_MIB,
+    MAX_BATCH_FILES, MAX_BATCH_MEMORY_MIB, MAX_CONCURRENT_LARGE_IMAGES, MIN_AVAILABLE_MEMORY_MIB,
 };
 use crate::error::{CompressionError, Result};
-use crate::processing::{process_image_pipeline, CompressionOptions};
+use crate::incremental::{options_fingerprint, Freshness, IncrementalManifest, ManifestEntry};
+use crate::processing::{
+    determine_output_format, process_image_pipeline, CompressionOptions, CompressionReport,
+};
+use crate::quality::ssim_working_set_bytes;
+use crate::renditions::{process_renditions, PlannedRendition, Renditions};
+use crate::upload::{compress_and_upload_with, UploadTarget};
+use crate::walrus::WalrusUploader;
 use glob::glob;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
 use std::time::Instant;
 use sysinfo::{MemoryRefreshKind, RefreshKind, System};
 use walkdir::WalkDir;
@@ -19,11 +29,12 @@ use walkdir::WalkDir;
 ///
 /// # Arguments
 /// * `file_path` - Path to the image file
+/// * `options` - Compression options; a perceptual quality search needs more memory
 ///
 /// # Returns
 /// * `Ok(memory_mib)` - Estimated memory usage in MiB
 /// * `Err(CompressionError)` - If file metadata cannot be read
-fn estimate_image_memory_usage(file_path: &Path) -> Result<f64> {
+fn estimate_image_memory_usage(file_path: &Path, options: &CompressionOptions) -> Result<f64> {
     let metadata = fs::metadata(file_path)?;
     let file_size_mib = metadata.len() as f64 / (1024.0 * 1024.0);
 
@@ -42,18 +53,31 @@ fn estimate_image_memory_usage(file_path: &Path) -> Result<f64> {
         None => 3.0,
     };
 
-    Ok(file_size_mib * multiplier)
+    let decoded_mib = file_size_mib * multiplier;
+    if options.target_ssim.is_none() {
+        return Ok(decoded_mib);
+    }
+
+    // --target-ssim decodes every candidate next to the source and scores it
+    // over a window of rows as wide as the image
+    let width = image::image_dimensions(file_path).map_or(0, |(width, _)| width);
+    let scoring_mib = ssim_working_set_bytes(width) as f64 / (1024.0 * 1024.0);
+    Ok(decoded_mib * 2.0 + scoring_mib)
 }
 
 /// Validates batch memory requirements before processing.
 ///
 /// # Arguments
 /// * `image_files` - List of image file paths to process
+/// * `options` - Compression options the files are processed with
 ///
 /// # Returns
 /// * `Ok((total_memory_mib, large_image_count))` - Estimated memory usage and count of large images
 /// * `Err(CompressionError)` - If memory limits would be exceeded
-fn validate_batch_memory_limits(image_files: &[PathBuf]) -> Result<(f64, usize)> {
+fn validate_batch_memory_limits(
+    image_files: &[PathBuf],
+    options: &CompressionOptions,
+) -> Result<(f64, usize)> {
     // Check file count limit
     if image_files.len() > MAX_BATCH_FILES {
         return Err(CompressionError::BatchFileLimitExceeded(
@@ -67,7 +91,7 @@ fn validate_batch_memory_limits(image_files: &[PathBuf]) -> Result<(f64, usize)>
 
     // Estimate memory usage for each file
     for file_path in image_files {
-        let memory_estimate = estimate_image_memory_usage(file_path)?;
+        let memory_estimate = estimate_image_memory_usage(file_path, options)?;
         total_memory_mib += memory_estimate;
 
         if memory_estimate > LARGE_IMAGE_THRESHOLD_MIB {
@@ -102,44 +126,342 @@ fn validate_batch_memory_limits(image_files: &[PathBuf]) -> Result<(f64, usize)>
     Ok((total_memory_mib, large_image_count))
 }
 
-pub fn batch_compress_images(
-    input: String,
-    output: PathBuf,
-    options: CompressionOptions,
-    recursive: bool,
-) -> Result<()> {
-    println!("🚀 Starting batch compression...");
-    println!("📁 Input: {}", input);
-    println!("📁 Output: {:?}", output);
+/// Settings for a batch run beyond the per-image `CompressionOptions`
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// Descend into subdirectories and mirror them under the output directory
+    pub recursive: bool,
+    /// Stop scheduling new files after the first failure
+    pub fail_fast: bool,
+    /// Skip inputs whose output is up to date according to the manifest in the output directory.
+    /// Not supported together with `renditions`, which it is ignored for.
+    pub incremental: bool,
+    /// Write a responsive set of widths and formats per input instead of one output
+    pub renditions: Option<Renditions>,
+    /// Asset manifest and HTML snippets written once the batch is done.
+    /// Inputs skipped by `incremental` are not listed in them.
+    pub assets: AssetOptions,
+    /// Upload every output to Walrus. Not supported together with `renditions`
+    /// or `incremental`, nor with `assets` unless local files are kept.
+    pub upload: Option<UploadTarget>,
+}
 
-    let start_time = Instant::now();
+/// A file that could not be compressed during a batch run
+#[derive(Debug, Serialize)]
+pub struct BatchFailure {
+    pub path: PathBuf,
+    #[serde(serialize_with = "serialize_error")]
+    pub error: CompressionError,
+}
 
-    // 收集所有图片文件
-    let image_files = collect_image_files(&input, recursive)?;
-    let total_files = image_files.len();
+fn serialize_error<S: serde::Serializer>(
+    error: &CompressionError,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(error)
+}
 
-    if total_files == 0 {
-        println!("⚠️  No image files found in the input path");
-        return Ok(());
+/// How a batch run ended, as reflected in the process exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchOutcome {
+    /// Every file was compressed (or there was nothing to do)
+    Success,
+    /// Some files were compressed, others failed or were cancelled
+    PartialFailure,
+    /// No file was compressed
+    TotalFailure,
+}
+
+impl BatchOutcome {
+    /// Process exit code for this outcome
+    pub fn exit_code(self) -> u8 {
+        match self {
+            BatchOutcome::Success => 0,
+            BatchOutcome::PartialFailure => EXIT_PARTIAL_FAILURE,
+            BatchOutcome::TotalFailure => EXIT_TOTAL_FAILURE,
+        }
+    }
+}
+
+/// Outcome of a batch run
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    pub outcome: BatchOutcome,
+    pub input: String,
+    pub output: PathBuf,
+    /// Number of image files found in the input
+    pub total_files: usize,
+    pub estimated_memory_mib: f64,
+    /// Files above `LARGE_IMAGE_THRESHOLD_MIB`
+    pub large_image_count: usize,
+    /// Parallel threads used for processing
+    pub threads: usize,
+    /// Successfully compressed files
+    pub files: Vec<CompressionReport>,
+    pub failures: Vec<BatchFailure>,
+    /// Files left unprocessed after a failure with `fail_fast`
+    pub cancelled: Vec<PathBuf>,
+    /// Inputs whose output was already up to date (`incremental`)
+    pub skipped: Vec<PathBuf>,
+    /// Asset manifest and HTML snippets written after the batch
+    pub assets: Vec<PathBuf>,
+    pub total_original_size: u64,
+    pub total_compressed_size: u64,
+    /// Percentage of the total input size saved
+    pub compression_ratio: f64,
+    /// Mean encoder quality over the compressed files
+    pub average_quality: Option<f64>,
+    /// Files that missed `--target-size` or `--target-ssim`
+    pub target_missed: usize,
+    pub elapsed_secs: f64,
+}
+
+impl BatchReport {
+    fn new(input: String, output: PathBuf) -> Self {
+        Self {
+            outcome: BatchOutcome::Success,
+            input,
+            output,
+            total_files: 0,
+            estimated_memory_mib: 0.0,
+            large_image_count: 0,
+            threads: 0,
+            files: Vec::new(),
+            failures: Vec::new(),
+            cancelled: Vec::new(),
+            skipped: Vec::new(),
+            assets: Vec::new(),
+            total_original_size: 0,
+            total_compressed_size: 0,
+            compression_ratio: 0.0,
+            average_quality: None,
+            target_missed: 0,
+            elapsed_secs: 0.0,
+        }
     }
 
-    println!("📊 Found {} image files to process", total_files);
+    /// Fills in the totals from the per-file reports
+    fn summarize(&mut self, start_time: Instant) {
+        // Renditions share their source, which is counted once
+        let mut sources = HashMap::new();
+        for file in &self.files {
+            sources.insert(&file.input, file.original_size);
+        }
+        self.total_original_size = sources.values().sum();
+        self.total_compressed_size = self.files.iter().map(|f| f.encode.compressed_size).sum();
+        self.compression_ratio = if self.total_original_size > 0 {
+            ((self.total_original_size as f64 - self.total_compressed_size as f64)
+                / self.total_original_size as f64)
+                * 100.0
+        } else {
+            0.0
+        };
+        self.average_quality = (!self.files.is_empty()).then(|| {
+            self.files
+                .iter()
+                .map(|f| f.encode.quality as f64)
+                .sum::<f64>()
+                / self.files.len() as f64
+        });
+        self.target_missed = self
+            .files
+            .iter()
+            .filter(|f| f.encode.target_met == Some(false))
+            .count();
+        self.outcome = if self.failures.is_empty() && self.cancelled.is_empty() {
+            BatchOutcome::Success
+        } else if self.files.is_empty() && self.skipped.is_empty() {
+            BatchOutcome::TotalFailure
+        } else {
+            BatchOutcome::PartialFailure
+        };
+        self.elapsed_secs = start_time.elapsed().as_secs_f64();
+    }
+}
+
+/// One input of a batch plan
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlannedFile {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// Output container, e.g. `webp`
+    pub format: String,
+    pub estimated_memory_mib: f64,
+    /// A file already exists at `output` and would be replaced
+    pub overwrites: bool,
+    /// `incremental` would skip this input because its output is up to date
+    pub up_to_date: bool,
+    /// What the incremental check found, handed to the run so it does not
+    /// check (and hash) the input again
+    #[serde(skip)]
+    pub(crate) freshness: Option<Freshness>,
+    /// With `renditions`, every file the input would be rendered to; `output`
+    /// is then the common base path they are derived from
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub renditions: Vec<PlannedRendition>,
+}
+
+/// What a batch run would do, worked out without decoding or writing any image
+#[derive(Debug, Serialize)]
+pub struct BatchPlan {
+    pub input: String,
+    pub output: PathBuf,
+    pub estimated_memory_mib: f64,
+    /// Files above `LARGE_IMAGE_THRESHOLD_MIB`
+    pub large_image_count: usize,
+    /// Parallel threads the run would use
+    pub threads: usize,
+    /// Inputs that would be compressed (or skipped when up to date), in path order
+    pub files: Vec<PlannedFile>,
+    /// Inputs that cannot be written, e.g. because of an output collision
+    pub failures: Vec<BatchFailure>,
+    /// The incremental manifest the plan was checked against, with deleted
+    /// inputs already pruned; the run records into and saves this copy
+    #[serde(skip)]
+    pub(crate) manifest: Option<IncrementalManifest>,
+}
+
+/// Rejects batch options that cannot be combined, before any file is read
+fn validate_batch_options(batch_options: &BatchOptions) -> Result<()> {
+    if let Some(target) = &batch_options.upload {
+        if batch_options.renditions.is_some() || batch_options.incremental {
+            return Err(CompressionError::Config(
+                "uploading is not supported with renditions or incremental batches".to_string(),
+            ));
+        }
+        if !target.keep_local && batch_options.assets.is_enabled() {
+            return Err(CompressionError::Config(
+                "asset files describe local outputs, which are not kept".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Plans a batch run: collects the inputs, checks the memory limits, chooses
+/// the parallelism and assigns every input its output path.
+///
+/// Only file metadata is read (plus input contents for `incremental` inputs
+/// whose modification time changed); nothing is decoded and nothing is written.
+///
+/// # Arguments
+/// * `input` - Directory, single file or glob pattern
+/// * `output` - Output directory
+/// * `options` - Compression options applied to every file
+/// * `batch_options` - Recursion, incremental and rendition settings
+///
+/// # Returns
+/// * `Ok(BatchPlan)` - The planned outputs and any inputs that cannot be written
+/// * `Err(CompressionError)` - If the batch options conflict, the input cannot be
+///   read or the batch exceeds resource limits
+pub fn plan_batch(
+    input: &str,
+    output: &Path,
+    options: &CompressionOptions,
+    batch_options: &BatchOptions,
+) -> Result<BatchPlan> {
+    validate_batch_options(batch_options)?;
+
+    // 收集所有图片文件
+    let image_files = collect_image_files(input, batch_options.recursive)?;
+
+    let mut plan = BatchPlan {
+        input: input.to_string(),
+        output: output.to_path_buf(),
+        estimated_memory_mib: 0.0,
+        large_image_count: 0,
+        threads: 0,
+        files: Vec::new(),
+        failures: Vec::new(),
+        manifest: None,
+    };
+    if image_files.is_empty() {
+        return Ok(plan);
+    }
 
     // Security: Validate batch memory requirements before processing
-    println!("🔍 Validating batch memory requirements...");
-    let (estimated_memory_mib, large_image_count) = validate_batch_memory_limits(&image_files)?;
+    let (estimated_memory_mib, large_image_count) =
+        validate_batch_memory_limits(&image_files, options)?;
+    plan.estimated_memory_mib = estimated_memory_mib;
+    plan.large_image_count = large_image_count;
+    plan.threads = choose_parallelism(image_files.len(), estimated_memory_mib, large_image_count);
+
+    // --incremental: 读取输出目录中的清单，并忘记已删除的输入
+    let fingerprint = if batch_options.incremental && batch_options.renditions.is_none() {
+        let mut manifest = IncrementalManifest::load(output)?;
+        manifest.prune_missing();
+        plan.manifest = Some(manifest);
+        Some(options_fingerprint(options)?)
+    } else {
+        None
+    };
 
-    println!("📊 Batch validation complete:");
-    println!("  📁 Total files: {}", total_files);
-    println!(
-        "  💾 Estimated memory usage: {:.1} MiB",
-        estimated_memory_mib
-    );
-    println!(
-        "  📏 Large images (>{}MiB): {}",
-        LARGE_IMAGE_THRESHOLD_MIB, large_image_count
-    );
+    // 规划输出路径，检测重名冲突
+    let input_root = mirror_root(input, batch_options.recursive);
+    let planned_outputs = match &batch_options.renditions {
+        Some(_) => plan_rendition_bases(&image_files, input_root.as_deref(), output),
+        None => plan_output_paths(&image_files, input_root.as_deref(), output, &options.format),
+    };
+    for (input_path, planned) in planned_outputs {
+        let planned = planned.and_then(|output_path| {
+            let renditions = match &batch_options.renditions {
+                Some(renditions) => renditions.plan(
+                    &input_path,
+                    output_path.parent().unwrap_or(Path::new("")),
+                    &options.format,
+                )?,
+                None => Vec::new(),
+            };
+            let (format, overwrites) = if renditions.is_empty() {
+                let format = determine_output_format(&output_path, &options.format)?;
+                (format!("{:?}", format).to_lowercase(), output_path.exists())
+            } else {
+                let mut formats: Vec<&str> = Vec::new();
+                for rendition in &renditions {
+                    if !formats.contains(&rendition.format.as_str()) {
+                        formats.push(&rendition.format);
+                    }
+                }
+                let overwrites = renditions.iter().any(|r| r.output.exists());
+                (formats.join(","), overwrites)
+            };
+            let freshness = match (&plan.manifest, &fingerprint) {
+                (Some(manifest), Some(fingerprint)) => {
+                    Some(manifest.check(&input_path, &output_path, fingerprint)?)
+                }
+                _ => None,
+            };
+            Ok(PlannedFile {
+                estimated_memory_mib: estimate_image_memory_usage(&input_path, options)?,
+                overwrites,
+                input: input_path.clone(),
+                output: output_path,
+                format,
+                up_to_date: matches!(freshness, Some(Freshness::UpToDate(_))),
+                freshness,
+                renditions,
+            })
+        });
+        match planned {
+            Ok(file) => plan.files.push(file),
+            Err(error) => plan.failures.push(BatchFailure {
+                path: input_path,
+                error,
+            }),
+        }
+    }
+
+    Ok(plan)
+}
 
+/// Picks the number of worker threads from the CPU count, the number of
+/// large images and the memory currently available.
+fn choose_parallelism(
+    total_files: usize,
+    estimated_memory_mib: f64,
+    large_image_count: usize,
+) -> usize {
     // Adjust parallelism based on large image count and available memory
     let baseline = rayon::current_num_threads().min(total_files);
     let large_cap = if large_image_count >= MAX_CONCURRENT_LARGE_IMAGES {
@@ -155,16 +477,93 @@ pub fn batch_compress_images(
     let avg_per_file_mib = ((estimated_memory_mib / total_files as f64).ceil() as u64).max(1);
     let mem_cap = ((available_mem_mib.saturating_sub(MIN_AVAILABLE_MEMORY_MIB)) / avg_per_file_mib)
         .clamp(1, baseline as u64) as usize;
-    let max_parallelism = large_cap.min(mem_cap);
+    large_cap.min(mem_cap)
+}
 
+/// Prints a batch plan
+pub fn print_batch_plan(plan: &BatchPlan) {
+    println!("🧪 Dry run: no image will be decoded or written");
+    println!("📁 Input: {}", plan.input);
+    println!("📁 Output: {:?}", plan.output);
+
+    let total_files = plan.files.len() + plan.failures.len();
+    if total_files == 0 {
+        println!("⚠️  No image files found in the input path");
+        return;
+    }
+
+    println!("📊 Batch validation complete:");
+    println!("  📁 Total files: {}", total_files);
+    println!(
+        "  💾 Estimated memory usage: {:.1} MiB",
+        plan.estimated_memory_mib
+    );
     println!(
-        "⚙️  Using {} parallel threads for processing",
-        max_parallelism
+        "  📏 Large images (>{}MiB): {}",
+        LARGE_IMAGE_THRESHOLD_MIB, plan.large_image_count
     );
+    println!("⚙️  Would use {} parallel threads", plan.threads);
+
+    println!("\n📋 Planned outputs:");
+    for file in &plan.files {
+        let note = if file.up_to_date {
+            " ⏭️  up to date, would be skipped"
+        } else if file.overwrites {
+            " ⚠️  overwrites existing file"
+        } else {
+            ""
+        };
+        println!(
+            "  {:?} -> {:?} ({}, ~{:.1} MiB){}",
+            file.input, file.output, file.format, file.estimated_memory_mib, note
+        );
+        for rendition in &file.renditions {
+            println!("    ↳ {:?} ({}w)", rendition.output, rendition.width);
+        }
+    }
+
+    if !plan.failures.is_empty() {
+        println!("\n⚠️  Cannot be written: {}", plan.failures.len());
+        for failure in &plan.failures {
+            println!("    ❌ {:?}: {}", failure.path, failure.error);
+        }
+    }
+}
+
+/// Compresses every image found under `input` into `output` in parallel
+///
+/// # Arguments
+/// * `input` - Directory, single file or glob pattern
+/// * `output` - Output directory, created if missing
+/// * `options` - Compression options applied to every file
+/// * `batch_options` - Recursion, incremental and failure handling
+///
+/// # Returns
+/// * `Ok(BatchReport)` - Per-file results and totals; individual failures are listed, not returned
+/// * `Err(CompressionError)` - If the input cannot be read or the batch exceeds resource limits
+pub fn batch_compress_images(
+    input: String,
+    output: PathBuf,
+    options: CompressionOptions,
+    batch_options: BatchOptions,
+) -> Result<BatchReport> {
+    let start_time = Instant::now();
+    validate_batch_options(&batch_options)?;
+    let plan = plan_batch(&input, &output, &options, &batch_options)?;
+
+    let mut report = BatchReport::new(input, output.clone());
+    report.total_files = plan.files.len() + plan.failures.len();
+    if report.total_files == 0 {
+        report.summarize(start_time);
+        return Ok(report);
+    }
+    report.estimated_memory_mib = plan.estimated_memory_mib;
+    report.large_image_count = plan.large_image_count;
+    report.threads = plan.threads;
 
     // Build a scoped Rayon pool to enforce the chosen parallelism
     let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(max_parallelism)
+        .num_threads(plan.threads)
         .build()
         .expect("Failed to build Rayon thread pool");
 
@@ -172,111 +571,198 @@ pub fn batch_compress_images(
     fs::create_dir_all(&output)
         .map_err(|_| CompressionError::DirectoryCreationFailed(output.clone()))?;
 
+    for failure in &plan.failures {
+        eprintln!("⚠️  Skipping {:?}: {}", failure.path, failure.error);
+    }
+    report.failures = plan.failures;
+    let large_image_count = plan.large_image_count;
+    let jobs = plan.files;
+
+    // --incremental: 沿用规划阶段读取并清理过的清单
+    let incremental = match plan.manifest {
+        Some(manifest) => Some(IncrementalState {
+            manifest: Mutex::new((manifest, 0)),
+            fingerprint: options_fingerprint(&options)?,
+        }),
+        None => None,
+    };
+
+    // --upload: 整个批次共用一个运行时和一组客户端
+    let uploader = batch_options
+        .upload
+        .as_ref()
+        .map(|target| WalrusUploader::new(&target.walrus))
+        .transpose()?;
+
     // 设置进度条
-    let main_progress = ProgressBar::new(total_files as u64);
+    let main_progress = ProgressBar::new(jobs.len() as u64);
     main_progress.set_style(ProgressStyle::default_bar());
 
-    let processed_count = Arc::new(AtomicUsize::new(0));
-    let total_size_before = Arc::new(AtomicUsize::new(0));
-    let total_size_after = Arc::new(AtomicUsize::new(0));
+    // --fail-fast: 出错后不再启动新的任务
+    let cancelled = AtomicBool::new(batch_options.fail_fast && !report.failures.is_empty());
+    let process = |file: &PlannedFile| {
+        let input_path = &file.input;
+        if cancelled.load(Ordering::Relaxed) {
+            main_progress.inc(1);
+            return (input_path.clone(), None);
+        }
+        let result = process_single_image(
+            file,
+            &options,
+            &batch_options,
+            uploader.as_ref(),
+            incremental.as_ref(),
+        );
+        if let Some(state) = &incremental {
+            state.record(input_path, &result, &output);
+        }
+        if let Err(e) = &result {
+            eprintln!("❌ Failed to process {:?}: {}", input_path, e);
+            if batch_options.fail_fast {
+                cancelled.store(true, Ordering::Relaxed);
+                // 中止前保存清单，保留已完成的进度
+                if let Some(state) = &incremental {
+                    state.save_or_warn(&output);
+                }
+            }
+        }
+        main_progress.inc(1);
+        (input_path.clone(), Some(result))
+    };
 
     // Security: Use limited parallelism based on memory requirements
-    let results: Vec<Result<()>> = pool.install(|| {
+    let results: Vec<(PathBuf, Option<Result<FileResult>>)> = pool.install(|| {
         if large_image_count > MAX_CONCURRENT_LARGE_IMAGES {
             // For batches with many large images, use chunked processing to limit memory usage
             let chunk_size = MAX_CONCURRENT_LARGE_IMAGES.max(1);
-            image_files
-                .chunks(chunk_size)
-                .flat_map(|chunk| {
-                    chunk
-                        .into_par_iter()
-                        .map(|input_path| {
-                            let progress = main_progress.clone();
-                            let processed_count = processed_count.clone();
-                            let total_size_before = total_size_before.clone();
-                            let total_size_after = total_size_after.clone();
-
-                            match process_single_image(input_path, &output, &options) {
-                                Ok((before_size, after_size)) => {
-                                    total_size_before.fetch_add(before_size, Ordering::Relaxed);
-                                    total_size_after.fetch_add(after_size, Ordering::Relaxed);
-                                    processed_count.fetch_add(1, Ordering::Relaxed);
-                                    progress.inc(1);
-                                    Ok(())
-                                }
-                                Err(e) => {
-                                    eprintln!("❌ Failed to process {:?}: {}", input_path, e);
-                                    progress.inc(1);
-                                    Err(e)
-                                }
-                            }
-                        })
-                        .collect::<Vec<_>>()
-                })
+            jobs.chunks(chunk_size)
+                .flat_map(|chunk| chunk.par_iter().map(&process).collect::<Vec<_>>())
                 .collect()
         } else {
             // Standard parallel processing for smaller batches
-            image_files
-                .into_par_iter()
-                .map(|input_path| {
-                    let progress = main_progress.clone();
-                    let processed_count = processed_count.clone();
-                    let total_size_before = total_size_before.clone();
-                    let total_size_after = total_size_after.clone();
-
-                    match process_single_image(&input_path, &output, &options) {
-                        Ok((before_size, after_size)) => {
-                            total_size_before.fetch_add(before_size, Ordering::Relaxed);
-                            total_size_after.fetch_add(after_size, Ordering::Relaxed);
-                            processed_count.fetch_add(1, Ordering::Relaxed);
-                            progress.inc(1);
-                            Ok(())
-                        }
-                        Err(e) => {
-                            eprintln!("❌ Failed to process {:?}: {}", input_path, e);
-                            progress.inc(1);
-                            Err(e)
-                        }
-                    }
-                })
-                .collect()
+            jobs.par_iter().map(&process).collect()
         }
     });
 
-    main_progress.finish_with_message("✅ Batch compression complete");
+    main_progress.finish_and_clear();
 
-    // 输出统计信息
-    let total_before = total_size_before.load(Ordering::Relaxed);
-    let total_after = total_size_after.load(Ordering::Relaxed);
-    let compression_ratio = if total_before > 0 {
-        ((total_before as f64 - total_after as f64) / total_before as f64) * 100.0
-    } else {
-        0.0
-    };
+    for (path, result) in results {
+        match result {
+            Some(Ok(FileResult::Compressed(file_reports, _))) => report.files.extend(file_reports),
+            Some(Ok(FileResult::Skipped(_))) => report.skipped.push(path),
+            Some(Err(error)) => report.failures.push(BatchFailure { path, error }),
+            None => report.cancelled.push(path),
+        }
+    }
+    if let Some(state) = &incremental {
+        state.save(&output)?;
+    }
+    if batch_options.assets.is_enabled() {
+        report.assets = write_assets(&report.files, &output, &batch_options.assets)?;
+    }
+    report.summarize(start_time);
 
-    let elapsed_time = start_time.elapsed();
+    Ok(report)
+}
+
+/// Prints a batch report
+pub fn print_batch_report(report: &BatchReport) {
+    println!("🚀 Batch compression");
+    println!("📁 Input: {}", report.input);
+    println!("📁 Output: {:?}", report.output);
+
+    if report.total_files == 0 {
+        println!("⚠️  No image files found in the input path");
+        return;
+    }
+
+    println!("📊 Batch validation complete:");
+    println!("  📁 Total files: {}", report.total_files);
+    println!(
+        "  💾 Estimated memory usage: {:.1} MiB",
+        report.estimated_memory_mib
+    );
+    println!(
+        "  📏 Large images (>{}MiB): {}",
+        LARGE_IMAGE_THRESHOLD_MIB, report.large_image_count
+    );
+    println!(
+        "⚙️  Used {} parallel threads for processing",
+        report.threads
+    );
 
     println!("\n📊 Batch Compression Summary:");
+    println!("  📁 Total files processed: {}", report.files.len());
     println!(
-        "  📁 Total files processed: {}",
-        processed_count.load(Ordering::Relaxed)
+        "  📊 Total original size: {} bytes",
+        report.total_original_size
     );
-    println!("  📊 Total original size: {} bytes", total_before);
-    println!("  📊 Total compressed size: {} bytes", total_after);
-    println!("  🎯 Overall compression ratio: {:.1}%", compression_ratio);
-    println!("  ⏱️  Total time: {:?}", elapsed_time);
     println!(
-        "  ⚡ Average speed: {:.2} files/second",
-        processed_count.load(Ordering::Relaxed) as f64 / elapsed_time.as_secs_f64()
+        "  📊 Total compressed size: {} bytes",
+        report.total_compressed_size
     );
+    println!(
+        "  🎯 Overall compression ratio: {:.1}%",
+        report.compression_ratio
+    );
+    let targeted = report.files.iter().any(|f| f.encode.target_met.is_some());
+    if let (true, Some(average)) = (targeted, report.average_quality) {
+        println!("  🎚️  Average chosen quality: {:.1}", average);
+        if report.target_missed > 0 {
+            println!(
+                "  ⚠️  Files that missed the target: {}",
+                report.target_missed
+            );
+        }
+    }
+    println!("  ⏱️  Total time: {:.2}s", report.elapsed_secs);
+    if report.elapsed_secs > 0.0 {
+        println!(
+            "  ⚡ Average speed: {:.2} files/second",
+            report.files.len() as f64 / report.elapsed_secs
+        );
+    }
 
-    // 检查是否有失败的文件
-    let failed_count = results.iter().filter(|r| r.is_err()).count();
-    if failed_count > 0 {
-        println!("  ⚠️  Failed files: {}", failed_count);
+    let uploaded: Vec<_> = report
+        .files
+        .iter()
+        .filter_map(|file| file.blob.as_ref().map(|blob| (file, blob)))
+        .collect();
+    if !uploaded.is_empty() {
+        println!("  ☁️  Uploaded to Walrus: {}", uploaded.len());
+        for (file, blob) in uploaded {
+            println!(
+                "    🆔 {:?} -> {} (until epoch {})",
+                file.output, blob.blob_id, blob.end_epoch
+            );
+        }
     }
 
-    Ok(())
+    // 检查是否有失败的文件
+    if !report.failures.is_empty() {
+        println!("  ⚠️  Failed files: {}", report.failures.len());
+        for failure in &report.failures {
+            println!("    ❌ {:?}: {}", failure.path, failure.error);
+        }
+    }
+    if !report.skipped.is_empty() {
+        println!(
+            "  ⏭️  Skipped (already up to date): {}",
+            report.skipped.len()
+        );
+    }
+    if !report.cancelled.is_empty() {
+        println!(
+            "  ⏹️  Cancelled after failure (--fail-fast): {}",
+            report.cancelled.len()
+        );
+    }
+    if !report.assets.is_empty() {
+        println!("  📝 Asset files written: {}", report.assets.len());
+        for path in &report.assets {
+            println!("    {:?}", path);
+        }
+    }
 }
 
 pub fn collect_image_files(input: &str, recursive: bool) -> Result<Vec<PathBuf>> {
@@ -338,25 +824,208 @@ pub fn is_image_file(path: &Path) -> bool {
         .map(|ext| {
             matches!(
                 ext.to_lowercase().as_str(),
-                "jpg" | "jpeg" | "png" | "webp" | "bmp" | "tiff" | "gif" | "avif" | "heic" | "heif" | "jxl"
+                "jpg"
+                    | "jpeg"
+                    | "png"
+                    | "webp"
+                    | "bmp"
+                    | "tiff"
+                    | "gif"
+                    | "avif"
+                    | "heic"
+                    | "heif"
+                    | "jxl"
             )
         })
         .unwrap_or(false)
 }
 
+/// Manifest and option fingerprint of an `--incremental` batch
+struct IncrementalState {
+    /// The manifest and the number of inputs recorded since it was last saved
+    manifest: Mutex<(IncrementalManifest, usize)>,
+    fingerprint: serde_json::Value,
+}
+
+impl IncrementalState {
+    /// Records the outcome of one input, saving the manifest every
+    /// `INCREMENTAL_SAVE_INTERVAL` inputs so an interrupted batch keeps its progress
+    fn record(&self, input: &Path, result: &Result<FileResult>, output_dir: &Path) {
+        let mut guard = self.manifest.lock().unwrap_or_else(PoisonError::into_inner);
+        let (manifest, unsaved) = &mut *guard;
+        match result {
+            Ok(FileResult::Compressed(_, Some(entry))) | Ok(FileResult::Skipped(entry)) => {
+                manifest.insert(input.to_path_buf(), entry.clone())
+            }
+            Ok(FileResult::Compressed(_, None)) => {}
+            Err(_) => manifest.remove(input),
+        }
+        *unsaved += 1;
+        if *unsaved >= INCREMENTAL_SAVE_INTERVAL {
+            if let Err(e) = manifest.save(output_dir) {
+                eprintln!("⚠️  Failed to save incremental manifest: {}", e);
+            }
+            *unsaved = 0;
+        }
+    }
+
+    fn save(&self, output_dir: &Path) -> Result<()> {
+        let mut guard = self.manifest.lock().unwrap_or_else(PoisonError::into_inner);
+        guard.0.save(output_dir)?;
+        guard.1 = 0;
+        Ok(())
+    }
+
+    fn save_or_warn(&self, output_dir: &Path) {
+        if let Err(e) = self.save(output_dir) {
+            eprintln!("⚠️  Failed to save incremental manifest: {}", e);
+        }
+    }
+}
+
+/// What happened to one batch input
+enum FileResult {
+    /// Compressed into one output, or one per rendition, with the manifest
+    /// entry to record in incremental mode
+    Compressed(Vec<CompressionReport>, Option<ManifestEntry>),
+    /// Left alone because its output is up to date
+    Skipped(ManifestEntry),
+}
+
 fn process_single_image(
-    input_path: &Path,
-    output_dir: &Path,
+    file: &PlannedFile,
     options: &CompressionOptions,
-) -> Result<(usize, usize)> {
-    // 生成输出路径
-    let output_path = generate_output_path(input_path, output_dir, &options.format)?;
+    batch_options: &BatchOptions,
+    uploader: Option<&WalrusUploader>,
+    incremental: Option<&IncrementalState>,
+) -> Result<FileResult> {
+    let (input_path, output_path) = (file.input.as_path(), file.output.as_path());
+    if let (Some(target), Some(uploader)) = (&batch_options.upload, uploader) {
+        // 批处理已有按文件计数的进度条，上传进度不再单独显示
+        let report = compress_and_upload_with(
+            input_path,
+            output_path,
+            options,
+            target,
+            uploader,
+            &ProgressBar::hidden(),
+        )?;
+        return Ok(FileResult::Compressed(vec![report], None));
+    }
+    if let Some(renditions) = &batch_options.renditions {
+        // `output_path` is the rendition base; the files go next to it
+        let output_dir = output_path.parent().unwrap_or(Path::new(""));
+        let report = process_renditions(input_path, output_dir, options, renditions)?;
+        return Ok(FileResult::Compressed(report.renditions, None));
+    }
+    let Some(state) = incremental else {
+        // 使用统一的图片处理管道
+        let report = process_image_pipeline(input_path, output_path, options)?;
+        return Ok(FileResult::Compressed(vec![report], None));
+    };
 
-    // 使用统一的图片处理管道
-    let (original_size, compressed_size) =
-        process_image_pipeline(input_path, &output_path, options)?;
+    // 规划阶段已检查过清单，这里直接沿用结果（包括已计算的哈希）
+    let known = match &file.freshness {
+        Some(Freshness::UpToDate(entry)) => return Ok(FileResult::Skipped(entry.clone())),
+        Some(Freshness::Stale(state)) => Some(state.clone()),
+        None => None,
+    };
+
+    // 压缩前记录输入状态，压缩期间的修改会在下次运行时被发现
+    let mut entry =
+        ManifestEntry::capture_with(input_path, output_path, &state.fingerprint, known)?;
+    let report = process_image_pipeline(input_path, output_path, options)?;
+    entry.output_size = report.encode.compressed_size;
+    Ok(FileResult::Compressed(vec![report], Some(entry)))
+}
+
+/// Directory whose layout a recursive batch reproduces under the output
+/// directory; `None` for flat batches and glob patterns.
+fn mirror_root(input: &str, recursive: bool) -> Option<PathBuf> {
+    let input_path = Path::new(input);
+    if recursive && input_path.is_dir() {
+        input_path.canonicalize().ok()
+    } else {
+        None
+    }
+}
 
-    Ok((original_size as usize, compressed_size as usize))
+/// Plans where each input of a batch is written.
+///
+/// Inputs below `input_root` keep their relative directory under `output_dir`.
+/// When several inputs map to the same output (e.g. `photo.jpg` and `photo.png`
+/// both converted to WebP), the first one in path order keeps it and the rest
+/// get `CompressionError::OutputCollision` instead of overwriting it.
+///
+/// # Arguments
+/// * `image_files` - Inputs as returned by `collect_image_files`
+/// * `input_root` - Directory to mirror, if any
+/// * `output_dir` - Batch output directory
+/// * `format` - Optional output format override
+///
+/// # Returns
+/// * Each input paired with its output path or the reason it cannot be written, sorted by input
+pub fn plan_output_paths(
+    image_files: &[PathBuf],
+    input_root: Option<&Path>,
+    output_dir: &Path,
+    format: &Option<String>,
+) -> Vec<(PathBuf, Result<PathBuf>)> {
+    plan_paths(image_files, input_root, output_dir, |input_path, dir| {
+        generate_output_path(input_path, dir, format)
+    })
+}
+
+/// Like `plan_output_paths`, but claims `<dir>/<stem>` for each input: the
+/// base its renditions are named after. Inputs sharing a stem in the same
+/// directory would overwrite each other's renditions, so they collide.
+fn plan_rendition_bases(
+    image_files: &[PathBuf],
+    input_root: Option<&Path>,
+    output_dir: &Path,
+) -> Vec<(PathBuf, Result<PathBuf>)> {
+    plan_paths(image_files, input_root, output_dir, |input_path, dir| {
+        let file_stem = input_path
+            .file_stem()
+            .ok_or_else(|| CompressionError::UnsupportedFormat("Invalid file name".to_string()))?;
+        Ok(dir.join(file_stem))
+    })
+}
+
+/// Assigns each input the path `path_for(input, mirrored output dir)`; the first
+/// input in path order keeps a contested path
+fn plan_paths(
+    image_files: &[PathBuf],
+    input_root: Option<&Path>,
+    output_dir: &Path,
+    path_for: impl Fn(&Path, &Path) -> Result<PathBuf>,
+) -> Vec<(PathBuf, Result<PathBuf>)> {
+    let mut inputs = image_files.to_vec();
+    inputs.sort();
+
+    let mut claimed: HashMap<PathBuf, PathBuf> = HashMap::new();
+    inputs
+        .into_iter()
+        .map(|input_path| {
+            let relative_dir = input_root
+                .and_then(|root| input_path.parent()?.strip_prefix(root).ok())
+                .unwrap_or(Path::new(""));
+            let planned =
+                path_for(&input_path, &output_dir.join(relative_dir)).and_then(|output_path| {
+                    match claimed.get(&output_path) {
+                        Some(owner) => Err(CompressionError::OutputCollision(
+                            output_path,
+                            owner.clone(),
+                        )),
+                        None => {
+                            claimed.insert(output_path.clone(), input_path.clone());
+                            Ok(output_path)
+                        }
+                    }
+                });
+            (input_path, planned)
+        })
+        .collect()
 }
 
 pub fn generate_output_path(
@@ -394,6 +1063,10 @@ mod tests {
     use std::io::Write;
     use tempfile::TempDir;
 
+    fn default_options() -> CompressionOptions {
+        CompressionOptions::new(None, None, None, None).unwrap()
+    }
+
     #[test]
     fn test_is_image_file() {
         let path = Path::new("test.jpg");
@@ -496,6 +1169,51 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_plan_output_paths_mirrors_tree() {
+        let root = Path::new("/photos");
+        let files = vec![
+            PathBuf::from("/photos/b/logo.png"),
+            PathBuf::from("/photos/a/logo.png"),
+            PathBuf::from("/photos/top.jpg"),
+        ];
+
+        let plan = plan_output_paths(&files, Some(root), Path::new("/out"), &None);
+        let outputs: Vec<PathBuf> = plan.into_iter().map(|(_, out)| out.unwrap()).collect();
+        assert_eq!(
+            outputs,
+            vec![
+                PathBuf::from("/out/a/logo.png"),
+                PathBuf::from("/out/b/logo.png"),
+                PathBuf::from("/out/top.jpg"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_output_paths_reports_collisions() {
+        let files = vec![
+            PathBuf::from("/photos/photo.png"),
+            PathBuf::from("/photos/photo.jpg"),
+            PathBuf::from("/photos/other.jpg"),
+        ];
+
+        let plan = plan_output_paths(&files, None, Path::new("/out"), &Some("webp".to_string()));
+        assert_eq!(plan[0].0, PathBuf::from("/photos/other.jpg"));
+        assert_eq!(
+            plan[1].1.as_ref().unwrap(),
+            &PathBuf::from("/out/photo.webp")
+        );
+        match &plan[2] {
+            (input, Err(CompressionError::OutputCollision(output, owner))) => {
+                assert_eq!(input, &PathBuf::from("/photos/photo.png"));
+                assert_eq!(output, &PathBuf::from("/out/photo.webp"));
+                assert_eq!(owner, &PathBuf::from("/photos/photo.jpg"));
+            }
+            other => panic!("expected a collision, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_collect_image_files_single_file() {
         let temp_dir = TempDir::new().unwrap();
@@ -574,6 +1292,149 @@ mod tests {
         assert_eq!(files.len(), 1);
     }
 
+    #[test]
+    fn test_batch_report_lists_successes_and_failures() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_dir = temp_dir.path().join("input");
+        std::fs::create_dir(&input_dir).unwrap();
+        image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 64]))
+            .save(input_dir.join("good.png"))
+            .unwrap();
+        File::create(input_dir.join("broken.jpg"))
+            .unwrap()
+            .write_all(b"not a jpeg")
+            .unwrap();
+
+        let output_dir = temp_dir.path().join("output");
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        let report = batch_compress_images(
+            input_dir.to_string_lossy().into_owned(),
+            output_dir.clone(),
+            options,
+            BatchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(report.total_files, 2);
+        assert_eq!(report.outcome, BatchOutcome::PartialFailure);
+        assert_eq!(report.outcome.exit_code(), EXIT_PARTIAL_FAILURE);
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].output, output_dir.join("good.png"));
+        assert_eq!(report.failures.len(), 1);
+        assert!(report.failures[0].path.ends_with("broken.jpg"));
+        assert_eq!(report.total_original_size, report.files[0].original_size);
+        assert_eq!(report.average_quality, Some(80.0));
+        assert_eq!(report.target_missed, 0);
+    }
+
+    #[test]
+    fn test_batch_report_empty_input() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        let report = batch_compress_images(
+            temp_dir.path().to_string_lossy().into_owned(),
+            temp_dir.path().join("output"),
+            options,
+            BatchOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(report.total_files, 0);
+        assert_eq!(report.outcome, BatchOutcome::Success);
+        assert!(report.files.is_empty() && report.failures.is_empty());
+        assert_eq!(report.average_quality, None);
+    }
+
+    #[test]
+    fn test_plan_batch_marks_up_to_date_inputs() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_dir = temp_dir.path().join("input");
+        std::fs::create_dir(&input_dir).unwrap();
+        image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([x as u8 * 16, y as u8 * 16, 64]))
+            .save(input_dir.join("good.png"))
+            .unwrap();
+        let input = input_dir.to_string_lossy().into_owned();
+        let output_dir = temp_dir.path().join("output");
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        let batch_options = BatchOptions {
+            incremental: true,
+            ..BatchOptions::default()
+        };
+
+        let plan = plan_batch(&input, &output_dir, &options, &batch_options).unwrap();
+        assert!(!output_dir.exists());
+        assert_eq!(plan.files.len(), 1);
+        assert!(!plan.files[0].up_to_date && !plan.files[0].overwrites);
+        assert_eq!(plan.files[0].format, "png");
+
+        batch_compress_images(
+            input.clone(),
+            output_dir.clone(),
+            options.clone(),
+            batch_options.clone(),
+        )
+        .unwrap();
+        let plan = plan_batch(&input, &output_dir, &options, &batch_options).unwrap();
+        assert!(plan.files[0].up_to_date && plan.files[0].overwrites);
+    }
+
+    #[test]
+    fn test_incremental_batch_prunes_deleted_inputs() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_dir = temp_dir.path().join("input");
+        std::fs::create_dir(&input_dir).unwrap();
+        for name in ["kept.png", "deleted.png"] {
+            image::RgbImage::from_pixel(8, 8, image::Rgb([10, 20, 30]))
+                .save(input_dir.join(name))
+                .unwrap();
+        }
+        let input = input_dir.to_string_lossy().into_owned();
+        let output_dir = temp_dir.path().join("output");
+        let batch_options = BatchOptions {
+            incremental: true,
+            ..BatchOptions::default()
+        };
+        let manifest_inputs = || {
+            let manifest: serde_json::Value =
+                serde_json::from_slice(&fs::read(IncrementalManifest::path(&output_dir)).unwrap())
+                    .unwrap();
+            let mut inputs: Vec<String> = manifest["entries"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(|key| {
+                    Path::new(key)
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
+            inputs.sort();
+            inputs
+        };
+
+        let run = || {
+            batch_compress_images(
+                input.clone(),
+                output_dir.clone(),
+                default_options(),
+                batch_options.clone(),
+            )
+            .unwrap()
+        };
+        run();
+        assert_eq!(manifest_inputs(), ["deleted.png", "kept.png"]);
+
+        fs::remove_file(input_dir.join("deleted.png")).unwrap();
+        // The plan carries the pruned manifest the run goes on to save
+        let plan = plan_batch(&input, &output_dir, &default_options(), &batch_options).unwrap();
+        assert_eq!(plan.manifest.unwrap().prune_missing(), 0);
+        let report = run();
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(manifest_inputs(), ["kept.png"]);
+    }
+
     #[test]
     fn test_estimate_image_memory_usage() {
         let temp_dir = TempDir::new().unwrap();
@@ -584,7 +1445,7 @@ mod tests {
         let data = vec![0u8; 1024]; // 1KB of data
         file.write_all(&data).unwrap();
 
-        let memory_estimate = estimate_image_memory_usage(&test_file).unwrap();
+        let memory_estimate = estimate_image_memory_usage(&test_file, &default_options()).unwrap();
 
         // JPEG multiplier is 4.0, so 1KB file should estimate ~4KB memory (0.004MB)
         assert!(memory_estimate > 0.0);
@@ -601,17 +1462,30 @@ mod tests {
         let data = vec![0u8; 2048]; // 2KB of data
         file.write_all(&data).unwrap();
 
-        let memory_estimate = estimate_image_memory_usage(&test_file).unwrap();
+        let memory_estimate = estimate_image_memory_usage(&test_file, &default_options()).unwrap();
 
         // PNG multiplier is 3.0, so 2KB file should estimate ~6KB memory
         assert!(memory_estimate > 0.0);
         assert!(memory_estimate < 1.0); // Should be less than 1MB for 2KB file
     }
 
+    #[test]
+    fn test_estimate_image_memory_usage_with_target_ssim() {
+        let temp_dir = TempDir::new().unwrap();
+        let test_file = temp_dir.path().join("wide.png");
+        image::RgbImage::new(4096, 4).save(&test_file).unwrap();
+
+        let plain = estimate_image_memory_usage(&test_file, &default_options()).unwrap();
+        let options = default_options().with_target_ssim(Some(0.98));
+        let searched = estimate_image_memory_usage(&test_file, &options).unwrap();
+        let scoring_mib = ssim_working_set_bytes(4096) as f64 / (1024.0 * 1024.0);
+        assert!((searched - (plain * 2.0 + scoring_mib)).abs() < 1e-9);
+    }
+
     #[test]
     fn test_validate_batch_memory_limits_empty() {
         let files = vec![];
-        let result = validate_batch_memory_limits(&files).unwrap();
+        let result = validate_batch_memory_limits(&files, &default_options()).unwrap();
         assert_eq!(result.0, 0.0); // No memory usage
         assert_eq!(result.1, 0); // No large images
     }
@@ -624,7 +1498,7 @@ mod tests {
             files.push(PathBuf::from(format!("test{}.jpg", i)));
         }
 
-        let result = validate_batch_memory_limits(&files);
+        let result = validate_batch_memory_limits(&files, &default_options());
         assert!(matches!(
             result,
             Err(CompressionError::BatchFileLimitExceeded(_, _))
@@ -649,7 +1523,7 @@ mod tests {
             .unwrap(); // 2KB
 
         let files = vec![file1, file2];
-        let result = validate_batch_memory_limits(&files).unwrap();
+        let result = validate_batch_memory_limits(&files, &default_options()).unwrap();
 
         assert!(result.0 > 0.0); // Should have some memory estimate
         assert_eq!(result.1, 0); // No large images (files are too small)
@@ -668,9 +1542,54 @@ mod tests {
             .unwrap();
 
         let files = vec![large_file];
-        let result = validate_batch_memory_limits(&files).unwrap();
+        let result = validate_batch_memory_limits(&files, &default_options()).unwrap();
 
         assert!(result.0 > LARGE_IMAGE_THRESHOLD_MIB); // Memory estimate should be above threshold
         assert_eq!(result.1, 1); // Should count as 1 large image
     }
+
+    #[test]
+    fn test_plan_batch_rejects_upload_with_renditions() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("photo.png");
+        image::RgbImage::new(4, 4).save(&input).unwrap();
+        let output = temp_dir.path().join("out");
+        let upload = UploadTarget {
+            walrus: crate::walrus::WalrusOptions::default(),
+            keep_local: true,
+        };
+
+        let batch_options = BatchOptions {
+            renditions: Some(
+                Renditions::new(
+                    "64:webp".parse().unwrap(),
+                    crate::constants::DEFAULT_RENDITION_TEMPLATE.to_string(),
+                )
+                .unwrap(),
+            ),
+            upload: Some(upload.clone()),
+            ..BatchOptions::default()
+        };
+        let result = plan_batch(
+            &input.to_string_lossy(),
+            &output,
+            &default_options(),
+            &batch_options,
+        );
+        assert!(matches!(result, Err(CompressionError::Config(_))));
+
+        let batch_options = BatchOptions {
+            incremental: true,
+            upload: Some(upload),
+            ..BatchOptions::default()
+        };
+        let result = plan_batch(
+            &input.to_string_lossy(),
+            &output,
+            &default_options(),
+            &batch_options,
+        );
+        assert!(matches!(result, Err(CompressionError::Config(_))));
+        assert!(!output.exists());
+    }
 }
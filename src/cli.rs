@@ -1,4 +1,15 @@
-use clap::{Parser, Subcommand};
+use crate::assets::AssetOptions;
+use crate::config::{Settings, WalrusSettings};
+use crate::constants::{
+    DEFAULT_PICTURE_SIZES, DEFAULT_RENDITION_TEMPLATE, DEFAULT_UPLOAD_CONCURRENCY,
+    DEFAULT_WATCH_DEBOUNCE_MS, DEFAULT_WEBP_ALPHA_QUALITY, DEFAULT_WEBP_METHOD,
+};
+use crate::error::Result;
+use crate::metadata::MetadataPolicy;
+use crate::processing::{ChromaSubsampling, ResizeFilter, ResizeMode};
+use crate::renditions::{RenditionSpec, Renditions};
+use crate::upload::UploadTarget;
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -10,35 +21,63 @@ use std::path::PathBuf;
 pub struct Args {
     #[command(subcommand)]
     pub command: Commands,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "How to print command results"
+    )]
+    pub output_format: OutputFormat,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Configuration file (default: ./img-squeeze.toml, then the user config directory)"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Apply a named preset from the configuration file"
+    )]
+    pub preset: Option<String>,
+}
+
+/// Rendering of command results on stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// A single pretty-printed JSON document
+    Json,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     #[command(about = "Compress an image")]
     Compress {
-        #[arg(help = "Input image file")]
+        #[arg(help = "Input image file, or - to read from stdin")]
         input: PathBuf,
 
-        #[arg(help = "Output image file")]
+        #[arg(
+            help = "Output image file, or - to write to stdout (requires --format); output directory with --renditions"
+        )]
         output: PathBuf,
 
-        #[arg(short = 'q', long, help = "Quality (1-100), default is 80")]
-        quality: Option<u8>,
-
-        #[arg(short = 'w', long, help = "Maximum width in pixels")]
-        width: Option<u32>,
+        #[command(flatten)]
+        options: CompressionArgs,
 
-        #[arg(short = 'H', long, help = "Maximum height in pixels")]
-        height: Option<u32>,
+        #[command(flatten)]
+        renditions: RenditionArgs,
 
-        #[arg(
-            short = 'f',
-            long,
-            value_parser = ["jpeg","jpg","png","webp","avif"],
-            value_name = "FORMAT",
-            help = "Output format (jpeg, jpg, png, webp, avif). Note: heic/heif/jxl are recognized as inputs only."
-        )]
-        format: Option<String>,
+        #[command(flatten)]
+        upload: UploadArgs,
 
         #[arg(short = 'j', long, help = "Number of parallel threads (default: auto)")]
         threads: Option<usize>,
@@ -52,47 +91,142 @@ pub enum Commands {
         #[arg(help = "Output directory")]
         output: PathBuf,
 
-        #[arg(short = 'q', long, help = "Quality (1-100), default is 80")]
-        quality: Option<u8>,
+        #[command(flatten)]
+        options: CompressionArgs,
 
-        #[arg(short = 'w', long, help = "Maximum width in pixels")]
-        width: Option<u32>,
+        #[command(flatten)]
+        renditions: RenditionArgs,
 
-        #[arg(short = 'H', long, help = "Maximum height in pixels")]
-        height: Option<u32>,
+        #[command(flatten)]
+        assets: AssetArgs,
 
-        #[arg(
-            short = 'f',
-            long,
-            value_parser = ["jpeg","jpg","png","webp","avif"],
-            value_name = "FORMAT",
-            help = "Output format (jpeg, jpg, png, webp, avif). Note: heic/heif/jxl are recognized as inputs only."
-        )]
-        format: Option<String>,
+        #[command(flatten)]
+        upload: UploadArgs,
 
         #[arg(short = 'j', long, help = "Number of parallel threads (default: auto)")]
         threads: Option<usize>,
 
         #[arg(short = 'r', long, help = "Recursive directory processing")]
         recursive: bool,
+
+        #[arg(long, help = "Stop starting new files after the first failure")]
+        fail_fast: bool,
+
+        #[arg(
+            long,
+            conflicts_with_all = ["renditions", "asset_manifest", "html_snippets", "upload"],
+            help = "Skip inputs unchanged since the last incremental run into this output directory"
+        )]
+        incremental: bool,
+
+        #[arg(
+            long,
+            help = "Print the planned outputs, memory estimate and parallelism without compressing anything"
+        )]
+        dry_run: bool,
     },
 
-    #[command(about = "Upload an image to Walrus storage")]
-    Upload {
-        #[arg(help = "Image file to upload")]
+    #[command(about = "Compress images as they are added to or changed in a directory")]
+    Watch {
+        #[arg(help = "Directory to watch")]
         input: PathBuf,
 
-        #[arg(short = 'a', long, help = "Walrus aggregator URL")]
-        aggregator_url: Option<String>,
+        #[arg(help = "Output directory")]
+        output: PathBuf,
 
-        #[arg(short = 'p', long, help = "Walrus publisher URL")]
-        publisher_url: Option<String>,
+        #[command(flatten)]
+        options: CompressionArgs,
 
-        #[arg(short = 'e', long, help = "Number of epochs for storage")]
-        epochs: Option<u64>,
+        #[arg(short = 'r', long, help = "Also watch subdirectories")]
+        recursive: bool,
+
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = DEFAULT_WATCH_DEBOUNCE_MS,
+            help = "Wait until a file has been unchanged for this long before compressing it"
+        )]
+        debounce_ms: u64,
+    },
+
+    #[command(about = "Upload images to Walrus storage")]
+    Upload {
+        #[arg(help = "Image file, directory or file pattern to upload")]
+        input: String,
+
+        #[command(flatten)]
+        walrus: WalrusArgs,
 
         #[arg(short = 't', long, help = "Upload as temporary file (1 epoch storage)")]
         temp: bool,
+
+        #[arg(short = 'r', long, help = "Recursive directory processing")]
+        recursive: bool,
+
+        #[arg(
+            short = 'j',
+            long,
+            value_name = "N",
+            default_value_t = DEFAULT_UPLOAD_CONCURRENCY,
+            help = "Maximum number of simultaneous uploads"
+        )]
+        concurrency: usize,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            help = "Write a JSON manifest mapping each file to its blob ID, URL and expiry"
+        )]
+        manifest: Option<PathBuf>,
+    },
+
+    #[command(about = "Download an image from Walrus storage")]
+    Download {
+        #[arg(help = "Blob ID printed by upload")]
+        blob_id: String,
+
+        #[arg(help = "Output file, or a directory to save it as <blob_id>.<ext>")]
+        output: PathBuf,
+
+        #[arg(short = 'a', long, help = "Walrus aggregator URL")]
+        aggregator_url: Option<String>,
+
+        #[arg(
+            long = "fallback-aggregator",
+            value_name = "URL",
+            help = "Aggregator to fail over to when the others fail; may be repeated"
+        )]
+        fallback_aggregators: Vec<String>,
+
+        #[arg(
+            long,
+            value_name = "SECS",
+            value_parser = clap::value_parser!(u64).range(1..),
+            help = "Timeout of each Walrus read request (default: 60)"
+        )]
+        timeout: Option<u64>,
+
+        #[arg(
+            long,
+            value_name = "N",
+            help = "Retry rounds after network errors, timeouts and 408/429/5xx responses (default: 3)"
+        )]
+        retries: Option<u32>,
+
+        #[arg(
+            long,
+            value_name = "HEX",
+            help = "Refuse the blob unless its SHA-256 matches (as printed by upload)"
+        )]
+        sha256: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with = "sha256",
+            help = "Blob manifest written by upload --manifest; the blob must be listed and match its SHA-256"
+        )]
+        manifest: Option<PathBuf>,
     },
 
     #[command(about = "Get information about an image")]
@@ -100,4 +234,515 @@ pub enum Commands {
         #[arg(help = "Image file to analyze")]
         input: PathBuf,
     },
+
+    #[command(about = "Compare a compressed image against its original")]
+    Compare {
+        #[arg(help = "Original (reference) image")]
+        original: PathBuf,
+
+        #[arg(help = "Compressed image to evaluate")]
+        compressed: PathBuf,
+
+        #[arg(long, value_name = "FILE", help = "Write a difference heatmap image")]
+        diff: Option<PathBuf>,
+    },
+
+    #[command(about = "Inspect the configuration file and presets")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    #[command(about = "Print the effective configuration for --preset (or the defaults)")]
+    Show,
+}
+
+/// Responsive image set options shared by the `compress` and `batch` commands
+#[derive(ClapArgs, Debug, Clone)]
+pub struct RenditionArgs {
+    #[arg(
+        long,
+        value_name = "SPEC",
+        conflicts_with_all = ["width", "height"],
+        help = "Write several widths and formats per input, e.g. 320,640,1280:avif,webp,jpeg"
+    )]
+    pub renditions: Option<RenditionSpec>,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = DEFAULT_RENDITION_TEMPLATE,
+        help = "Rendition file names; {stem}, {width} and {ext} are required, {format} is optional"
+    )]
+    pub rendition_template: String,
+}
+
+impl RenditionArgs {
+    /// The requested renditions, if any
+    pub fn to_renditions(&self) -> Result<Option<Renditions>> {
+        self.renditions
+            .clone()
+            .map(|spec| Renditions::new(spec, self.rendition_template.clone()))
+            .transpose()
+    }
+}
+
+/// Static site asset options of the `batch` command
+#[derive(ClapArgs, Debug, Clone)]
+pub struct AssetArgs {
+    #[arg(
+        long,
+        conflicts_with = "no_keep",
+        help = "Write asset-manifest.json mapping each source to its outputs"
+    )]
+    pub asset_manifest: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "no_keep",
+        help = "Write a <picture> snippet next to the outputs of each source"
+    )]
+    pub html_snippets: bool,
+
+    #[arg(
+        long,
+        value_name = "SIZES",
+        default_value = DEFAULT_PICTURE_SIZES,
+        requires = "html_snippets",
+        help = "sizes attribute of the <picture> snippets"
+    )]
+    pub html_sizes: String,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        default_value = "",
+        requires = "html_snippets",
+        help = "Prefix of the output paths in the <picture> snippets, e.g. /static/img/"
+    )]
+    pub html_base_url: String,
+}
+
+impl AssetArgs {
+    /// Converts the parsed flags into `AssetOptions`
+    pub fn to_asset_options(&self) -> AssetOptions {
+        AssetOptions {
+            manifest: self.asset_manifest,
+            html: self.html_snippets,
+            sizes: self.html_sizes.clone(),
+            base_url: self.html_base_url.clone(),
+        }
+    }
+}
+
+/// Uploading compressed outputs to Walrus, shared by `compress` and `batch`
+#[derive(ClapArgs, Debug, Clone)]
+pub struct UploadArgs {
+    #[arg(
+        long,
+        conflicts_with = "renditions",
+        help = "Upload each compressed output to Walrus and print its blob ID"
+    )]
+    pub upload: bool,
+
+    #[arg(
+        long,
+        requires = "upload",
+        help = "Only upload the compressed output, without writing the output file"
+    )]
+    pub no_keep: bool,
+
+    #[command(flatten)]
+    pub walrus: WalrusArgs,
+}
+
+impl UploadArgs {
+    /// Where to upload with `--upload`, given the Walrus settings merged with the flags
+    pub fn to_upload_target(&self, walrus: WalrusSettings) -> Option<UploadTarget> {
+        self.upload.then(|| UploadTarget {
+            walrus: walrus.to_walrus_options(),
+            keep_local: !self.no_keep,
+        })
+    }
+}
+
+/// Walrus endpoint options of the `upload` command
+#[derive(ClapArgs, Debug, Clone)]
+pub struct WalrusArgs {
+    #[arg(short = 'a', long, help = "Walrus aggregator URL")]
+    pub aggregator_url: Option<String>,
+
+    #[arg(short = 'p', long, help = "Walrus publisher URL")]
+    pub publisher_url: Option<String>,
+
+    #[arg(
+        long = "fallback-publisher",
+        value_name = "URL",
+        help = "Publisher to fail over to when the others fail; may be repeated"
+    )]
+    pub fallback_publishers: Vec<String>,
+
+    #[arg(short = 'e', long, help = "Number of epochs for storage")]
+    pub epochs: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Timeout of each Walrus store request (default: 60)"
+    )]
+    pub timeout: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Retry rounds after network errors, timeouts and 408/429/5xx responses (default: 3)"
+    )]
+    pub retries: Option<u32>,
+}
+
+impl WalrusArgs {
+    /// The flags given on the command line, to layer over the configuration file
+    pub fn to_settings(&self) -> WalrusSettings {
+        WalrusSettings {
+            aggregator_url: self.aggregator_url.clone(),
+            fallback_aggregators: None,
+            publisher_url: self.publisher_url.clone(),
+            fallback_publishers: (!self.fallback_publishers.is_empty())
+                .then(|| self.fallback_publishers.clone()),
+            epochs: self.epochs,
+            timeout_secs: self.timeout,
+            retries: self.retries,
+        }
+    }
+}
+
+/// Encoding options shared by the `compress`, `batch` and `watch` commands
+#[derive(ClapArgs, Debug, Clone)]
+pub struct CompressionArgs {
+    #[arg(short = 'q', long, help = "Quality (1-100), default is 80")]
+    pub quality: Option<u8>,
+
+    #[arg(short = 'w', long, help = "Target width in pixels (see --resize-mode)")]
+    pub width: Option<u32>,
+
+    #[arg(
+        short = 'H',
+        long,
+        help = "Target height in pixels (see --resize-mode)"
+    )]
+    pub height: Option<u32>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "How width/height are applied: fit within (default), fill and crop, cover, exact, or fit without upscaling"
+    )]
+    pub resize_mode: Option<ResizeMode>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Resampling filter (nearest for pixel art, triangle for speed, lanczos3 for quality; default)"
+    )]
+    pub filter: Option<ResizeFilter>,
+
+    #[arg(
+        short = 'f',
+        long,
+        value_parser = ["jpeg","jpg","png","webp","avif"],
+        value_name = "FORMAT",
+        help = "Output format (jpeg, jpg, png, webp, avif). Note: heic/heif/jxl are recognized as inputs only."
+    )]
+    pub format: Option<String>,
+
+    #[arg(long, value_enum, help = "JPEG chroma subsampling (default: 420)")]
+    pub subsampling: Option<ChromaSubsampling>,
+
+    #[arg(
+        long,
+        overrides_with = "no_progressive",
+        help = "Write progressive JPEG instead of baseline"
+    )]
+    pub progressive: bool,
+
+    #[arg(
+        long,
+        overrides_with = "progressive",
+        help = "Write baseline JPEG even if the configuration enables progressive"
+    )]
+    pub no_progressive: bool,
+
+    #[arg(
+        long,
+        overrides_with = "no_optimize_huffman",
+        help = "Optimize JPEG Huffman tables (smaller files, slower)"
+    )]
+    pub optimize_huffman: bool,
+
+    #[arg(
+        long,
+        overrides_with = "optimize_huffman",
+        help = "Use the standard JPEG Huffman tables even if the configuration enables optimize-huffman"
+    )]
+    pub no_optimize_huffman: bool,
+
+    #[arg(
+        long,
+        overrides_with = "no_lossless",
+        help = "Encode WebP losslessly (quality then controls effort)"
+    )]
+    pub lossless: bool,
+
+    #[arg(
+        long,
+        overrides_with = "lossless",
+        help = "Encode WebP lossily even if the configuration enables lossless"
+    )]
+    pub no_lossless: bool,
+
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u8).range(0..=6),
+        help = format!("WebP encoder method (0 = fastest, 6 = smallest), default is {}", DEFAULT_WEBP_METHOD)
+    )]
+    pub webp_method: Option<u8>,
+
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        help = format!("WebP alpha channel quality (0-100), default is {}", DEFAULT_WEBP_ALPHA_QUALITY)
+    )]
+    pub alpha_quality: Option<u8>,
+
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        value_name = "LEVEL",
+        help = "WebP near-lossless preprocessing level (0-100, lower is smaller); implies --lossless"
+    )]
+    pub near_lossless: Option<u8>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Source metadata to keep: all (keep), none (strip, default), ICC profile only (keep-icc), or ICC plus EXIF artist/copyright (keep-copyright)"
+    )]
+    pub metadata: Option<MetadataPolicy>,
+
+    #[arg(
+        long,
+        overrides_with = "auto_orient",
+        help = "Keep pixels in stored orientation instead of applying the EXIF orientation tag"
+    )]
+    pub no_auto_orient: bool,
+
+    #[arg(
+        long,
+        overrides_with = "no_auto_orient",
+        help = "Apply the EXIF orientation tag even if the configuration disables auto-orient (default)"
+    )]
+    pub auto_orient: bool,
+
+    #[arg(
+        long,
+        value_parser = parse_byte_size,
+        value_name = "SIZE",
+        help = "Largest acceptable output size (e.g. 200K, 1.5M); searches quality up to --quality"
+    )]
+    pub target_size: Option<u64>,
+
+    #[arg(
+        long,
+        overrides_with = "no_target_downscale",
+        help = "Downscale when --target-size cannot be met at minimum quality"
+    )]
+    pub target_downscale: bool,
+
+    #[arg(
+        long,
+        overrides_with = "target_downscale",
+        help = "Never downscale for the target size, even if the configuration enables target-downscale"
+    )]
+    pub no_target_downscale: bool,
+
+    #[arg(
+        long,
+        value_parser = parse_ssim,
+        value_name = "SSIM",
+        conflicts_with_all = ["target_size", "target_dssim"],
+        help = "Smallest output scoring at least this SSIM (0-1, e.g. 0.98) against the resized source; searches quality up to --quality"
+    )]
+    pub target_ssim: Option<f64>,
+
+    #[arg(
+        long,
+        value_parser = parse_dssim,
+        value_name = "DSSIM",
+        conflicts_with = "target_size",
+        help = "Like --target-ssim, but as a maximum DSSIM (1/SSIM - 1, e.g. 0.01)"
+    )]
+    pub target_dssim: Option<f64>,
+}
+
+impl CompressionArgs {
+    /// The flags given on the command line, to layer over the configuration file
+    pub fn to_settings(&self) -> Settings {
+        Settings {
+            quality: self.quality,
+            width: self.width,
+            height: self.height,
+            resize_mode: self.resize_mode,
+            filter: self.filter,
+            format: self.format.clone(),
+            subsampling: self.subsampling,
+            progressive: toggle(self.progressive, self.no_progressive),
+            optimize_huffman: toggle(self.optimize_huffman, self.no_optimize_huffman),
+            lossless: toggle(self.lossless, self.no_lossless),
+            webp_method: self.webp_method,
+            alpha_quality: self.alpha_quality,
+            near_lossless: self.near_lossless,
+            metadata: self.metadata,
+            auto_orient: toggle(self.auto_orient, self.no_auto_orient),
+            target_size: self.target_size,
+            target_downscale: toggle(self.target_downscale, self.no_target_downscale),
+            target_ssim: self.target_ssim,
+            target_dssim: self.target_dssim,
+            walrus: WalrusSettings::default(),
+        }
+    }
+}
+
+/// Maps a `--flag` / `--no-flag` pair to a setting; `None` leaves the configured value
+fn toggle(on: bool, off: bool) -> Option<bool> {
+    if on {
+        Some(true)
+    } else if off {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Parses a byte count with an optional binary unit suffix: `204800`, `200K`, `200KB`, `1.5MiB`
+pub(crate) fn parse_byte_size(value: &str) -> std::result::Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        _ => return Err(format!("unknown size unit '{}'", unit.trim())),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let bytes = (number * multiplier as f64).round();
+    if bytes < 1.0 {
+        return Err("size must be at least 1 byte".to_string());
+    }
+    Ok(bytes as u64)
+}
+
+/// Parses an SSIM threshold in `(0, 1]`
+fn parse_ssim(value: &str) -> std::result::Result<f64, String> {
+    let ssim: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid SSIM '{}'", value))?;
+    if !(ssim > 0.0 && ssim <= 1.0) {
+        return Err("SSIM must be greater than 0 and at most 1".to_string());
+    }
+    Ok(ssim)
+}
+
+/// Parses a non-negative DSSIM threshold
+fn parse_dssim(value: &str) -> std::result::Result<f64, String> {
+    let dssim: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid DSSIM '{}'", value))?;
+    if !(dssim >= 0.0 && dssim.is_finite()) {
+        return Err("DSSIM must be a finite number of at least 0".to_string());
+    }
+    Ok(dssim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(args: &[&str]) -> Settings {
+        let args = Args::try_parse_from(
+            ["img-squeeze", "compress", "in.jpg", "out.jpg"]
+                .iter()
+                .chain(args),
+        )
+        .unwrap();
+        match args.command {
+            Commands::Compress { options, .. } => options.to_settings(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_toggles_map_both_directions() {
+        let unset = settings(&[]);
+        assert_eq!(unset.progressive, None);
+        assert_eq!(unset.lossless, None);
+        assert_eq!(unset.auto_orient, None);
+
+        let on = settings(&[
+            "--progressive",
+            "--optimize-huffman",
+            "--lossless",
+            "--auto-orient",
+        ]);
+        assert_eq!(on.progressive, Some(true));
+        assert_eq!(on.optimize_huffman, Some(true));
+        assert_eq!(on.lossless, Some(true));
+        assert_eq!(on.auto_orient, Some(true));
+
+        let off = settings(&[
+            "--no-progressive",
+            "--no-optimize-huffman",
+            "--no-lossless",
+            "--no-auto-orient",
+            "--no-target-downscale",
+        ]);
+        assert_eq!(off.progressive, Some(false));
+        assert_eq!(off.optimize_huffman, Some(false));
+        assert_eq!(off.lossless, Some(false));
+        assert_eq!(off.auto_orient, Some(false));
+        assert_eq!(off.target_downscale, Some(false));
+    }
+
+    #[test]
+    fn test_target_downscale_leaves_target_size_to_the_configuration() {
+        // target-size may come from a preset, so it is checked once merged
+        let settings = settings(&["--target-downscale"]);
+        assert_eq!(settings.target_downscale, Some(true));
+        assert_eq!(settings.target_size, None);
+    }
+
+    #[test]
+    fn test_last_toggle_wins() {
+        assert_eq!(
+            settings(&["--progressive", "--no-progressive"]).progressive,
+            Some(false)
+        );
+        assert_eq!(
+            settings(&["--no-lossless", "--lossless"]).lossless,
+            Some(true)
+        );
+        assert_eq!(
+            settings(&["--auto-orient", "--no-auto-orient"]).auto_orient,
+            Some(false)
+        );
+    }
 }
@@ -0,0 +1,174 @@
+use crate::error::{CompressionError, Result};
+use crate::processing::load_image_with_metadata;
+use crate::quality::{diff_heatmap, max_pixel_delta, psnr, ssim};
+use image::ImageFormat;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// Quality and size comparison between an original image and its compressed copy
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ComparisonReport {
+    pub dimensions: (u32, u32),
+    pub original_size: u64,
+    pub compressed_size: u64,
+    /// Percentage of the original file size saved by the compressed copy
+    /// (negative when the compressed file is larger)
+    pub size_savings: f64,
+    /// PSNR in dB; infinite (serialized as `null`) for identical pixels
+    pub psnr: f64,
+    pub ssim: f64,
+    pub max_delta: u8,
+}
+
+/// Compares a compressed image against its original
+///
+/// Both files are loaded with EXIF orientation applied, so an auto-oriented
+/// output lines up with its source.
+///
+/// # Arguments
+/// * `original_path` - Path to the reference image
+/// * `compressed_path` - Path to the compressed image
+/// * `diff_output` - Optional path to write a difference heatmap to
+///
+/// # Returns
+/// * `Ok(ComparisonReport)` - Metrics for the pair
+/// * `Err(CompressionError::DimensionMismatch)` - If the images differ in size
+/// * `Err(CompressionError::UnsupportedFormat)` - If either image is AVIF, which
+///   this build can encode but not decode
+pub fn compare_images(
+    original_path: &Path,
+    compressed_path: &Path,
+    diff_output: Option<&Path>,
+) -> Result<ComparisonReport> {
+    reject_undecodable(original_path)?;
+    reject_undecodable(compressed_path)?;
+    let (original, original_size) = load_image_with_metadata(original_path)?;
+    let (compressed, compressed_size) = load_image_with_metadata(compressed_path)?;
+
+    let report = ComparisonReport {
+        dimensions: (original.width(), original.height()),
+        original_size,
+        compressed_size,
+        size_savings: if original_size > 0 {
+            (1.0 - compressed_size as f64 / original_size as f64) * 100.0
+        } else {
+            0.0
+        },
+        psnr: psnr(&original, &compressed)?,
+        ssim: ssim(&original, &compressed)?,
+        max_delta: max_pixel_delta(&original, &compressed)?,
+    };
+
+    if let Some(diff_path) = diff_output {
+        if let Some(parent) = diff_path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        diff_heatmap(&original, &compressed)?.save(diff_path)?;
+    }
+
+    Ok(report)
+}
+
+/// Fails early, with a clear message, for images `compare` cannot decode
+fn reject_undecodable(path: &Path) -> Result<()> {
+    let format = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .ok()
+        .and_then(|reader| reader.format());
+    if format == Some(ImageFormat::Avif) {
+        return Err(CompressionError::UnsupportedFormat(format!(
+            "{} is AVIF, which cannot be decoded for comparison; compare a JPEG, PNG or WebP output instead",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Prints a comparison report
+pub fn print_comparison(report: &ComparisonReport) {
+    println!("📊 Comparison Results:");
+    println!(
+        "  📏 Dimensions: {}x{} pixels",
+        report.dimensions.0, report.dimensions.1
+    );
+    if report.psnr.is_infinite() {
+        println!("  📈 PSNR: ∞ (identical pixels)");
+    } else {
+        println!("  📈 PSNR: {:.2} dB", report.psnr);
+    }
+    println!("  👁️  SSIM: {:.4}", report.ssim);
+    println!("  🔺 Max pixel delta: {}", report.max_delta);
+    println!("  📦 Original size: {} bytes", report.original_size);
+    println!("  📦 Compressed size: {} bytes", report.compressed_size);
+    println!("  🎯 Size savings: {:.1}%", report.size_savings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_compare_identical_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("same.png");
+        RgbImage::from_fn(16, 16, |x, y| {
+            image::Rgb([(x * 16) as u8, (y * 16) as u8, 128])
+        })
+        .save(&path)
+        .unwrap();
+        let diff = temp_dir.path().join("diff/heatmap.png");
+
+        let report = compare_images(&path, &path, Some(&diff)).unwrap();
+        assert_eq!(report.dimensions, (16, 16));
+        assert!(report.psnr.is_infinite());
+        assert!((report.ssim - 1.0).abs() < 1e-9);
+        assert_eq!(report.max_delta, 0);
+        assert_eq!(report.size_savings, 0.0);
+        assert!(image::open(&diff)
+            .unwrap()
+            .to_rgb8()
+            .pixels()
+            .all(|p| p.0 == [0, 0, 0]));
+    }
+
+    #[test]
+    fn test_compare_dimension_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        let small = temp_dir.path().join("small.png");
+        let large = temp_dir.path().join("large.png");
+        RgbImage::new(8, 8).save(&small).unwrap();
+        RgbImage::new(8, 9).save(&large).unwrap();
+
+        assert!(matches!(
+            compare_images(&small, &large, None),
+            Err(crate::error::CompressionError::DimensionMismatch(
+                8, 8, 8, 9
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_compare_rejects_avif() {
+        let temp_dir = TempDir::new().unwrap();
+        let original = temp_dir.path().join("original.png");
+        let img = RgbImage::new(8, 8);
+        img.save(&original).unwrap();
+        // Named .png on purpose: the format is sniffed from the contents
+        let avif = temp_dir.path().join("compressed.png");
+        img.save_with_format(&avif, ImageFormat::Avif).unwrap();
+
+        for (a, b) in [(&original, &avif), (&avif, &original)] {
+            let result = compare_images(a, b, None);
+            assert!(
+                matches!(&result, Err(CompressionError::UnsupportedFormat(message)) if message.contains("AVIF")),
+                "{:?}",
+                result
+            );
+        }
+    }
+}
@@ -0,0 +1,664 @@
+use crate::cli::parse_byte_size;
+use crate::constants::{CONFIG_DIR_NAME, CONFIG_FILE_NAME, MAX_WEBP_METHOD};
+use crate::error::{CompressionError, Result};
+use crate::metadata::MetadataPolicy;
+use crate::processing::{ChromaSubsampling, CompressionOptions, ResizeFilter, ResizeMode};
+use crate::quality::dssim_to_ssim;
+use crate::walrus::WalrusOptions;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Output formats accepted by `--format` and the `format` key
+const OUTPUT_FORMATS: [&str; 5] = ["jpeg", "jpg", "png", "webp", "avif"];
+
+/// One layer of settings: the `[defaults]` table, a preset, or the command line.
+///
+/// Keys match the long command-line flags. Unset fields fall through to the
+/// layer below and finally to the built-in defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resize_mode: Option<ResizeMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<ResizeFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subsampling: Option<ChromaSubsampling>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progressive: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optimize_huffman: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lossless: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webp_method: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha_quality: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub near_lossless: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MetadataPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_orient: Option<bool>,
+    /// Bytes, or a size string such as `"200K"`
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_byte_size"
+    )]
+    pub target_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_downscale: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_ssim: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_dssim: Option<f64>,
+    pub walrus: WalrusSettings,
+}
+
+/// Walrus endpoints, storage duration and retry policy of one settings layer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WalrusSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregator_url: Option<String>,
+    /// Aggregators to fail over to when downloading, in order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_aggregators: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher_url: Option<String>,
+    /// Publishers to fail over to, in order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_publishers: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epochs: Option<u64>,
+    /// Timeout of one store or read request in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Retry rounds after transient failures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+}
+
+/// Contents of an `img-squeeze.toml` file
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Applied to every command
+    pub defaults: Settings,
+    /// Named settings selected with `--preset`, applied on top of `defaults`
+    pub presets: BTreeMap<String, Settings>,
+}
+
+/// A configuration file together with where it was found
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// `None` when no configuration file exists
+    pub source: Option<PathBuf>,
+    pub file: ConfigFile,
+}
+
+/// The configuration in effect for a preset, as printed by `config show`
+#[derive(Debug, Serialize)]
+pub struct EffectiveConfig {
+    pub source: Option<PathBuf>,
+    /// Locations searched when no `--config` file is given, in order
+    pub search_paths: Vec<PathBuf>,
+    pub preset: Option<String>,
+    pub presets: Vec<String>,
+    /// Every setting with built-in defaults filled in
+    pub settings: Settings,
+}
+
+impl Settings {
+    /// Returns these settings with every field set in `overrides` replaced.
+    ///
+    /// `target-size`, `target-ssim` and `target-dssim` are mutually exclusive,
+    /// so setting any of them in `overrides` replaces all three.
+    pub fn overridden_by(self, overrides: Settings) -> Settings {
+        let overrides_target = overrides.target_size.is_some()
+            || overrides.target_ssim.is_some()
+            || overrides.target_dssim.is_some();
+        let (target_size, target_ssim, target_dssim) = if overrides_target {
+            (
+                overrides.target_size,
+                overrides.target_ssim,
+                overrides.target_dssim,
+            )
+        } else {
+            (self.target_size, self.target_ssim, self.target_dssim)
+        };
+
+        Settings {
+            quality: overrides.quality.or(self.quality),
+            width: overrides.width.or(self.width),
+            height: overrides.height.or(self.height),
+            resize_mode: overrides.resize_mode.or(self.resize_mode),
+            filter: overrides.filter.or(self.filter),
+            format: overrides.format.or(self.format),
+            subsampling: overrides.subsampling.or(self.subsampling),
+            progressive: overrides.progressive.or(self.progressive),
+            optimize_huffman: overrides.optimize_huffman.or(self.optimize_huffman),
+            lossless: overrides.lossless.or(self.lossless),
+            webp_method: overrides.webp_method.or(self.webp_method),
+            alpha_quality: overrides.alpha_quality.or(self.alpha_quality),
+            near_lossless: overrides.near_lossless.or(self.near_lossless),
+            metadata: overrides.metadata.or(self.metadata),
+            auto_orient: overrides.auto_orient.or(self.auto_orient),
+            target_size,
+            target_downscale: overrides.target_downscale.or(self.target_downscale),
+            target_ssim,
+            target_dssim,
+            walrus: self.walrus.overridden_by(overrides.walrus),
+        }
+    }
+
+    /// Builds validated `CompressionOptions`, using built-in defaults for unset fields
+    ///
+    /// # Returns
+    /// * `Ok(CompressionOptions)` - The options to compress with
+    /// * `Err(CompressionError)` - If a value is out of range or settings conflict
+    pub fn to_compression_options(&self) -> Result<CompressionOptions> {
+        if let Some(format) = &self.format {
+            if !OUTPUT_FORMATS.contains(&format.to_lowercase().as_str()) {
+                return Err(CompressionError::UnsupportedFormat(format.clone()));
+            }
+        }
+        if self
+            .webp_method
+            .is_some_and(|method| method > MAX_WEBP_METHOD)
+        {
+            return Err(CompressionError::Config(format!(
+                "webp-method must be between 0 and {}",
+                MAX_WEBP_METHOD
+            )));
+        }
+        if self.alpha_quality.is_some_and(|quality| quality > 100) {
+            return Err(CompressionError::Config(
+                "alpha-quality must be between 0 and 100".to_string(),
+            ));
+        }
+        if self.near_lossless.is_some_and(|level| level > 100) {
+            return Err(CompressionError::Config(
+                "near-lossless must be between 0 and 100".to_string(),
+            ));
+        }
+        if self.target_size == Some(0) {
+            return Err(CompressionError::Config(
+                "target-size must be at least 1 byte".to_string(),
+            ));
+        }
+        self.walrus.validate()?;
+        if self.target_downscale == Some(true) && self.target_size.is_none() {
+            return Err(CompressionError::Config(
+                "target-downscale requires target-size".to_string(),
+            ));
+        }
+        if self.target_ssim.is_some() && self.target_dssim.is_some() {
+            return Err(CompressionError::Config(
+                "target-ssim and target-dssim cannot be combined".to_string(),
+            ));
+        }
+        if let Some(dssim) = self.target_dssim {
+            if !(dssim >= 0.0 && dssim.is_finite()) {
+                return Err(CompressionError::Config(
+                    "target-dssim must be a finite number of at least 0".to_string(),
+                ));
+            }
+        }
+        let target_ssim = self.target_ssim.or(self.target_dssim.map(dssim_to_ssim));
+        if let Some(ssim) = target_ssim {
+            if !(ssim > 0.0 && ssim <= 1.0) {
+                return Err(CompressionError::Config(
+                    "target-ssim must be greater than 0 and at most 1".to_string(),
+                ));
+            }
+            if self.target_size.is_some() {
+                return Err(CompressionError::Config(
+                    "target-size cannot be combined with target-ssim or target-dssim".to_string(),
+                ));
+            }
+        }
+
+        let defaults = CompressionOptions::new(None, None, None, None)?;
+        let options =
+            CompressionOptions::new(self.quality, self.width, self.height, self.format.clone())?
+                .with_resize_mode(self.resize_mode.unwrap_or(defaults.resize_mode))
+                .with_filter(self.filter.unwrap_or(defaults.filter))
+                .with_jpeg_options(
+                    self.subsampling.unwrap_or(defaults.chroma_subsampling),
+                    self.progressive.unwrap_or(defaults.progressive),
+                    self.optimize_huffman.unwrap_or(defaults.optimize_huffman),
+                )
+                .with_webp_options(
+                    self.lossless.unwrap_or(defaults.webp_lossless),
+                    self.webp_method.unwrap_or(defaults.webp_method),
+                    self.alpha_quality.unwrap_or(defaults.webp_alpha_quality),
+                    self.near_lossless,
+                )
+                .with_metadata(self.metadata.unwrap_or(defaults.metadata))
+                .with_auto_orient(self.auto_orient.unwrap_or(defaults.auto_orient))
+                .with_target_size(
+                    self.target_size,
+                    self.target_downscale.unwrap_or(defaults.target_downscale),
+                )
+                .with_target_ssim(target_ssim);
+        Ok(options)
+    }
+
+    /// Returns these settings with every built-in default filled in
+    ///
+    /// # Returns
+    /// * `Ok(Settings)` - Fully populated settings
+    /// * `Err(CompressionError)` - If the settings are invalid
+    pub fn resolved(&self) -> Result<Settings> {
+        let options = self.to_compression_options()?;
+        let walrus = self.walrus.to_walrus_options();
+        Ok(Settings {
+            quality: Some(options.quality),
+            width: options.width,
+            height: options.height,
+            resize_mode: Some(options.resize_mode),
+            filter: Some(options.filter),
+            format: options.format,
+            subsampling: Some(options.chroma_subsampling),
+            progressive: Some(options.progressive),
+            optimize_huffman: Some(options.optimize_huffman),
+            lossless: Some(options.webp_lossless),
+            webp_method: Some(options.webp_method),
+            alpha_quality: Some(options.webp_alpha_quality),
+            near_lossless: options.webp_near_lossless,
+            metadata: Some(options.metadata),
+            auto_orient: Some(options.auto_orient),
+            target_size: options.target_size,
+            target_downscale: Some(options.target_downscale),
+            target_ssim: options.target_ssim,
+            target_dssim: None,
+            walrus: WalrusSettings {
+                aggregator_url: Some(walrus.aggregator_url),
+                fallback_aggregators: Some(walrus.fallback_aggregators),
+                publisher_url: Some(walrus.publisher_url),
+                fallback_publishers: Some(walrus.fallback_publishers),
+                epochs: walrus.epochs,
+                timeout_secs: Some(walrus.retry.timeout.as_secs()),
+                retries: Some(walrus.retry.retries),
+            },
+        })
+    }
+}
+
+impl WalrusSettings {
+    /// Returns these settings with every field set in `overrides` replaced
+    pub fn overridden_by(self, overrides: WalrusSettings) -> WalrusSettings {
+        WalrusSettings {
+            aggregator_url: overrides.aggregator_url.or(self.aggregator_url),
+            fallback_aggregators: overrides.fallback_aggregators.or(self.fallback_aggregators),
+            publisher_url: overrides.publisher_url.or(self.publisher_url),
+            fallback_publishers: overrides.fallback_publishers.or(self.fallback_publishers),
+            epochs: overrides.epochs.or(self.epochs),
+            timeout_secs: overrides.timeout_secs.or(self.timeout_secs),
+            retries: overrides.retries.or(self.retries),
+        }
+    }
+
+    /// Rejects values the command-line flags would not accept
+    ///
+    /// # Returns
+    /// * `Ok(())` - If the settings are usable
+    /// * `Err(CompressionError::Config)` - If `timeout-secs` is 0, which would
+    ///   time out every request at once
+    pub fn validate(&self) -> Result<()> {
+        if self.timeout_secs == Some(0) {
+            return Err(CompressionError::Config(
+                "walrus timeout-secs must be at least 1 second".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Builds `WalrusOptions`, using the testnet endpoints for unset URLs and
+    /// the default retry policy for unset limits
+    pub fn to_walrus_options(&self) -> WalrusOptions {
+        let mut options = WalrusOptions::new(
+            self.aggregator_url.clone(),
+            self.publisher_url.clone(),
+            self.epochs,
+        );
+        options.fallback_aggregators = self.fallback_aggregators.clone().unwrap_or_default();
+        options.fallback_publishers = self.fallback_publishers.clone().unwrap_or_default();
+        if let Some(timeout_secs) = self.timeout_secs {
+            options.retry.timeout = Duration::from_secs(timeout_secs);
+        }
+        if let Some(retries) = self.retries {
+            options.retry.retries = retries;
+        }
+        options
+    }
+}
+
+impl Config {
+    /// Locations searched for a configuration file, in order: the current
+    /// directory, then the user configuration directory
+    pub fn search_paths() -> Vec<PathBuf> {
+        let project_dir = std::env::current_dir().unwrap_or_default();
+        let mut paths = vec![project_dir.join(CONFIG_FILE_NAME)];
+        if let Some(config_dir) = dirs::config_dir() {
+            paths.push(config_dir.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME));
+        }
+        paths
+    }
+
+    /// Loads the first configuration file found in `search_paths`
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - The parsed file, or an empty configuration if none exists
+    /// * `Err(CompressionError)` - If a file exists but cannot be read or parsed
+    pub fn discover() -> Result<Self> {
+        match Self::search_paths().into_iter().find(|path| path.is_file()) {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Loads the configuration file at `path`
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - The parsed file
+    /// * `Err(CompressionError)` - If the file is missing, unreadable or invalid
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Err(CompressionError::FileNotFound(path.to_path_buf()));
+        }
+        let contents = fs::read_to_string(path)?;
+        let file = toml::from_str(&contents)
+            .map_err(|e| CompressionError::Config(format!("{}: {}", path.display(), e)))?;
+        Ok(Self {
+            source: Some(path.to_path_buf()),
+            file,
+        })
+    }
+
+    /// Returns `[defaults]` with `preset` applied on top
+    ///
+    /// # Returns
+    /// * `Ok(Settings)` - The settings of the preset, or just the defaults
+    /// * `Err(CompressionError::Config)` - If the preset is not defined
+    pub fn settings(&self, preset: Option<&str>) -> Result<Settings> {
+        let defaults = self.file.defaults.clone();
+        let Some(name) = preset else {
+            return Ok(defaults);
+        };
+        match self.file.presets.get(name) {
+            Some(preset) => Ok(defaults.overridden_by(preset.clone())),
+            None => Err(CompressionError::Config(format!(
+                "unknown preset '{}' (available: {})",
+                name,
+                self.preset_names().join(", ")
+            ))),
+        }
+    }
+
+    /// Names of the presets defined in the file, sorted
+    pub fn preset_names(&self) -> Vec<String> {
+        self.file.presets.keys().cloned().collect()
+    }
+
+    /// Describes the configuration in effect for `preset`
+    pub fn effective(&self, preset: Option<&str>) -> Result<EffectiveConfig> {
+        Ok(EffectiveConfig {
+            source: self.source.clone(),
+            search_paths: Self::search_paths(),
+            preset: preset.map(str::to_string),
+            presets: self.preset_names(),
+            settings: self.settings(preset)?.resolved()?,
+        })
+    }
+}
+
+/// Prints the effective configuration as TOML that can be pasted into a preset
+pub fn print_effective_config(config: &EffectiveConfig) {
+    match &config.source {
+        Some(source) => println!("📄 Configuration file: {:?}", source),
+        None => {
+            println!("📄 No configuration file found. Searched:");
+            for path in &config.search_paths {
+                println!("    {:?}", path);
+            }
+        }
+    }
+    if let Some(preset) = &config.preset {
+        println!("🎛️  Preset: {}", preset);
+    }
+    if !config.presets.is_empty() {
+        println!("📚 Available presets: {}", config.presets.join(", "));
+    }
+
+    println!("\n⚙️  Effective settings:");
+    match toml::to_string_pretty(&config.settings) {
+        Ok(settings) => print!("{}", settings),
+        Err(e) => println!("⚠️  Cannot render settings: {}", e),
+    }
+}
+
+/// Accepts a byte count or a size string such as `"1.5M"`
+fn deserialize_byte_size<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ByteSize {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Option::<ByteSize>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(ByteSize::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(ByteSize::Text(text)) => parse_byte_size(&text)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{DEFAULT_WALRUS_AGGREGATOR, DEFAULT_WALRUS_PUBLISHER};
+    use tempfile::TempDir;
+
+    const SAMPLE: &str = r#"
+[defaults]
+quality = 85
+metadata = "keep-icc"
+
+[defaults.walrus]
+epochs = 3
+
+[presets.web-thumb]
+width = 320
+height = 320
+resize-mode = "fill"
+format = "webp"
+quality = 70
+
+[presets.archive]
+quality = 95
+target-size = "2M"
+walrus = { epochs = 200 }
+"#;
+
+    fn sample_config() -> (TempDir, Config) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(&path, SAMPLE).unwrap();
+        let config = Config::load(&path).unwrap();
+        (dir, config)
+    }
+
+    #[test]
+    fn test_preset_layers_over_defaults() {
+        let (_dir, config) = sample_config();
+        assert_eq!(config.preset_names(), ["archive", "web-thumb"]);
+
+        let options = config
+            .settings(Some("web-thumb"))
+            .unwrap()
+            .to_compression_options()
+            .unwrap();
+        assert_eq!(options.quality, 70);
+        assert_eq!((options.width, options.height), (Some(320), Some(320)));
+        assert_eq!(options.resize_mode, ResizeMode::Fill);
+        assert_eq!(options.metadata, MetadataPolicy::KeepIcc);
+        assert_eq!(options.format.as_deref(), Some("webp"));
+
+        let archive = config.settings(Some("archive")).unwrap();
+        assert_eq!(archive.target_size, Some(2 * 1024 * 1024));
+        assert_eq!(archive.walrus.to_walrus_options().epochs, Some(200));
+        assert_eq!(
+            archive.walrus.to_walrus_options().aggregator_url,
+            DEFAULT_WALRUS_AGGREGATOR
+        );
+    }
+
+    #[test]
+    fn test_overrides_replace_file_values() {
+        let (_dir, config) = sample_config();
+        let cli = Settings {
+            quality: Some(50),
+            target_ssim: Some(0.98),
+            ..Settings::default()
+        };
+        let settings = config.settings(Some("archive")).unwrap().overridden_by(cli);
+        assert_eq!(settings.quality, Some(50));
+        // A target on the command line replaces the preset's target
+        assert_eq!(settings.target_size, None);
+        let options = settings.to_compression_options().unwrap();
+        assert_eq!(options.target_ssim, Some(0.98));
+        assert_eq!(options.metadata, MetadataPolicy::KeepIcc);
+    }
+
+    #[test]
+    fn test_cli_target_downscale_uses_the_preset_target_size() {
+        let (_dir, config) = sample_config();
+        let cli = Settings {
+            target_downscale: Some(true),
+            ..Settings::default()
+        };
+        let options = config
+            .settings(Some("archive"))
+            .unwrap()
+            .overridden_by(cli.clone())
+            .to_compression_options()
+            .unwrap();
+        assert_eq!(options.target_size, Some(2 * 1024 * 1024));
+        assert!(options.target_downscale);
+
+        // Without a target size anywhere, downscaling has nothing to aim for
+        assert!(matches!(
+            config
+                .settings(Some("web-thumb"))
+                .unwrap()
+                .overridden_by(cli)
+                .to_compression_options(),
+            Err(CompressionError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_configuration() {
+        let (_dir, config) = sample_config();
+        assert!(matches!(
+            config.settings(Some("missing")),
+            Err(CompressionError::Config(_))
+        ));
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        fs::write(&path, "[defaults]\nqualty = 80\n").unwrap();
+        assert!(matches!(
+            Config::load(&path),
+            Err(CompressionError::Config(_))
+        ));
+
+        let settings = Settings {
+            webp_method: Some(9),
+            ..Settings::default()
+        };
+        assert!(settings.to_compression_options().is_err());
+    }
+
+    #[test]
+    fn test_resolved_settings_round_trip() {
+        let resolved = Settings::default().resolved().unwrap();
+        assert_eq!(resolved.quality, Some(80));
+        assert_eq!(
+            resolved.walrus.publisher_url.as_deref(),
+            Some(DEFAULT_WALRUS_PUBLISHER)
+        );
+
+        let text = toml::to_string_pretty(&resolved).unwrap();
+        let parsed: Settings = toml::from_str(&text).unwrap();
+        assert_eq!(parsed, resolved);
+    }
+
+    #[test]
+    fn test_walrus_retry_settings() {
+        let file: Settings = toml::from_str(
+            r#"
+[walrus]
+fallback-aggregators = ["https://read-backup.example"]
+fallback-publishers = ["https://backup.example"]
+timeout-secs = 5
+retries = 1
+"#,
+        )
+        .unwrap();
+        let walrus = file.walrus.overridden_by(WalrusSettings {
+            retries: Some(0),
+            ..WalrusSettings::default()
+        });
+        let options = walrus.to_walrus_options();
+        assert_eq!(
+            options.fallback_aggregators,
+            ["https://read-backup.example"]
+        );
+        assert_eq!(options.fallback_publishers, ["https://backup.example"]);
+        assert_eq!(options.retry.timeout, Duration::from_secs(5));
+        assert_eq!(options.retry.retries, 0);
+
+        let defaults = WalrusSettings::default().to_walrus_options().retry;
+        assert_eq!(defaults, crate::walrus::RetryPolicy::default());
+    }
+
+    #[test]
+    fn test_zero_walrus_timeout_is_rejected() {
+        let settings: Settings = toml::from_str("[walrus]\ntimeout-secs = 0\n").unwrap();
+        assert!(matches!(
+            settings.to_compression_options(),
+            Err(CompressionError::Config(_))
+        ));
+        assert!(matches!(
+            settings.walrus.validate(),
+            Err(CompressionError::Config(_))
+        ));
+
+        let settings = settings.overridden_by(Settings {
+            walrus: WalrusSettings {
+                timeout_secs: Some(1),
+                ..WalrusSettings::default()
+            },
+            ..Settings::default()
+        });
+        assert!(settings.to_compression_options().is_ok());
+    }
+}
@@ -5,12 +5,29 @@ pub const MAX_QUALITY: u8 = 100;
 pub const DEFAULT_EPOCHS: u64 = 10;
 pub const TEMP_EPOCHS: u64 = 1;
 
+pub const DEFAULT_WEBP_METHOD: u8 = 4; // libwebp default effort (0 = fastest, 6 = smallest)
+pub const MAX_WEBP_METHOD: u8 = 6;
+pub const DEFAULT_WEBP_ALPHA_QUALITY: u8 = 100;
+
+pub const MAX_TARGET_SIZE_DOWNSCALES: u32 = 8; // Shrink attempts before giving up on --target-size
+pub const TARGET_SIZE_DOWNSCALE_MARGIN: f64 = 0.95; // Undershoot each shrink so it usually fits
+
 pub const ZOPFLI_ITERATIONS: u8 = 15;
 pub const LIBDEFLATER_HIGH_LEVEL: u8 = 12;
 pub const LIBDEFLATER_LOW_LEVEL: u8 = 8;
 
 pub const DEFAULT_WALRUS_AGGREGATOR: &str = "https://aggregator.walrus-testnet.walrus.space";
 pub const DEFAULT_WALRUS_PUBLISHER: &str = "https://publisher.walrus-testnet.walrus.space";
+pub const DEFAULT_UPLOAD_CONCURRENCY: usize = 4; // Simultaneous uploads of a directory or glob
+pub const DEFAULT_WALRUS_TIMEOUT_SECS: u64 = 60; // Per store request, including sending the blob
+pub const DEFAULT_WALRUS_RETRIES: u32 = 3; // Retry rounds over the publishers after transient failures
+pub const WALRUS_INITIAL_BACKOFF_MS: u64 = 500; // Doubled every retry round, with jitter
+pub const WALRUS_MAX_BACKOFF_MS: u64 = 10_000;
+pub const WALRUS_UPLOAD_CHUNK_SIZE: usize = 64 * 1024; // Bytes read and sent per step of a streamed upload
+pub const WALRUS_FORMAT_SNIFF_BYTES: usize = 64; // Leading bytes of a downloaded blob kept to detect its format
+pub const UPLOAD_MANIFEST_VERSION: u32 = 1;
+
+pub const STDIO_PATH: &str = "-"; // Stands for stdin as an input and stdout as an output
 
 pub const MAX_IMAGE_DIMENSION: u32 = 16384; // Maximum allowed image dimension
 pub const MAX_FILE_SIZE: u64 = 100 * 1024 * 1024; // 100 MiB maximum file size
@@ -21,3 +38,29 @@ pub const MAX_BATCH_FILES: usize = 10000; // Maximum number of files in a batch
 pub const MIN_AVAILABLE_MEMORY_MIB: u64 = 512; // Minimum memory to keep available (MiB)
 pub const LARGE_IMAGE_THRESHOLD_MIB: f64 = 50.0; // Images above this size are considered large (MiB)
 pub const MAX_CONCURRENT_LARGE_IMAGES: usize = 2; // Maximum concurrent large image processing
+
+// Incremental batch state, stored in the output directory
+pub const INCREMENTAL_MANIFEST_FILE: &str = ".img-squeeze-manifest.json";
+pub const INCREMENTAL_MANIFEST_VERSION: u32 = 1;
+// Inputs recorded between manifest saves, so an interrupted batch keeps its progress
+pub const INCREMENTAL_SAVE_INTERVAL: usize = 32;
+
+// Default file name template for --renditions
+pub const DEFAULT_RENDITION_TEMPLATE: &str = "{stem}-{width}w.{ext}";
+
+// Static site assets written by batch --asset-manifest / --html-snippets
+pub const ASSET_MANIFEST_FILE: &str = "asset-manifest.json";
+pub const ASSET_MANIFEST_VERSION: u32 = 1;
+pub const DEFAULT_PICTURE_SIZES: &str = "100vw";
+
+// Configuration file, looked up in the current directory, then in the user config directory
+pub const CONFIG_FILE_NAME: &str = "img-squeeze.toml";
+pub const CONFIG_DIR_NAME: &str = "img-squeeze";
+
+// Watch mode: quiet period before a changed file is compressed, and event poll interval
+pub const DEFAULT_WATCH_DEBOUNCE_MS: u64 = 500;
+pub const WATCH_POLL_INTERVAL_MS: u64 = 100;
+
+// Process exit codes for batch runs (1 is left for errors that abort the command)
+pub const EXIT_PARTIAL_FAILURE: u8 = 2; // Some files failed or were cancelled
+pub const EXIT_TOTAL_FAILURE: u8 = 3; // No file could be compressed
@@ -0,0 +1,98 @@
+use crate::error::{CompressionError, Result};
+use crate::upload::UploadManifest;
+use crate::walrus::{download_from_walrus_sync, WalrusOptions};
+use image::ImageFormat;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Outcome of downloading one blob from Walrus
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DownloadReport {
+    pub blob_id: String,
+    /// File the blob was written to
+    pub output: PathBuf,
+    /// Format detected from the blob contents, e.g. `png`
+    pub format: String,
+    pub size: u64,
+    pub sha256: String,
+    /// The contents matched the hash given with `--sha256` or `--manifest`
+    pub verified: bool,
+    /// Aggregator that served the blob
+    pub aggregator_url: String,
+}
+
+/// Handles downloading an image from Walrus decentralized storage
+///
+/// # Arguments
+/// * `blob_id` - Blob ID printed by `upload`
+/// * `output_path` - Output file, or a directory to name the file `<blob_id>.<ext>` in
+/// * `options` - Aggregators and retry policy
+/// * `sha256` - Optional hex SHA-256 the contents must match
+///
+/// # Returns
+/// * `Ok(DownloadReport)` with where the image was written if the download succeeds
+/// * `Err(CompressionError)` if the download or verification fails
+pub fn download_image_from_walrus(
+    blob_id: &str,
+    output_path: &Path,
+    options: WalrusOptions,
+    sha256: Option<String>,
+) -> Result<DownloadReport> {
+    let blob = download_from_walrus_sync(blob_id, output_path, &options, sha256.as_deref())?;
+
+    Ok(DownloadReport {
+        blob_id: blob_id.to_string(),
+        output: blob.path,
+        format: format!("{:?}", blob.format).to_lowercase(),
+        size: blob.size,
+        sha256: blob.sha256,
+        verified: sha256.is_some(),
+        aggregator_url: blob.aggregator_url,
+    })
+}
+
+/// Looks up the SHA-256 a blob was uploaded with in an upload manifest
+///
+/// # Arguments
+/// * `manifest_path` - Manifest written by `upload --manifest`
+/// * `blob_id` - Blob to look up
+///
+/// # Returns
+/// * `Ok(sha256)` - The hex SHA-256 recorded for the blob
+/// * `Err(CompressionError)` - If the manifest cannot be read or does not list the blob
+pub fn manifest_sha256(manifest_path: &Path, blob_id: &str) -> Result<String> {
+    let manifest = UploadManifest::load(manifest_path)?;
+    let entry = manifest.find_blob(blob_id).ok_or_else(|| {
+        CompressionError::Config(format!(
+            "blob {} is not listed in {:?}",
+            blob_id, manifest_path
+        ))
+    })?;
+    Ok(entry.sha256.clone())
+}
+
+/// Prints a download report
+pub fn print_download_report(report: &DownloadReport) {
+    println!("📥 Downloaded from Walrus: {}", report.blob_id);
+    println!("🔗 Aggregator URL: {}", report.aggregator_url);
+    println!("✅ Saved to: {:?}", report.output);
+    println!("🖼️  Format: {}", report.format);
+    println!("📊 File size: {} bytes", report.size);
+    println!("🔒 SHA-256: {}", report.sha256);
+    if report.verified {
+        println!("✔️  Hash verified");
+    }
+
+    // 扩展名与实际格式不一致时提醒
+    let extension_matches = report
+        .output
+        .extension()
+        .and_then(ImageFormat::from_extension)
+        .is_some_and(|format| format!("{:?}", format).to_lowercase() == report.format);
+    if !extension_matches {
+        println!(
+            "⚠️  The file extension does not match its {} contents",
+            report.format
+        );
+    }
+}
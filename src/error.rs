@@ -1,3 +1,4 @@
+use serde::Serialize;
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -12,12 +13,30 @@ pub enum CompressionError {
     #[error("PNG optimization error: {0}")]
     PngOptimization(String),
 
+    #[error("JPEG encoding error: {0}")]
+    JpegEncoding(String),
+
+    #[error("WebP encoding error: {0}")]
+    WebPEncoding(String),
+
+    #[error("JSON serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Metadata error: {0}")]
+    Metadata(String),
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+
     #[error("Invalid quality value: {0}. Must be between 1 and 100")]
     InvalidQuality(u8),
 
     #[error("Invalid image dimensions: {0}x{1}. Maximum allowed: {2}x{2}")]
     InvalidDimensions(u32, u32, u32),
 
+    #[error("Image dimensions differ: {0}x{1} vs {2}x{3}")]
+    DimensionMismatch(u32, u32, u32, u32),
+
     #[error("File too large: {0} bytes. Maximum allowed: {1} bytes")]
     FileTooLarge(u64, u64),
 
@@ -27,6 +46,9 @@ pub enum CompressionError {
     #[error("File not found: {0}")]
     FileNotFound(PathBuf),
 
+    #[error("Output path {0} is already claimed by {1}")]
+    OutputCollision(PathBuf, PathBuf),
+
     #[error("Failed to create output directory: {0}")]
     DirectoryCreationFailed(PathBuf),
 
@@ -36,8 +58,28 @@ pub enum CompressionError {
     #[error("Walkdir error: {0}")]
     WalkdirError(#[from] walkdir::Error),
 
-    #[error("Walrus upload error: {0}")]
-    WalrusUpload(String),
+    #[error("File watcher error: {0}")]
+    Watch(String),
+
+    #[error("Network error talking to Walrus at {0}: {1}")]
+    WalrusNetwork(String, String),
+
+    #[error("Walrus at {0} answered HTTP {1}")]
+    WalrusHttpStatus(String, u16),
+
+    #[error(
+        "Walrus publisher {0} refused the blob (HTTP {1}): storage quota or size limit exceeded"
+    )]
+    WalrusQuota(String, u16),
+
+    #[error("Unexpected response from Walrus at {0}: {1}")]
+    WalrusBadResponse(String, String),
+
+    #[error("Walrus download error: {0}")]
+    WalrusDownload(String),
+
+    #[error("Content hash mismatch: expected {0}, got {1}")]
+    HashMismatch(String, String),
 
     #[error("Batch memory limit exceeded: estimated {0}MiB, maximum allowed {1}MiB")]
     BatchMemoryLimitExceeded(u64, u64),
@@ -51,4 +93,56 @@ pub enum CompressionError {
     InsufficientMemory(u64, u64),
 }
 
+impl CompressionError {
+    /// Stable, machine-readable name of the error variant, e.g. `file_not_found`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CompressionError::Io(..) => "io",
+            CompressionError::ImageProcessing(..) => "image_processing",
+            CompressionError::PngOptimization(..) => "png_optimization",
+            CompressionError::JpegEncoding(..) => "jpeg_encoding",
+            CompressionError::WebPEncoding(..) => "web_p_encoding",
+            CompressionError::Json(..) => "json",
+            CompressionError::Metadata(..) => "metadata",
+            CompressionError::Config(..) => "config",
+            CompressionError::InvalidQuality(..) => "invalid_quality",
+            CompressionError::InvalidDimensions(..) => "invalid_dimensions",
+            CompressionError::DimensionMismatch(..) => "dimension_mismatch",
+            CompressionError::FileTooLarge(..) => "file_too_large",
+            CompressionError::UnsupportedFormat(..) => "unsupported_format",
+            CompressionError::FileNotFound(..) => "file_not_found",
+            CompressionError::OutputCollision(..) => "output_collision",
+            CompressionError::DirectoryCreationFailed(..) => "directory_creation_failed",
+            CompressionError::NoImageFilesFound(..) => "no_image_files_found",
+            CompressionError::WalkdirError(..) => "walkdir_error",
+            CompressionError::Watch(..) => "watch",
+            CompressionError::WalrusNetwork(..) => "walrus_network",
+            CompressionError::WalrusHttpStatus(..) => "walrus_http_status",
+            CompressionError::WalrusQuota(..) => "walrus_quota",
+            CompressionError::WalrusBadResponse(..) => "walrus_bad_response",
+            CompressionError::WalrusDownload(..) => "walrus_download",
+            CompressionError::HashMismatch(..) => "hash_mismatch",
+            CompressionError::BatchMemoryLimitExceeded(..) => "batch_memory_limit_exceeded",
+            CompressionError::BatchFileLimitExceeded(..) => "batch_file_limit_exceeded",
+            CompressionError::InsufficientMemory(..) => "insufficient_memory",
+        }
+    }
+}
+
+/// A fatal error as reported with `--output-format json`
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub error: String,
+    pub kind: &'static str,
+}
+
+impl From<&CompressionError> for ErrorReport {
+    fn from(error: &CompressionError) -> Self {
+        Self {
+            error: error.to_string(),
+            kind: error.kind(),
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, CompressionError>;
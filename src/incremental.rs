@@ -0,0 +1,400 @@
+use crate::constants::{INCREMENTAL_MANIFEST_FILE, INCREMENTAL_MANIFEST_VERSION};
+use crate::error::Result;
+use crate::processing::CompressionOptions;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// What an incremental batch remembers about one compressed input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Input size in bytes
+    pub size: u64,
+    /// Input modification time in nanoseconds since the Unix epoch
+    pub modified_ns: u64,
+    /// Hex SHA-256 of the input contents
+    pub sha256: String,
+    pub output: PathBuf,
+    /// Size of the output when it was written, to notice outputs replaced by hand
+    pub output_size: u64,
+    /// `CompressionOptions` the output was produced with
+    pub options: serde_json::Value,
+}
+
+/// Outcome of `IncrementalManifest::check`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Freshness {
+    /// The output is up to date; the entry carries the input's current modification time
+    UpToDate(ManifestEntry),
+    /// The input must be compressed
+    Stale(InputState),
+}
+
+/// Size and modification time of an input as seen by `IncrementalManifest::check`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputState {
+    pub size: u64,
+    pub modified_ns: u64,
+    /// Hex SHA-256 of the contents, if they had to be read
+    pub sha256: Option<String>,
+}
+
+/// Per-output-directory record of which inputs are already compressed.
+///
+/// Stored as `INCREMENTAL_MANIFEST_FILE` inside the batch output directory and
+/// keyed by canonical input path.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncrementalManifest {
+    version: u32,
+    entries: BTreeMap<PathBuf, ManifestEntry>,
+}
+
+impl Default for IncrementalManifest {
+    fn default() -> Self {
+        Self {
+            version: INCREMENTAL_MANIFEST_VERSION,
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl IncrementalManifest {
+    /// Path of the manifest for a batch output directory
+    pub fn path(output_dir: &Path) -> PathBuf {
+        output_dir.join(INCREMENTAL_MANIFEST_FILE)
+    }
+
+    /// Loads the manifest of `output_dir`.
+    ///
+    /// A missing manifest, or one written by an incompatible version, yields an
+    /// empty manifest so that every input is recompressed.
+    ///
+    /// # Returns
+    /// * `Ok(manifest)` - The recorded entries, possibly none
+    /// * `Err(CompressionError)` - If the manifest exists but cannot be read
+    pub fn load(output_dir: &Path) -> Result<Self> {
+        let path = Self::path(output_dir);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        match serde_json::from_slice::<Self>(&data) {
+            Ok(manifest) if manifest.version == INCREMENTAL_MANIFEST_VERSION => Ok(manifest),
+            _ => {
+                eprintln!(
+                    "⚠️  Ignoring unreadable incremental manifest {:?}, recompressing everything",
+                    path
+                );
+                Ok(Self::default())
+            }
+        }
+    }
+
+    /// Writes the manifest into `output_dir`, replacing the previous one atomically.
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        let path = Self::path(output_dir);
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+
+    /// Checks whether the recorded output of `input` is still up to date.
+    ///
+    /// The output must exist with its recorded size and have been produced at
+    /// `output` with the same `options`. The input matches when its size and
+    /// modification time are unchanged; if only the time differs, its contents
+    /// are hashed and compared instead, and the returned entry carries the new time.
+    ///
+    /// # Returns
+    /// * `Ok(Freshness::UpToDate(entry))` - `input` can be skipped
+    /// * `Ok(Freshness::Stale(state))` - `input` must be compressed; pass `state`
+    ///   to `ManifestEntry::capture_with` so a hash computed here is not recomputed
+    /// * `Err(CompressionError)` - If `input` cannot be read
+    pub fn check(
+        &self,
+        input: &Path,
+        output: &Path,
+        options: &serde_json::Value,
+    ) -> Result<Freshness> {
+        let (size, modified_ns) = size_and_mtime(input)?;
+        let mut state = InputState {
+            size,
+            modified_ns,
+            sha256: None,
+        };
+        let Some(entry) = self.entries.get(input) else {
+            return Ok(Freshness::Stale(state));
+        };
+        if entry.output != output || &entry.options != options || size != entry.size {
+            return Ok(Freshness::Stale(state));
+        }
+        match fs::metadata(output) {
+            Ok(metadata) if metadata.len() == entry.output_size => {}
+            _ => return Ok(Freshness::Stale(state)),
+        }
+
+        if modified_ns == entry.modified_ns {
+            return Ok(Freshness::UpToDate(entry.clone()));
+        }
+        let sha256 = hash_file(input)?;
+        if sha256 == entry.sha256 {
+            return Ok(Freshness::UpToDate(ManifestEntry {
+                modified_ns,
+                ..entry.clone()
+            }));
+        }
+        state.sha256 = Some(sha256);
+        Ok(Freshness::Stale(state))
+    }
+
+    /// Records or replaces the entry for `input`
+    pub fn insert(&mut self, input: PathBuf, entry: ManifestEntry) {
+        self.entries.insert(input, entry);
+    }
+
+    /// Forgets `input`, e.g. after it failed to compress
+    pub fn remove(&mut self, input: &Path) {
+        self.entries.remove(input);
+    }
+
+    /// Drops the entries of inputs that no longer exist.
+    ///
+    /// # Returns
+    /// * The number of entries dropped
+    pub fn prune_missing(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|input, _| input.exists());
+        before - self.entries.len()
+    }
+}
+
+impl ManifestEntry {
+    /// Captures the current state of `input` before it is compressed to `output`.
+    ///
+    /// The hash in `known` (as returned by `IncrementalManifest::check`) is
+    /// reused while the input's size and modification time still match it.
+    /// `output_size` is filled in by the caller once the output is written.
+    pub fn capture_with(
+        input: &Path,
+        output: &Path,
+        options: &serde_json::Value,
+        known: Option<InputState>,
+    ) -> Result<Self> {
+        let (size, modified_ns) = size_and_mtime(input)?;
+        let sha256 = match known {
+            Some(InputState {
+                size: known_size,
+                modified_ns: known_modified_ns,
+                sha256: Some(sha256),
+            }) if known_size == size && known_modified_ns == modified_ns => sha256,
+            _ => hash_file(input)?,
+        };
+        Ok(Self {
+            size,
+            modified_ns,
+            sha256,
+            output: output.to_path_buf(),
+            output_size: 0,
+            options: options.clone(),
+        })
+    }
+}
+
+/// Serializes compression options into the form stored in manifest entries.
+pub fn options_fingerprint(options: &CompressionOptions) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(options)?)
+}
+
+fn size_and_mtime(path: &Path) -> Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let modified_ns = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|age| age.as_nanos() as u64)
+        .unwrap_or(0);
+    Ok((metadata.len(), modified_ns))
+}
+
+/// Hex SHA-256 of a file's contents
+pub(crate) fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Hex SHA-256 of an in-memory buffer
+#[cfg(test)]
+pub(crate) fn hash_bytes(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+/// Lower-case hex encoding of a digest
+pub(crate) fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, PathBuf, PathBuf, serde_json::Value) {
+        let dir = TempDir::new().unwrap();
+        let input = dir.path().join("in.png");
+        let output = dir.path().join("out.png");
+        fs::write(&input, b"source bytes").unwrap();
+        fs::write(&output, b"compressed").unwrap();
+        let options = serde_json::json!({ "quality": 80 });
+        (dir, input, output, options)
+    }
+
+    fn record(
+        manifest: &mut IncrementalManifest,
+        input: &Path,
+        output: &Path,
+        options: &serde_json::Value,
+    ) {
+        let mut entry = ManifestEntry::capture_with(input, output, options, None).unwrap();
+        entry.output_size = fs::metadata(output).unwrap().len();
+        manifest.insert(input.to_path_buf(), entry);
+    }
+
+    #[test]
+    fn test_unchanged_input_is_up_to_date() {
+        let (dir, input, output, options) = setup();
+        let mut manifest = IncrementalManifest::default();
+        record(&mut manifest, &input, &output, &options);
+
+        manifest.save(dir.path()).unwrap();
+        let manifest = IncrementalManifest::load(dir.path()).unwrap();
+        assert!(matches!(
+            manifest.check(&input, &output, &options).unwrap(),
+            Freshness::UpToDate(_)
+        ));
+    }
+
+    #[test]
+    fn test_changes_invalidate_entry() {
+        let (_dir, input, output, options) = setup();
+        let mut manifest = IncrementalManifest::default();
+        record(&mut manifest, &input, &output, &options);
+
+        let other_options = serde_json::json!({ "quality": 60 });
+        assert!(matches!(
+            manifest.check(&input, &output, &other_options).unwrap(),
+            Freshness::Stale(_)
+        ));
+
+        fs::write(&output, b"edited by hand").unwrap();
+        assert!(matches!(
+            manifest.check(&input, &output, &options).unwrap(),
+            Freshness::Stale(_)
+        ));
+        record(&mut manifest, &input, &output, &options);
+
+        fs::write(&input, b"SOURCE BYTES").unwrap();
+        File::options()
+            .write(true)
+            .open(&input)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        assert!(matches!(
+            manifest.check(&input, &output, &options).unwrap(),
+            Freshness::Stale(_)
+        ));
+    }
+
+    #[test]
+    fn test_touched_input_matches_by_hash() {
+        let (_dir, input, output, options) = setup();
+        let mut manifest = IncrementalManifest::default();
+        record(&mut manifest, &input, &output, &options);
+
+        let later = SystemTime::now() + Duration::from_secs(5);
+        File::options()
+            .write(true)
+            .open(&input)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        let Freshness::UpToDate(entry) = manifest.check(&input, &output, &options).unwrap() else {
+            panic!("touched input reported stale");
+        };
+        assert_eq!(
+            entry.modified_ns,
+            later.duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64
+        );
+    }
+
+    #[test]
+    fn test_check_hands_its_hash_to_capture() {
+        let (_dir, input, output, options) = setup();
+        let mut manifest = IncrementalManifest::default();
+        record(&mut manifest, &input, &output, &options);
+
+        // Same size, new contents and time: hashed once by `check`
+        fs::write(&input, b"SOURCE BYTES").unwrap();
+        File::options()
+            .write(true)
+            .open(&input)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        let Freshness::Stale(state) = manifest.check(&input, &output, &options).unwrap() else {
+            panic!("changed input reported up to date");
+        };
+        assert_eq!(
+            state.sha256.as_deref(),
+            Some(hash_file(&input).unwrap().as_str())
+        );
+
+        // A hash for the current size and time is reused, a stale one is not
+        let known = InputState {
+            sha256: Some("reused".to_string()),
+            ..state.clone()
+        };
+        let entry = ManifestEntry::capture_with(&input, &output, &options, Some(known)).unwrap();
+        assert_eq!(entry.sha256, "reused");
+
+        let outdated = InputState {
+            modified_ns: state.modified_ns - 1,
+            sha256: Some("reused".to_string()),
+            ..state
+        };
+        let entry = ManifestEntry::capture_with(&input, &output, &options, Some(outdated)).unwrap();
+        assert_eq!(entry.sha256, hash_file(&input).unwrap());
+    }
+
+    #[test]
+    fn test_prune_missing_drops_deleted_inputs() {
+        let (_dir, input, output, options) = setup();
+        let mut manifest = IncrementalManifest::default();
+        record(&mut manifest, &input, &output, &options);
+        assert_eq!(manifest.prune_missing(), 0);
+
+        fs::remove_file(&input).unwrap();
+        assert_eq!(manifest.prune_missing(), 1);
+        assert_eq!(manifest, IncrementalManifest::default());
+    }
+
+    #[test]
+    fn test_corrupt_manifest_starts_fresh() {
+        let dir = TempDir::new().unwrap();
+        fs::write(IncrementalManifest::path(dir.path()), b"{ not json").unwrap();
+        assert_eq!(
+            IncrementalManifest::load(dir.path()).unwrap(),
+            IncrementalManifest::default()
+        );
+    }
+}
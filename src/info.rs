@@ -1,31 +1,138 @@
 use crate::error::Result;
 use crate::processing::validate_file_exists;
 use image::{DynamicImage, GenericImageView, ImageReader};
+use serde::Serialize;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Properties of an image file and compression suggestions for it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageInfo {
+    pub path: PathBuf,
+    /// Container detected from the file name, e.g. `jpeg`
+    pub format: Option<String>,
+    pub dimensions: (u32, u32),
+    pub file_size: u64,
+    pub color_type: String,
+    pub total_pixels: u64,
+    pub megapixels: f64,
+    pub aspect_ratio: f64,
+    pub dpi: Option<u32>,
+    /// Estimated decoded size in MB
+    pub estimated_memory_mb: f64,
+    /// Last modification time in seconds since the Unix epoch
+    pub modified: Option<u64>,
+    pub readonly: bool,
+    pub suggestions: Vec<String>,
+}
 
-pub fn get_image_info(input_path: &Path) -> Result<()> {
+/// Reads an image file and analyzes it
+///
+/// # Arguments
+/// * `input_path` - Path to the image file to analyze
+///
+/// # Returns
+/// * `Ok(ImageInfo)` - The image properties
+/// * `Err(CompressionError)` - If the file cannot be read or decoded
+pub fn get_image_info(input_path: &Path) -> Result<ImageInfo> {
     validate_file_exists(input_path)?;
 
-    println!("📊 Analyzing image: {:?}", input_path);
-
     // 读取图片信息
-    let img = ImageReader::open(input_path)?.decode()?;
+    let reader = ImageReader::open(input_path)?;
+    let format = reader.format();
+    let img = reader.decode()?;
     let metadata = fs::metadata(input_path)?;
 
+    let (width, height) = img.dimensions();
+    let total_pixels = width as u64 * height as u64;
+
+    Ok(ImageInfo {
+        path: input_path.to_path_buf(),
+        format: format.map(|f| format!("{:?}", f).to_lowercase()),
+        dimensions: (width, height),
+        file_size: metadata.len(),
+        color_type: format!("{:?}", img.color()),
+        total_pixels,
+        megapixels: total_pixels as f64 / 1_000_000.0,
+        aspect_ratio: width as f64 / height as f64,
+        dpi: get_dpi_info(&img),
+        estimated_memory_mb: estimate_memory_usage(&img),
+        modified: metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|age| age.as_secs()),
+        readonly: metadata.permissions().readonly(),
+        suggestions: compression_suggestions(metadata.len(), (width, height), format),
+    })
+}
+
+/// 根据文件大小、尺寸和格式给出压缩建议
+fn compression_suggestions(
+    file_size: u64,
+    (width, height): (u32, u32),
+    format: Option<image::ImageFormat>,
+) -> Vec<String> {
+    let mut suggestions = Vec::new();
+
+    if file_size > 5 * 1024 * 1024 {
+        suggestions.push("Large file (>5MB): Consider high compression (quality 60-80)".into());
+    } else if file_size > 1024 * 1024 {
+        suggestions.push("Medium file (1-5MB): Consider medium compression (quality 70-85)".into());
+    } else {
+        suggestions.push("Small file (<1MB): Consider light compression (quality 85-95)".into());
+    }
+
+    // 根据图片尺寸提供建议
+    if width > 1920 || height > 1080 {
+        suggestions.push("Large dimensions: Consider resizing to 1920x1080 or smaller".into());
+    } else if width > 1280 || height > 720 {
+        suggestions.push("HD dimensions: Consider resizing to 1280x720 for web use".into());
+    }
+
+    // 根据图片格式提供建议
+    if let Some(format) = format {
+        suggestions.push(
+            match format {
+                image::ImageFormat::Png => {
+                    "PNG format: Use oxipng optimization for better compression"
+                }
+                image::ImageFormat::Jpeg => {
+                    "JPEG format: Adjust quality setting for size/quality balance"
+                }
+                image::ImageFormat::WebP => {
+                    "WebP format: Already well compressed, consider quality adjustment"
+                }
+                _ => "Other format: Consider converting to JPEG/WebP for better compression",
+            }
+            .into(),
+        );
+    }
+
+    suggestions
+}
+
+/// Prints the basic image information and compression suggestions
+pub fn print_image_info(info: &ImageInfo) {
+    println!("📊 Analyzing image: {:?}", info.path);
+
     // 基本信息
     println!("📋 Basic Information:");
-    println!("  📁 File: {:?}", input_path);
-    println!("  📏 Dimensions: {}x{} pixels", img.width(), img.height());
-    println!("  📦 File size: {} bytes", metadata.len());
-    println!("  🎨 Color type: {:?}", img.color());
+    println!("  📁 File: {:?}", info.path);
     println!(
-        "  🎭 Image format: {:?}",
-        ImageReader::open(input_path)?.format()
+        "  📏 Dimensions: {}x{} pixels",
+        info.dimensions.0, info.dimensions.1
+    );
+    println!("  📦 File size: {} bytes", info.file_size);
+    println!("  🎨 Color type: {}", info.color_type);
+    println!(
+        "  🎭 Image format: {}",
+        info.format.as_deref().unwrap_or("unknown")
     );
 
     // 计算文件大小信息
-    let size_kb = metadata.len() as f64 / 1024.0;
+    let size_kb = info.file_size as f64 / 1024.0;
     let size_mb = size_kb / 1024.0;
 
     if size_mb >= 1.0 {
@@ -34,114 +141,63 @@ pub fn get_image_info(input_path: &Path) -> Result<()> {
         println!("  📊 Size: {:.2} KB", size_kb);
     }
 
-    // 像素总数
-    let total_pixels = img.width() * img.height();
-    println!("  🔢 Total pixels: {}", total_pixels);
-
-    // 宽高比
-    let aspect_ratio = img.width() as f64 / img.height() as f64;
-    println!("  📐 Aspect ratio: {:.2}:1", aspect_ratio);
+    println!("  🔢 Total pixels: {}", info.total_pixels);
+    println!("  📐 Aspect ratio: {:.2}:1", info.aspect_ratio);
 
     // 压缩建议
     println!("\n💡 Compression Suggestions:");
-
-    if metadata.len() > 5 * 1024 * 1024 {
-        println!("  🎯 Large file (>5MB): Consider high compression (quality 60-80)");
-    } else if metadata.len() > 1024 * 1024 {
-        println!("  🎯 Medium file (1-5MB): Consider medium compression (quality 70-85)");
-    } else {
-        println!("  🎯 Small file (<1MB): Consider light compression (quality 85-95)");
+    for suggestion in &info.suggestions {
+        println!("  🎯 {}", suggestion);
     }
-
-    // 根据图片尺寸提供建议
-    if img.width() > 1920 || img.height() > 1080 {
-        println!("  📏 Large dimensions: Consider resizing to 1920x1080 or smaller");
-    } else if img.width() > 1280 || img.height() > 720 {
-        println!("  📏 HD dimensions: Consider resizing to 1280x720 for web use");
-    }
-
-    // 根据图片格式提供建议
-    if let Some(format) = ImageReader::open(input_path)?.format() {
-        match format {
-            image::ImageFormat::Png => {
-                println!("  🎭 PNG format: Use oxipng optimization for better compression");
-            }
-            image::ImageFormat::Jpeg => {
-                println!("  🎭 JPEG format: Adjust quality setting for size/quality balance");
-            }
-            image::ImageFormat::WebP => {
-                println!("  🎭 WebP format: Already well compressed, consider quality adjustment");
-            }
-            _ => {
-                println!(
-                    "  🎭 Other format: Consider converting to JPEG/WebP for better compression"
-                );
-            }
-        }
-    }
-
-    Ok(())
 }
 
-pub fn print_detailed_info(input_path: &Path) -> Result<()> {
-    validate_file_exists(input_path)?;
-
-    let img = ImageReader::open(input_path)?.decode()?;
-    let metadata = fs::metadata(input_path)?;
-
+/// Prints the detailed analysis section
+pub fn print_detailed_info(info: &ImageInfo) {
     println!("🔍 Detailed Image Analysis:");
-    println!("═");
-    for _ in 0..60 {
-        print!("═");
-    }
-    println!();
+    println!("{}", "═".repeat(60));
 
     // 文件信息
     println!("📁 File Information:");
-    println!("  Path: {:?}", input_path);
+    println!("  Path: {:?}", info.path);
     println!(
         "  Size: {} bytes ({:.2} KB)",
-        metadata.len(),
-        metadata.len() as f64 / 1024.0
+        info.file_size,
+        info.file_size as f64 / 1024.0
     );
-    println!("  Modified: {:?}", metadata.modified());
-    println!("  Permissions: {:?}", metadata.permissions());
+    if let Some(modified) = info.modified {
+        println!("  Modified: {} (Unix time)", modified);
+    }
+    println!("  Read-only: {}", info.readonly);
 
     // 图片信息
     println!("\n🎨 Image Properties:");
-    println!("  Dimensions: {}x{} pixels", img.width(), img.height());
-    println!("  Color type: {:?}", img.color());
     println!(
-        "  Image format: {:?}",
-        ImageReader::open(input_path)?.format()
+        "  Dimensions: {}x{} pixels",
+        info.dimensions.0, info.dimensions.1
+    );
+    println!("  Color type: {}", info.color_type);
+    println!(
+        "  Image format: {}",
+        info.format.as_deref().unwrap_or("unknown")
     );
-
-    // 计算信息
-    let total_pixels = img.width() * img.height();
-    let aspect_ratio = img.width() as f64 / img.height() as f64;
-    let megapixels = total_pixels as f64 / 1_000_000.0;
 
     println!("\n📊 Calculated Metrics:");
-    println!("  Total pixels: {}", total_pixels);
-    println!("  Megapixels: {:.2} MP", megapixels);
-    println!("  Aspect ratio: {:.2}:1", aspect_ratio);
+    println!("  Total pixels: {}", info.total_pixels);
+    println!("  Megapixels: {:.2} MP", info.megapixels);
+    println!("  Aspect ratio: {:.2}:1", info.aspect_ratio);
 
     // 像素密度信息（如果可能）
-    if let Some(dpi) = get_dpi_info(&img) {
+    if let Some(dpi) = info.dpi {
         println!("  DPI: {}", dpi);
     }
 
     // 内存使用估算
-    let estimated_memory = estimate_memory_usage(&img);
-    println!("  Estimated memory usage: {:.2} MB", estimated_memory);
-
-    println!("═");
-    for _ in 0..60 {
-        print!("═");
-    }
-    println!();
+    println!(
+        "  Estimated memory usage: {:.2} MB",
+        info.estimated_memory_mb
+    );
 
-    Ok(())
+    println!("{}", "═".repeat(60));
 }
 
 fn get_dpi_info(_img: &DynamicImage) -> Option<u32> {
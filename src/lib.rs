@@ -1,17 +1,64 @@
+pub mod assets;
 pub mod batch;
 pub mod cli;
+pub mod compare;
+pub mod config;
 pub mod constants;
+pub mod download;
 pub mod error;
+pub mod incremental;
 pub mod info;
+pub mod metadata;
 pub mod processing;
+pub mod quality;
+pub mod renditions;
 pub mod upload;
 pub mod walrus;
+pub mod watch;
 
-pub use batch::{batch_compress_images, collect_image_files, generate_output_path, is_image_file};
-pub use error::{CompressionError, Result};
-pub use info::{get_image_info, print_detailed_info};
+pub use assets::{
+    picture_html, write_assets, AssetManifest, AssetOptions, AssetOutput, SourceAssets,
+};
+pub use batch::{
+    batch_compress_images, collect_image_files, generate_output_path, is_image_file, plan_batch,
+    plan_output_paths, print_batch_plan, print_batch_report, BatchFailure, BatchOptions,
+    BatchOutcome, BatchPlan, BatchReport, PlannedFile,
+};
+pub use compare::{compare_images, print_comparison, ComparisonReport};
+pub use config::{
+    print_effective_config, Config, ConfigFile, EffectiveConfig, Settings, WalrusSettings,
+};
+pub use download::{
+    download_image_from_walrus, manifest_sha256, print_download_report, DownloadReport,
+};
+pub use error::{CompressionError, ErrorReport, Result};
+pub use incremental::{IncrementalManifest, ManifestEntry};
+pub use info::{get_image_info, print_detailed_info, print_image_info, ImageInfo};
+pub use metadata::{ImageMetadata, MetadataPolicy};
 pub use processing::{
-    compress_image, determine_output_format, load_image_with_metadata, process_and_save_image,
-    process_image_pipeline, resize_image, validate_file_exists, CompressionOptions,
+    calculate_resize_dimensions, compress_bytes, compress_image, determine_output_format,
+    encode_for_output, encode_image, encode_jpeg, encode_to_target_size, encode_to_target_ssim,
+    encode_webp, is_stdio, load_image_from_bytes, load_image_with_metadata,
+    load_image_with_options, print_compression_report, process_and_save_image,
+    process_image_pipeline, resize_image, resized_image, save_image, validate_file_exists,
+    ChromaSubsampling, CompressedImage, CompressionOptions, CompressionReport, EncodeReport,
+    ResizeFilter, ResizeMode,
+};
+pub use quality::{diff_heatmap, dssim_to_ssim, max_pixel_delta, psnr, ssim};
+pub use renditions::{
+    print_rendition_report, process_renditions, PlannedRendition, RenditionReport, RenditionSpec,
+    Renditions,
+};
+pub use upload::{
+    compress_and_upload_image, print_batch_upload_report, print_upload_report,
+    upload_image_to_walrus, upload_images_to_walrus, BatchUploadOptions, BatchUploadReport,
+    UploadManifest, UploadManifestEntry, UploadReport, UploadTarget, UploadedBlob,
+};
+pub use walrus::{
+    download_from_walrus_async, download_from_walrus_sync, upload_bytes_to_walrus_async,
+    upload_bytes_to_walrus_sync, upload_files_to_walrus_async, upload_files_to_walrus_sync,
+    upload_to_walrus_async, upload_to_walrus_async_with_progress, upload_to_walrus_sync,
+    upload_to_walrus_sync_with_progress, DownloadedBlob, RetryPolicy, StoredBlob, WalrusOptions,
+    WalrusUploader,
 };
-pub use walrus::{upload_to_walrus_async, upload_to_walrus_sync, WalrusOptions};
+pub use watch::{print_watch_report, watch_directory, WatchOptions, WatchReport};
@@ -1,68 +1,254 @@
-mod batch;
-mod cli;
-mod constants;
-mod error;
-mod info;
-mod processing;
-mod upload;
-mod walrus;
-
-use batch::batch_compress_images;
 use clap::Parser;
-use cli::{Args, Commands};
-use error::Result;
-use info::{get_image_info, print_detailed_info};
-use processing::{compress_image, CompressionOptions};
+use img_squeeze::batch::{
+    batch_compress_images, plan_batch, print_batch_plan, print_batch_report, BatchOptions,
+};
+use img_squeeze::cli::{Args, Commands, ConfigCommand, OutputFormat};
+use img_squeeze::compare::{compare_images, print_comparison};
+use img_squeeze::config::{print_effective_config, Config, Settings, WalrusSettings};
+use img_squeeze::download::{download_image_from_walrus, manifest_sha256, print_download_report};
+use img_squeeze::error::{CompressionError, ErrorReport, Result};
+use img_squeeze::info::{get_image_info, print_detailed_info, print_image_info};
+use img_squeeze::processing::{compress_image, is_stdio, print_compression_report};
+use img_squeeze::renditions::{print_rendition_report, process_renditions};
+use img_squeeze::upload::{
+    compress_and_upload_image, print_batch_upload_report, print_upload_report,
+    upload_image_to_walrus, upload_images_to_walrus, BatchUploadOptions,
+};
+use img_squeeze::watch::{print_watch_report, watch_directory, WatchOptions};
 use rayon::ThreadPoolBuilder;
+use serde::Serialize;
 use std::path::Path;
-use upload::upload_image_to_walrus;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let args = Args::parse();
+    let format = args.output_format;
+    match run(args) {
+        Ok(code) => code,
+        Err(error) => {
+            // JSON 模式下错误也输出为 JSON 文档，便于脚本解析
+            match format {
+                OutputFormat::Text => eprintln!("Error: {:?}", error),
+                OutputFormat::Json => {
+                    if let Err(e) = emit_report(format, &ErrorReport::from(&error), |_| {}) {
+                        eprintln!("Error: {:?}", e);
+                    }
+                }
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the parsed command
+///
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+///
+/// # Returns
+/// * `Ok(ExitCode)` - The exit code of the command, non-zero when a batch partly fails
+/// * `Err(CompressionError)` - A fatal error, reported by `main`
+fn run(args: Args) -> Result<ExitCode> {
+    let format = args.output_format;
+    let config_path = args.config;
+    let preset = args.preset;
 
     match args.command {
         Commands::Compress {
             input,
             output,
-            quality,
-            width,
-            height,
-            format,
+            options,
+            renditions,
+            upload,
             threads,
         } => {
             setup_thread_pool(threads);
-            let options = CompressionOptions::new(quality, width, height, format)?;
-            compress_image(input, output, options)?;
+            let settings = load_settings(config_path.as_deref(), preset.as_deref())?;
+            let upload = upload.to_upload_target(
+                settings
+                    .walrus
+                    .clone()
+                    .overridden_by(upload.walrus.to_settings()),
+            );
+            let options = settings
+                .overridden_by(options.to_settings())
+                .to_compression_options()?;
+            if let Some(renditions) = renditions.to_renditions()? {
+                if is_stdio(&input) || is_stdio(&output) {
+                    return Err(CompressionError::Config(
+                        "--renditions reads and writes files; '-' is not supported".to_string(),
+                    ));
+                }
+                let report = process_renditions(&input, &output, &options, &renditions)?;
+                emit_report(format, &report, print_rendition_report)?;
+            } else {
+                let report = match &upload {
+                    Some(target) => compress_and_upload_image(&input, &output, &options, target)?,
+                    None => compress_image(input, output.clone(), options)?,
+                };
+                if is_stdio(&output) {
+                    // stdout carries the image, so only a summary goes to stderr
+                    eprintln!(
+                        "✅ Compressed {} -> {} bytes ({:.1}%)",
+                        report.original_size,
+                        report.encode.compressed_size,
+                        report.compression_ratio
+                    );
+                    if let Some(blob) = &report.blob {
+                        eprintln!("🆔 Blob ID: {}", blob.blob_id);
+                    }
+                } else {
+                    emit_report(format, &report, print_compression_report)?;
+                }
+            }
         }
         Commands::Batch {
             input,
             output,
-            quality,
-            width,
-            height,
-            format,
+            options,
+            renditions,
+            assets,
+            upload,
             threads,
             recursive,
+            fail_fast,
+            incremental,
+            dry_run,
         } => {
             setup_thread_pool(threads);
-            let options = CompressionOptions::new(quality, width, height, format)?;
-            batch_compress_images(input, output, options, recursive)?;
+            let settings = load_settings(config_path.as_deref(), preset.as_deref())?;
+            let upload = upload.to_upload_target(
+                settings
+                    .walrus
+                    .clone()
+                    .overridden_by(upload.walrus.to_settings()),
+            );
+            let options = settings
+                .overridden_by(options.to_settings())
+                .to_compression_options()?;
+            let batch_options = BatchOptions {
+                recursive,
+                fail_fast,
+                incremental,
+                renditions: renditions.to_renditions()?,
+                assets: assets.to_asset_options(),
+                upload,
+            };
+            if dry_run {
+                let plan = plan_batch(&input, &output, &options, &batch_options)?;
+                emit_report(format, &plan, print_batch_plan)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+            let report = batch_compress_images(input, output, options, batch_options)?;
+            emit_report(format, &report, print_batch_report)?;
+            return Ok(ExitCode::from(report.outcome.exit_code()));
+        }
+        Commands::Watch {
+            input,
+            output,
+            options,
+            recursive,
+            debounce_ms,
+        } => {
+            let options = load_settings(config_path.as_deref(), preset.as_deref())?
+                .overridden_by(options.to_settings())
+                .to_compression_options()?;
+            let watch_options = WatchOptions {
+                recursive,
+                debounce: Duration::from_millis(debounce_ms),
+            };
+
+            // Ctrl+C / SIGTERM end the session after the current file
+            let stop = Arc::new(AtomicBool::new(false));
+            let handler_stop = Arc::clone(&stop);
+            ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst))
+                .map_err(|e| CompressionError::Watch(e.to_string()))?;
+
+            let report = watch_directory(&input, &output, &options, watch_options, &stop)?;
+            emit_report(format, &report, print_watch_report)?;
         }
         Commands::Upload {
             input,
-            aggregator_url,
-            publisher_url,
-            epochs,
+            walrus,
             temp,
+            recursive,
+            concurrency,
+            manifest,
+        } => {
+            let walrus = load_settings(config_path.as_deref(), preset.as_deref())?
+                .walrus
+                .overridden_by(walrus.to_settings());
+            walrus.validate()?;
+
+            // 单个文件且不需要清单时保持原来的输出
+            if Path::new(&input).is_file() && manifest.is_none() {
+                let report =
+                    upload_image_to_walrus(Path::new(&input), walrus.to_walrus_options(), temp)?;
+                emit_report(format, &report, print_upload_report)?;
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let batch_options = BatchUploadOptions {
+                recursive,
+                concurrency,
+                manifest,
+            };
+            let report =
+                upload_images_to_walrus(&input, walrus.to_walrus_options(), temp, &batch_options)?;
+            emit_report(format, &report, print_batch_upload_report)?;
+            return Ok(ExitCode::from(report.outcome.exit_code()));
+        }
+        Commands::Download {
+            blob_id,
+            output,
+            aggregator_url,
+            fallback_aggregators,
+            timeout,
+            retries,
+            sha256,
+            manifest,
         } => {
-            upload_image_to_walrus(&input, aggregator_url, publisher_url, epochs, temp)?;
+            let walrus = load_settings(config_path.as_deref(), preset.as_deref())?
+                .walrus
+                .overridden_by(WalrusSettings {
+                    aggregator_url,
+                    fallback_aggregators: (!fallback_aggregators.is_empty())
+                        .then_some(fallback_aggregators),
+                    timeout_secs: timeout,
+                    retries,
+                    ..Default::default()
+                });
+            walrus.validate()?;
+            let sha256 = match manifest {
+                Some(manifest) => Some(manifest_sha256(&manifest, &blob_id)?),
+                None => sha256,
+            };
+            let report =
+                download_image_from_walrus(&blob_id, &output, walrus.to_walrus_options(), sha256)?;
+            emit_report(format, &report, print_download_report)?;
         }
         Commands::Info { input } => {
-            show_image_info(&input)?;
+            show_image_info(&input, format)?;
+        }
+        Commands::Compare {
+            original,
+            compressed,
+            diff,
+        } => {
+            show_comparison(&original, &compressed, diff.as_deref(), format)?;
+        }
+        Commands::Config {
+            command: ConfigCommand::Show,
+        } => {
+            let effective = load_config(config_path.as_deref())?.effective(preset.as_deref())?;
+            emit_report(format, &effective, print_effective_config)?;
         }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
 /// Sets up the global thread pool for parallel processing
@@ -80,22 +266,108 @@ fn setup_thread_pool(threads: Option<usize>) {
     }
 }
 
+/// Loads the configuration file given with `--config`, or the first one found
+/// in the default locations
+///
+/// # Arguments
+/// * `config_path` - Explicit configuration file, if any
+///
+/// # Returns
+/// * `Ok(Config)` - The loaded configuration, empty if no file exists
+/// * `Err(CompressionError)` if the file cannot be read or parsed
+fn load_config(config_path: Option<&Path>) -> Result<Config> {
+    match config_path {
+        Some(path) => Config::load(path),
+        None => Config::discover(),
+    }
+}
+
+/// Loads the configuration and returns the settings of `preset`, or the defaults
+///
+/// # Arguments
+/// * `config_path` - Explicit configuration file, if any
+/// * `preset` - Preset selected with `--preset`
+///
+/// # Returns
+/// * `Ok(Settings)` - The settings to layer command-line flags over
+/// * `Err(CompressionError)` if the configuration is invalid or the preset is unknown
+fn load_settings(config_path: Option<&Path>, preset: Option<&str>) -> Result<Settings> {
+    load_config(config_path)?.settings(preset)
+}
+
+/// Prints a command result in the requested output format
+///
+/// # Arguments
+/// * `format` - Text or JSON
+/// * `report` - The structured result returned by the command
+/// * `print_text` - Renders `report` as human-readable text
+///
+/// # Returns
+/// * `Ok(())` if the report was printed
+/// * `Err(CompressionError::Json)` if JSON serialization fails
+fn emit_report<T: Serialize>(
+    format: OutputFormat,
+    report: &T,
+    print_text: impl FnOnce(&T),
+) -> Result<()> {
+    match format {
+        OutputFormat::Text => print_text(report),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+    }
+    Ok(())
+}
+
 /// Displays information about an image file
 ///
 /// # Arguments
 /// * `input_path` - Path to the image file to analyze
+/// * `format` - Text or JSON output
 ///
 /// # Returns
 /// * `Ok(())` if analysis succeeds
 /// * `Err(CompressionError)` if file cannot be read or analyzed
-fn show_image_info(input_path: &Path) -> Result<()> {
-    println!("📋 Getting info for: {:?}", input_path);
+fn show_image_info(input_path: &Path, format: OutputFormat) -> Result<()> {
+    let info = get_image_info(input_path)?;
 
-    // 基本图片信息
-    get_image_info(input_path)?;
+    emit_report(format, &info, |info| {
+        println!("📋 Getting info for: {:?}", input_path);
 
-    // 详细信息（可选）
-    print_detailed_info(input_path)?;
+        // 基本图片信息
+        print_image_info(info);
 
-    Ok(())
+        // 详细信息（可选）
+        print_detailed_info(info);
+    })
+}
+
+/// Compares a compressed image against its original and prints the metrics
+///
+/// # Arguments
+/// * `original_path` - Path to the reference image
+/// * `compressed_path` - Path to the compressed image
+/// * `diff_output` - Optional path for the difference heatmap
+/// * `format` - Text or JSON output
+///
+/// # Returns
+/// * `Ok(())` if both images load and share the same dimensions
+/// * `Err(CompressionError)` otherwise
+fn show_comparison(
+    original_path: &Path,
+    compressed_path: &Path,
+    diff_output: Option<&Path>,
+    format: OutputFormat,
+) -> Result<()> {
+    let report = compare_images(original_path, compressed_path, diff_output)?;
+
+    emit_report(format, &report, |report| {
+        println!(
+            "🔍 Comparing {:?} with {:?}",
+            original_path, compressed_path
+        );
+        print_comparison(report);
+
+        if let Some(diff_path) = diff_output {
+            println!("🗺️  Diff heatmap saved to: {:?}", diff_path);
+        }
+    })
 }
@@ -0,0 +1,1141 @@
+use crate::error::{CompressionError, Result};
+use clap::ValueEnum;
+use exif::experimental::Writer as ExifWriter;
+use exif::{In, Reader as ExifReader, Tag};
+use image::ImageFormat;
+use img_parts::jpeg::{markers, Jpeg, JpegSegment};
+use img_parts::png::{Png, PngChunk};
+use img_parts::riff::{RiffChunk, RiffContent};
+use img_parts::webp::{
+    WebP, CHUNK_ALPH, CHUNK_EXIF, CHUNK_ICCP, CHUNK_VP8L, CHUNK_VP8X, CHUNK_XMP,
+};
+use img_parts::{Bytes, DynImage, ImageEXIF, ImageICC};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+/// JPEG APP1 payload prefix identifying an XMP packet
+const JPEG_XMP_PREFIX: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// PNG iTXt keyword identifying an XMP packet
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+const PNG_CHUNK_ITXT: [u8; 4] = *b"iTXt";
+const PNG_CHUNK_IDAT: [u8; 4] = *b"IDAT";
+/// MIME type of the AVIF item holding an XMP packet
+const AVIF_XMP_CONTENT_TYPE: &[u8] = b"application/rdf+xml";
+
+// VP8X feature flags (first byte of the VP8X payload)
+const VP8X_FLAG_ICC: u8 = 0b0010_0000;
+const VP8X_FLAG_ALPHA: u8 = 0b0001_0000;
+const VP8X_FLAG_EXIF: u8 = 0b0000_1000;
+const VP8X_FLAG_XMP: u8 = 0b0000_0100;
+
+/// Which source metadata blocks are carried over into the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetadataPolicy {
+    /// Keep EXIF, ICC profile and XMP
+    Keep,
+    /// Drop all metadata
+    #[default]
+    Strip,
+    /// Keep only the ICC colour profile
+    KeepIcc,
+    /// Keep the ICC profile and the EXIF Artist/Copyright fields
+    KeepCopyright,
+}
+
+/// Raw metadata blocks extracted from an image container.
+///
+/// `exif` holds the TIFF-structured EXIF payload without the `Exif\0\0` prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageMetadata {
+    pub exif: Option<Vec<u8>>,
+    pub icc: Option<Vec<u8>>,
+    pub xmp: Option<Vec<u8>>,
+}
+
+impl ImageMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.icc.is_none() && self.xmp.is_none()
+    }
+
+    /// Extracts metadata from encoded JPEG, PNG, WebP or AVIF bytes.
+    ///
+    /// Other containers, and containers that cannot be parsed, yield empty metadata:
+    /// metadata is best effort and never blocks compression.
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let image = match DynImage::from_bytes(Bytes::copy_from_slice(data)) {
+            Ok(Some(image)) => image,
+            _ => return avif_metadata(data).unwrap_or_default(),
+        };
+
+        let xmp = match &image {
+            DynImage::Jpeg(jpeg) => jpeg_xmp(jpeg),
+            DynImage::Png(png) => png_xmp(png),
+            DynImage::WebP(webp) => webp
+                .chunk_by_id(CHUNK_XMP)
+                .and_then(|chunk| chunk.content().data())
+                .map(|data| data.to_vec()),
+        };
+
+        Self {
+            exif: image.exif().map(|b| b.to_vec()),
+            icc: image.icc_profile().map(|b| b.to_vec()),
+            xmp,
+        }
+    }
+
+    /// Reads and extracts metadata from an image file.
+    pub fn read_from_file(path: &Path) -> Result<Self> {
+        let data = fs::read(path)?;
+        Ok(Self::from_bytes(&data))
+    }
+
+    /// Resets the EXIF orientation tag to "upright", for images whose pixels were rotated.
+    pub fn reset_orientation(&mut self) {
+        if let Some(exif) = &mut self.exif {
+            reset_exif_orientation(exif);
+        }
+    }
+
+    /// Returns only the blocks allowed by `policy`.
+    pub fn apply_policy(self, policy: MetadataPolicy) -> Self {
+        match policy {
+            MetadataPolicy::Keep => self,
+            MetadataPolicy::Strip => Self::default(),
+            MetadataPolicy::KeepIcc => Self {
+                icc: self.icc,
+                ..Self::default()
+            },
+            MetadataPolicy::KeepCopyright => Self {
+                exif: self.exif.as_deref().and_then(copyright_exif),
+                icc: self.icc,
+                xmp: None,
+            },
+        }
+    }
+
+    /// Embeds these metadata blocks into an encoded image, replacing any existing ones.
+    ///
+    /// AVIF blocks are added next to whatever the encoder wrote, which is never metadata.
+    ///
+    /// # Arguments
+    /// * `encoded` - The encoded image bytes
+    /// * `format` - Container format of `encoded`
+    ///
+    /// # Returns
+    /// * `Ok(bytes)` - The re-muxed image
+    /// * `Err(CompressionError::Metadata)` - If the container cannot be parsed
+    /// * `Err(CompressionError::UnsupportedFormat)` - If `format` cannot carry metadata;
+    ///   strip it (embed an empty `ImageMetadata`) to write such formats
+    pub fn embed(&self, encoded: Vec<u8>, format: ImageFormat) -> Result<Vec<u8>> {
+        if self.is_empty() {
+            return Ok(encoded);
+        }
+
+        let bytes = Bytes::from(encoded);
+        let output = match format {
+            ImageFormat::Jpeg => {
+                let mut jpeg = Jpeg::from_bytes(bytes).map_err(metadata_error)?;
+                jpeg.set_exif(self.exif.clone().map(Bytes::from));
+                jpeg.set_icc_profile(self.icc.clone().map(Bytes::from));
+                set_jpeg_xmp(&mut jpeg, self.xmp.as_deref());
+                jpeg.encoder().bytes()
+            }
+            ImageFormat::Png => {
+                let mut png = Png::from_bytes(bytes).map_err(metadata_error)?;
+                png.set_exif(self.exif.clone().map(Bytes::from));
+                png.set_icc_profile(self.icc.clone().map(Bytes::from));
+                set_png_xmp(&mut png, self.xmp.as_deref());
+                png.encoder().bytes()
+            }
+            ImageFormat::WebP => {
+                let mut webp = WebP::from_bytes(bytes).map_err(metadata_error)?;
+                self.embed_webp(&mut webp)?;
+                webp.encoder().bytes()
+            }
+            ImageFormat::Avif => avif_with_metadata(&bytes, self)
+                .map(Bytes::from)
+                .ok_or_else(|| metadata_error("unsupported AVIF container layout"))?,
+            _ => {
+                return Err(CompressionError::UnsupportedFormat(format!(
+                    "{:?} output cannot carry embedded metadata",
+                    format
+                )))
+            }
+        };
+
+        Ok(output.to_vec())
+    }
+
+    /// Rebuilds the extended (VP8X) WebP layout: VP8X, ICCP, image data, EXIF, XMP.
+    fn embed_webp(&self, webp: &mut WebP) -> Result<()> {
+        let (width, height) = webp_canvas_size(webp)
+            .ok_or_else(|| metadata_error("WebP image has no readable dimensions"))?;
+        let has_alpha = webp_has_alpha(webp);
+
+        for id in [CHUNK_VP8X, CHUNK_ICCP, CHUNK_EXIF, CHUNK_XMP] {
+            webp.remove_chunks_by_id(id);
+        }
+
+        let mut flags = 0u8;
+        if has_alpha {
+            flags |= VP8X_FLAG_ALPHA;
+        }
+
+        let chunks = webp.chunks_mut();
+        if let Some(icc) = &self.icc {
+            flags |= VP8X_FLAG_ICC;
+            chunks.insert(0, data_chunk(CHUNK_ICCP, icc.clone()));
+        }
+        if let Some(exif) = &self.exif {
+            flags |= VP8X_FLAG_EXIF;
+            let mut payload = b"Exif\0\0".to_vec();
+            payload.extend_from_slice(exif);
+            chunks.push(data_chunk(CHUNK_EXIF, payload));
+        }
+        if let Some(xmp) = &self.xmp {
+            flags |= VP8X_FLAG_XMP;
+            chunks.push(data_chunk(CHUNK_XMP, xmp.clone()));
+        }
+
+        let mut vp8x = vec![flags, 0, 0, 0];
+        vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        chunks.insert(0, data_chunk(CHUNK_VP8X, vp8x));
+
+        Ok(())
+    }
+}
+
+/// Reads source metadata for `policy`, skipping the file read entirely when stripping.
+pub fn read_metadata_for_policy(path: &Path, policy: MetadataPolicy) -> Result<ImageMetadata> {
+    if policy == MetadataPolicy::Strip {
+        return Ok(ImageMetadata::default());
+    }
+    Ok(ImageMetadata::read_from_file(path)?.apply_policy(policy))
+}
+
+/// Extracts source metadata for `policy` from encoded bytes, skipping the parse when stripping.
+pub fn metadata_from_bytes_for_policy(data: &[u8], policy: MetadataPolicy) -> ImageMetadata {
+    if policy == MetadataPolicy::Strip {
+        return ImageMetadata::default();
+    }
+    ImageMetadata::from_bytes(data).apply_policy(policy)
+}
+
+/// Builds a minimal EXIF block containing only the Artist and Copyright fields.
+///
+/// Returns `None` if the source EXIF cannot be parsed or has neither field.
+pub fn copyright_exif(exif: &[u8]) -> Option<Vec<u8>> {
+    let parsed = ExifReader::new().read_raw(exif.to_vec()).ok()?;
+    let fields: Vec<_> = [Tag::Artist, Tag::Copyright]
+        .into_iter()
+        .filter_map(|tag| parsed.get_field(tag, In::PRIMARY))
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+
+    let mut writer = ExifWriter::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut buf = Cursor::new(Vec::new());
+    writer.write(&mut buf, parsed.little_endian()).ok()?;
+    Some(buf.into_inner())
+}
+
+/// Sets the IFD0 Orientation tag of a TIFF-structured EXIF block to 1 in place.
+///
+/// Returns `false` if the block has no readable orientation tag.
+pub fn reset_exif_orientation(exif: &mut [u8]) -> bool {
+    let little_endian = match exif.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return false,
+    };
+    let read_u16 = |data: &[u8], pos: usize| {
+        let bytes: [u8; 2] = data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let ifd = match exif.get(4..8).and_then(|b| b.try_into().ok()) {
+        Some(bytes) if little_endian => u32::from_le_bytes(bytes) as usize,
+        Some(bytes) => u32::from_be_bytes(bytes) as usize,
+        None => return false,
+    };
+
+    let Some(count) = read_u16(exif, ifd) else {
+        return false;
+    };
+    for entry in (0..count as usize).map(|i| ifd + 2 + i * 12) {
+        // Orientation is tag 0x0112, a single SHORT stored inline
+        if read_u16(exif, entry) == Some(0x0112) && read_u16(exif, entry + 2) == Some(3) {
+            let one = if little_endian {
+                1u16.to_le_bytes()
+            } else {
+                1u16.to_be_bytes()
+            };
+            let Some(value) = exif.get_mut(entry + 8..entry + 10) else {
+                return false;
+            };
+            value.copy_from_slice(&one);
+            return true;
+        }
+    }
+    false
+}
+
+fn metadata_error(e: impl std::fmt::Display) -> CompressionError {
+    CompressionError::Metadata(e.to_string())
+}
+
+fn data_chunk(id: [u8; 4], data: Vec<u8>) -> RiffChunk {
+    RiffChunk::new(id, RiffContent::Data(Bytes::from(data)))
+}
+
+fn jpeg_xmp(jpeg: &Jpeg) -> Option<Vec<u8>> {
+    jpeg.segments_by_marker(markers::APP1)
+        .find_map(|segment| segment.contents().strip_prefix(JPEG_XMP_PREFIX))
+        .map(|xmp| xmp.to_vec())
+}
+
+fn set_jpeg_xmp(jpeg: &mut Jpeg, xmp: Option<&[u8]>) {
+    let segments = jpeg.segments_mut();
+    segments.retain(|segment| {
+        !(segment.marker() == markers::APP1 && segment.contents().starts_with(JPEG_XMP_PREFIX))
+    });
+
+    if let Some(xmp) = xmp {
+        let mut contents = JPEG_XMP_PREFIX.to_vec();
+        contents.extend_from_slice(xmp);
+        // Keep application segments together at the start of the file
+        let pos = segments
+            .iter()
+            .position(|segment| !(markers::APP0..=markers::APP15).contains(&segment.marker()))
+            .unwrap_or(segments.len());
+        segments.insert(
+            pos,
+            JpegSegment::new_with_contents(markers::APP1, Bytes::from(contents)),
+        );
+    }
+}
+
+fn png_xmp(png: &Png) -> Option<Vec<u8>> {
+    png.chunks_by_type(PNG_CHUNK_ITXT).find_map(|chunk| {
+        let rest = chunk.contents().strip_prefix(PNG_XMP_KEYWORD)?;
+        // keyword NUL, compression flag, compression method
+        let (&[0, 0, _], rest) = rest.split_first_chunk::<3>()? else {
+            return None;
+        };
+        // language tag and translated keyword, both NUL-terminated
+        let lang_end = rest.iter().position(|&b| b == 0)?;
+        let rest = &rest[lang_end + 1..];
+        let translated_end = rest.iter().position(|&b| b == 0)?;
+        Some(rest[translated_end + 1..].to_vec())
+    })
+}
+
+fn set_png_xmp(png: &mut Png, xmp: Option<&[u8]>) {
+    let chunks = png.chunks_mut();
+    chunks.retain(|chunk| {
+        !(chunk.kind() == PNG_CHUNK_ITXT && chunk.contents().starts_with(PNG_XMP_KEYWORD))
+    });
+
+    if let Some(xmp) = xmp {
+        let mut contents = PNG_XMP_KEYWORD.to_vec();
+        // keyword NUL, uncompressed, no language tag, no translated keyword
+        contents.extend_from_slice(&[0, 0, 0, 0, 0]);
+        contents.extend_from_slice(xmp);
+        let pos = chunks
+            .iter()
+            .position(|chunk| chunk.kind() == PNG_CHUNK_IDAT)
+            .unwrap_or(chunks.len());
+        chunks.insert(pos, PngChunk::new(PNG_CHUNK_ITXT, Bytes::from(contents)));
+    }
+}
+
+fn webp_canvas_size(webp: &WebP) -> Option<(u32, u32)> {
+    if let Some(data) = webp
+        .chunk_by_id(CHUNK_VP8X)
+        .and_then(|chunk| chunk.content().data())
+    {
+        let size = data.get(4..10)?;
+        let width = u32::from_le_bytes([size[0], size[1], size[2], 0]) + 1;
+        let height = u32::from_le_bytes([size[3], size[4], size[5], 0]) + 1;
+        return Some((width, height));
+    }
+    webp.dimensions()
+}
+
+fn webp_has_alpha(webp: &WebP) -> bool {
+    if webp.has_chunk(CHUNK_ALPH) {
+        return true;
+    }
+    if let Some(flags) = webp
+        .chunk_by_id(CHUNK_VP8X)
+        .and_then(|chunk| chunk.content().data())
+        .and_then(|data| data.first().copied())
+    {
+        if flags & VP8X_FLAG_ALPHA != 0 {
+            return true;
+        }
+    }
+    // VP8L header: signature byte, then 14-bit width, 14-bit height and the alpha bit
+    webp.chunk_by_id(CHUNK_VP8L)
+        .and_then(|chunk| chunk.content().data())
+        .and_then(|data| data.get(1..5))
+        .map(|header| {
+            let bits = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            (bits >> 28) & 1 == 1
+        })
+        .unwrap_or(false)
+}
+
+/// A box inside an ISOBMFF (AVIF) buffer.
+struct IsoBox<'a> {
+    kind: [u8; 4],
+    /// Offset of the box header within the parsed buffer
+    start: usize,
+    /// The whole box, header included
+    raw: &'a [u8],
+    payload: &'a [u8],
+}
+
+/// An AVIF item as listed in `iinf`.
+struct ItemInfo<'a> {
+    id: u32,
+    kind: [u8; 4],
+    content_type: Option<&'a [u8]>,
+}
+
+/// An `iloc` entry; base offsets are folded into each extent offset.
+#[derive(Clone)]
+struct ItemLocation {
+    id: u32,
+    construction_method: u8,
+    data_reference_index: u16,
+    extents: Vec<(u64, u64)>,
+}
+
+struct ItemLocations {
+    version: u8,
+    items: Vec<ItemLocation>,
+}
+
+/// `ipma` associations as `(essential, property index)` per item.
+struct PropertyAssociations {
+    version: u8,
+    flags: u32,
+    entries: Vec<(u32, Vec<(bool, u16)>)>,
+}
+
+/// Big-endian reader over a box payload.
+struct BoxReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BoxReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Reads an unsigned field of 0, 4 or 8 bytes
+    fn uint(&mut self, size: u8) -> Option<u64> {
+        match size {
+            0 => Some(0),
+            4 => self.u32().map(u64::from),
+            8 => self
+                .bytes(8)
+                .map(|b| u64::from_be_bytes(b.try_into().unwrap())),
+            _ => None,
+        }
+    }
+
+    /// Reads an item ID that is 16-bit in early box versions and 32-bit later
+    fn item_id(&mut self, wide: bool) -> Option<u32> {
+        if wide {
+            self.u32()
+        } else {
+            self.u16().map(u32::from)
+        }
+    }
+
+    fn full_box_header(&mut self) -> Option<(u8, u32)> {
+        let header = self.u32()?;
+        Some(((header >> 24) as u8, header & 0x00FF_FFFF))
+    }
+
+    fn cstr(&mut self) -> Option<&'a [u8]> {
+        let len = self.rest().iter().position(|&b| b == 0)?;
+        let s = self.bytes(len)?;
+        self.pos += 1;
+        Some(s)
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+}
+
+fn iso_boxes(data: &[u8]) -> Option<Vec<IsoBox<'_>>> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut reader = BoxReader::new(&data[pos..]);
+        let size = reader.u32()?;
+        let kind: [u8; 4] = reader.bytes(4)?.try_into().ok()?;
+        let len = match size {
+            0 => data.len() - pos,
+            1 => usize::try_from(reader.uint(8)?).ok()?,
+            n => n as usize,
+        };
+        if len < reader.pos {
+            return None;
+        }
+        let raw = data.get(pos..pos.checked_add(len)?)?;
+        boxes.push(IsoBox {
+            kind,
+            start: pos,
+            raw,
+            payload: &raw[reader.pos..],
+        });
+        pos += len;
+    }
+    Some(boxes)
+}
+
+fn iso_box(kind: [u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(&kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+fn iso_full_box(kind: [u8; 4], version: u8, flags: u32, payload: &[u8]) -> Vec<u8> {
+    let mut body = ((version as u32) << 24 | flags).to_be_bytes().to_vec();
+    body.extend_from_slice(payload);
+    iso_box(kind, &body)
+}
+
+fn push_item_id(out: &mut Vec<u8>, id: u32, wide: bool) {
+    if wide {
+        out.extend_from_slice(&id.to_be_bytes());
+    } else {
+        out.extend_from_slice(&(id as u16).to_be_bytes());
+    }
+}
+
+fn parse_pitm(payload: &[u8]) -> Option<u32> {
+    let mut reader = BoxReader::new(payload);
+    let (version, _) = reader.full_box_header()?;
+    reader.item_id(version > 0)
+}
+
+fn parse_iinf(payload: &[u8]) -> Option<(u8, Vec<IsoBox<'_>>)> {
+    let mut reader = BoxReader::new(payload);
+    let (version, _) = reader.full_box_header()?;
+    reader.item_id(version > 0)?;
+    Some((version, iso_boxes(reader.rest())?))
+}
+
+fn parse_infe(payload: &[u8]) -> Option<ItemInfo<'_>> {
+    let mut reader = BoxReader::new(payload);
+    let (version, _) = reader.full_box_header()?;
+    if version < 2 {
+        return None;
+    }
+    let id = reader.item_id(version > 2)?;
+    reader.u16()?; // item_protection_index
+    let kind: [u8; 4] = reader.bytes(4)?.try_into().ok()?;
+    reader.cstr()?; // item_name
+    let content_type = if &kind == b"mime" {
+        reader.cstr()
+    } else {
+        None
+    };
+    Some(ItemInfo {
+        id,
+        kind,
+        content_type,
+    })
+}
+
+fn parse_iloc(payload: &[u8]) -> Option<ItemLocations> {
+    let mut reader = BoxReader::new(payload);
+    let (version, _) = reader.full_box_header()?;
+    let sizes = reader.u8()?;
+    let (offset_size, length_size) = (sizes >> 4, sizes & 0x0F);
+    let sizes = reader.u8()?;
+    let base_offset_size = sizes >> 4;
+    let index_size = if version > 0 { sizes & 0x0F } else { 0 };
+    let count = if version < 2 {
+        reader.u16()? as u32
+    } else {
+        reader.u32()?
+    };
+
+    let mut items = Vec::new();
+    for _ in 0..count {
+        let id = reader.item_id(version > 1)?;
+        let construction_method = if version > 0 {
+            (reader.u16()? & 0x0F) as u8
+        } else {
+            0
+        };
+        let data_reference_index = reader.u16()?;
+        let base_offset = reader.uint(base_offset_size)?;
+        let mut extents = Vec::new();
+        for _ in 0..reader.u16()? {
+            // Extent indices refer to `idx` data references, which this writer does not emit
+            if reader.uint(index_size)? != 0 {
+                return None;
+            }
+            let offset = base_offset.checked_add(reader.uint(offset_size)?)?;
+            extents.push((offset, reader.uint(length_size)?));
+        }
+        items.push(ItemLocation {
+            id,
+            construction_method,
+            data_reference_index,
+            extents,
+        });
+    }
+    Some(ItemLocations { version, items })
+}
+
+/// Writes `iloc` with 4-byte offsets and lengths and no base offsets.
+fn write_iloc(iloc: &ItemLocations) -> Option<Vec<u8>> {
+    let mut out = vec![4 << 4 | 4, 0];
+    if iloc.version < 2 {
+        out.extend_from_slice(&u16::try_from(iloc.items.len()).ok()?.to_be_bytes());
+    } else {
+        out.extend_from_slice(&(iloc.items.len() as u32).to_be_bytes());
+    }
+    for item in &iloc.items {
+        push_item_id(&mut out, item.id, iloc.version > 1);
+        if iloc.version > 0 {
+            out.extend_from_slice(&u16::from(item.construction_method).to_be_bytes());
+        }
+        out.extend_from_slice(&item.data_reference_index.to_be_bytes());
+        out.extend_from_slice(&(item.extents.len() as u16).to_be_bytes());
+        for &(offset, length) in &item.extents {
+            out.extend_from_slice(&u32::try_from(offset).ok()?.to_be_bytes());
+            out.extend_from_slice(&u32::try_from(length).ok()?.to_be_bytes());
+        }
+    }
+    Some(iso_full_box(*b"iloc", iloc.version, 0, &out))
+}
+
+fn parse_ipma(payload: &[u8]) -> Option<PropertyAssociations> {
+    let mut reader = BoxReader::new(payload);
+    let (version, flags) = reader.full_box_header()?;
+    let mut entries = Vec::new();
+    for _ in 0..reader.u32()? {
+        let id = reader.item_id(version > 0)?;
+        let mut associations = Vec::new();
+        for _ in 0..reader.u8()? {
+            let association = if flags & 1 == 1 {
+                let value = reader.u16()?;
+                (value & 0x8000 != 0, value & 0x7FFF)
+            } else {
+                let value = reader.u8()?;
+                (value & 0x80 != 0, u16::from(value & 0x7F))
+            };
+            associations.push(association);
+        }
+        entries.push((id, associations));
+    }
+    Some(PropertyAssociations {
+        version,
+        flags,
+        entries,
+    })
+}
+
+fn write_ipma(ipma: &PropertyAssociations) -> Vec<u8> {
+    let mut out = (ipma.entries.len() as u32).to_be_bytes().to_vec();
+    for (id, associations) in &ipma.entries {
+        push_item_id(&mut out, *id, ipma.version > 0);
+        out.push(associations.len() as u8);
+        for &(essential, index) in associations {
+            if ipma.flags & 1 == 1 {
+                out.extend_from_slice(&((essential as u16) << 15 | index).to_be_bytes());
+            } else {
+                out.push((essential as u8) << 7 | index as u8);
+            }
+        }
+    }
+    iso_full_box(*b"ipma", ipma.version, ipma.flags, &out)
+}
+
+/// Returns the contents of a single-extent item stored in the file itself.
+fn avif_item_data<'a>(data: &'a [u8], iloc: &ItemLocations, id: u32) -> Option<&'a [u8]> {
+    let item = iloc.items.iter().find(|item| item.id == id)?;
+    if item.construction_method != 0 || item.data_reference_index != 0 {
+        return None;
+    }
+    let &[(offset, length)] = item.extents.as_slice() else {
+        return None;
+    };
+    data.get(usize::try_from(offset).ok()?..usize::try_from(offset + length).ok()?)
+}
+
+/// Extracts the primary item's ICC profile and the EXIF/XMP items from an AVIF file.
+fn avif_metadata(data: &[u8]) -> Option<ImageMetadata> {
+    let top = iso_boxes(data)?;
+    let meta = top.iter().find(|b| &b.kind == b"meta")?;
+    let children = iso_boxes(meta.payload.get(4..)?)?;
+    let child = |kind: &[u8; 4]| children.iter().find(|b| &b.kind == kind);
+
+    let primary = parse_pitm(child(b"pitm")?.payload)?;
+    let iloc = parse_iloc(child(b"iloc")?.payload)?;
+    let (_, infe_boxes) = parse_iinf(child(b"iinf")?.payload)?;
+    let items: Vec<_> = infe_boxes
+        .iter()
+        .filter_map(|infe| parse_infe(infe.payload))
+        .collect();
+
+    let exif = items
+        .iter()
+        .find(|item| &item.kind == b"Exif")
+        .and_then(|item| avif_item_data(data, &iloc, item.id))
+        .and_then(|payload| {
+            // The payload starts with the offset of the TIFF header past this field
+            let skip = BoxReader::new(payload).u32()? as usize;
+            payload.get(4usize.checked_add(skip)?..)
+        })
+        .map(|exif| exif.to_vec());
+    let xmp = items
+        .iter()
+        .find(|item| &item.kind == b"mime" && item.content_type == Some(AVIF_XMP_CONTENT_TYPE))
+        .and_then(|item| avif_item_data(data, &iloc, item.id))
+        .map(|xmp| xmp.to_vec());
+
+    let icc = child(b"iprp").and_then(|iprp| {
+        let iprp = iso_boxes(iprp.payload)?;
+        let properties = iso_boxes(iprp.iter().find(|b| &b.kind == b"ipco")?.payload)?;
+        let ipma = parse_ipma(iprp.iter().find(|b| &b.kind == b"ipma")?.payload)?;
+        let (_, associations) = ipma.entries.iter().find(|(id, _)| *id == primary)?;
+        associations.iter().find_map(|&(_, index)| {
+            let property = properties.get(usize::from(index).checked_sub(1)?)?;
+            let profile = property.payload.strip_prefix(b"prof")?;
+            (&property.kind == b"colr").then(|| profile.to_vec())
+        })
+    });
+
+    Some(ImageMetadata { exif, icc, xmp })
+}
+
+/// Adds metadata to an AVIF file by rewriting its `meta` box.
+///
+/// The ICC profile becomes a `colr` property of the primary item; EXIF and XMP
+/// become items stored in an extra `mdat` at the end of the file. Existing item
+/// offsets are shifted by however much `meta` grew.
+fn avif_with_metadata(data: &[u8], metadata: &ImageMetadata) -> Option<Vec<u8>> {
+    let top = iso_boxes(data)?;
+    let meta = top.iter().find(|b| &b.kind == b"meta")?;
+    let meta_end = meta.start + meta.raw.len();
+    let mut header = BoxReader::new(meta.payload);
+    let (meta_version, meta_flags) = header.full_box_header()?;
+    let children = iso_boxes(header.rest())?;
+    let child = |kind: &[u8; 4]| children.iter().find(|b| &b.kind == kind);
+
+    let primary = parse_pitm(child(b"pitm")?.payload)?;
+    let iloc = parse_iloc(child(b"iloc")?.payload)?;
+    let (iinf_version, infe_boxes) = parse_iinf(child(b"iinf")?.payload)?;
+    let iref = match child(b"iref") {
+        Some(iref) => {
+            let mut reader = BoxReader::new(iref.payload);
+            let (version, _) = reader.full_box_header()?;
+            Some((version, reader.rest()))
+        }
+        None => None,
+    };
+
+    // New items as (id, infe box, payload)
+    let mut next_id = infe_boxes
+        .iter()
+        .filter_map(|infe| parse_infe(infe.payload).map(|item| item.id))
+        .chain(iloc.items.iter().map(|item| item.id))
+        .max()
+        .unwrap_or(0);
+    let mut new_items = Vec::new();
+    let mut add_item = |kind: &[u8; 4], content_type: Option<&[u8]>, payload: Vec<u8>| {
+        next_id += 1;
+        let mut infe = Vec::new();
+        push_item_id(&mut infe, next_id, next_id > u16::MAX as u32);
+        infe.extend_from_slice(&[0, 0]);
+        infe.extend_from_slice(kind);
+        infe.push(0);
+        if let Some(content_type) = content_type {
+            infe.extend_from_slice(content_type);
+            infe.push(0);
+        }
+        let version = if next_id > u16::MAX as u32 { 3 } else { 2 };
+        new_items.push((next_id, iso_full_box(*b"infe", version, 0, &infe), payload));
+    };
+    if let Some(exif) = &metadata.exif {
+        let mut payload = 0u32.to_be_bytes().to_vec();
+        payload.extend_from_slice(exif);
+        add_item(b"Exif", None, payload);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        add_item(b"mime", Some(AVIF_XMP_CONTENT_TYPE), xmp.clone());
+    }
+
+    let iinf = {
+        let mut body = Vec::new();
+        let count = infe_boxes.len() + new_items.len();
+        if iinf_version == 0 {
+            body.extend_from_slice(&u16::try_from(count).ok()?.to_be_bytes());
+        } else {
+            body.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+        for infe in &infe_boxes {
+            body.extend_from_slice(infe.raw);
+        }
+        for (_, infe, _) in &new_items {
+            body.extend_from_slice(infe);
+        }
+        iso_full_box(*b"iinf", iinf_version, 0, &body)
+    };
+
+    let iref = (iref.is_some() || !new_items.is_empty()).then(|| {
+        let (version, existing) = iref.unwrap_or((0, &[]));
+        let mut body = existing.to_vec();
+        for (id, _, _) in &new_items {
+            let mut reference = Vec::new();
+            push_item_id(&mut reference, *id, version > 0);
+            reference.extend_from_slice(&1u16.to_be_bytes());
+            push_item_id(&mut reference, primary, version > 0);
+            body.extend_from_slice(&iso_box(*b"cdsc", &reference));
+        }
+        iso_full_box(*b"iref", version, 0, &body)
+    });
+
+    let iprp = match &metadata.icc {
+        Some(icc) => {
+            let iprp = iso_boxes(child(b"iprp")?.payload)?;
+            let mut body = Vec::new();
+            let mut colr_index = 0;
+            for property in &iprp {
+                if &property.kind == b"ipco" {
+                    colr_index = iso_boxes(property.payload)?.len() as u16 + 1;
+                    let mut ipco = property.payload.to_vec();
+                    ipco.extend_from_slice(&iso_box(*b"colr", &[b"prof", icc.as_slice()].concat()));
+                    body.extend_from_slice(&iso_box(*b"ipco", &ipco));
+                }
+            }
+            for property in &iprp {
+                if &property.kind == b"ipma" {
+                    let mut ipma = parse_ipma(property.payload)?;
+                    let (_, associations) =
+                        ipma.entries.iter_mut().find(|(id, _)| *id == primary)?;
+                    associations.push((false, colr_index));
+                    if colr_index > 0x7F {
+                        ipma.flags |= 1;
+                    }
+                    body.extend_from_slice(&write_ipma(&ipma));
+                } else if &property.kind != b"ipco" {
+                    body.extend_from_slice(property.raw);
+                }
+            }
+            (colr_index > 0).then(|| iso_box(*b"iprp", &body))?
+        }
+        None => child(b"iprp")?.raw.to_vec(),
+    };
+
+    // `iloc` has a fixed size, so the second pass only changes offsets
+    let build_meta = |growth: i64| -> Option<Vec<u8>> {
+        let mut locations = ItemLocations {
+            version: iloc.version,
+            items: iloc.items.clone(),
+        };
+        for item in &mut locations.items {
+            if item.construction_method == 0 && item.data_reference_index == 0 {
+                for (offset, _) in &mut item.extents {
+                    if *offset >= meta_end as u64 {
+                        *offset = offset.checked_add_signed(growth)?;
+                    }
+                }
+            }
+        }
+        let mut offset = (data.len() as u64).checked_add_signed(growth)? + 8;
+        for (id, _, payload) in &new_items {
+            locations.items.push(ItemLocation {
+                id: *id,
+                construction_method: 0,
+                data_reference_index: 0,
+                extents: vec![(offset, payload.len() as u64)],
+            });
+            offset += payload.len() as u64;
+        }
+        if locations.version == 0 && locations.items.iter().any(|item| item.id > 0xFFFF) {
+            locations.version = 2;
+        }
+
+        let mut body = Vec::new();
+        for child in &children {
+            match &child.kind {
+                b"iloc" => body.extend_from_slice(&write_iloc(&locations)?),
+                b"iinf" => {
+                    body.extend_from_slice(&iinf);
+                    if !children.iter().any(|b| &b.kind == b"iref") {
+                        body.extend_from_slice(iref.as_deref().unwrap_or_default());
+                    }
+                }
+                b"iref" => body.extend_from_slice(iref.as_deref()?),
+                b"iprp" => body.extend_from_slice(&iprp),
+                _ => body.extend_from_slice(child.raw),
+            }
+        }
+        Some(iso_full_box(*b"meta", meta_version, meta_flags, &body))
+    };
+    let draft = build_meta(0)?;
+    let new_meta = build_meta(draft.len() as i64 - meta.raw.len() as i64)?;
+
+    let mut out = data[..meta.start].to_vec();
+    out.extend_from_slice(&new_meta);
+    out.extend_from_slice(&data[meta_end..]);
+    if !new_items.is_empty() {
+        let payloads: Vec<u8> = new_items
+            .iter()
+            .flat_map(|(_, _, payload)| payload.iter().copied())
+            .collect();
+        out.extend_from_slice(&iso_box(*b"mdat", &payloads));
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::{Field, Value};
+    use image::{DynamicImage, GenericImageView, RgbImage, RgbaImage};
+
+    fn sample_exif() -> Vec<u8> {
+        let make = Field {
+            tag: Tag::Make,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"Camera Co".to_vec()]),
+        };
+        let artist = Field {
+            tag: Tag::Artist,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"Jane Doe".to_vec()]),
+        };
+        let copyright = Field {
+            tag: Tag::Copyright,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"(c) Jane Doe".to_vec()]),
+        };
+        let mut writer = ExifWriter::new();
+        writer.push_field(&make);
+        writer.push_field(&artist);
+        writer.push_field(&copyright);
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+        buf.into_inner()
+    }
+
+    fn sample_metadata() -> ImageMetadata {
+        ImageMetadata {
+            exif: Some(sample_exif()),
+            icc: Some(b"fake icc profile".to_vec()),
+            xmp: Some(b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>".to_vec()),
+        }
+    }
+
+    fn encode(img: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        img.write_to(&mut buf, format).unwrap();
+        buf.into_inner()
+    }
+
+    fn exif_ascii(exif: &[u8], tag: Tag) -> Option<String> {
+        let parsed = ExifReader::new().read_raw(exif.to_vec()).unwrap();
+        parsed
+            .get_field(tag, In::PRIMARY)
+            .map(|field| field.display_value().to_string())
+    }
+
+    #[test]
+    fn test_apply_policy() {
+        let metadata = sample_metadata();
+
+        assert!(metadata
+            .clone()
+            .apply_policy(MetadataPolicy::Strip)
+            .is_empty());
+        assert_eq!(
+            metadata.clone().apply_policy(MetadataPolicy::Keep),
+            metadata
+        );
+
+        let icc_only = metadata.clone().apply_policy(MetadataPolicy::KeepIcc);
+        assert_eq!(icc_only.icc, metadata.icc);
+        assert!(icc_only.exif.is_none() && icc_only.xmp.is_none());
+
+        let copyright = metadata.clone().apply_policy(MetadataPolicy::KeepCopyright);
+        assert_eq!(copyright.icc, metadata.icc);
+        assert!(copyright.xmp.is_none());
+        let exif = copyright.exif.unwrap();
+        assert!(exif_ascii(&exif, Tag::Copyright)
+            .unwrap()
+            .contains("Jane Doe"));
+        assert!(exif_ascii(&exif, Tag::Artist).is_some());
+        assert!(exif_ascii(&exif, Tag::Make).is_none());
+    }
+
+    #[test]
+    fn test_copyright_exif_without_fields() {
+        let field = Field {
+            tag: Tag::Make,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"Camera Co".to_vec()]),
+        };
+        let mut writer = ExifWriter::new();
+        writer.push_field(&field);
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, true).unwrap();
+
+        assert_eq!(copyright_exif(&buf.into_inner()), None);
+        assert_eq!(copyright_exif(b"not exif"), None);
+    }
+
+    #[test]
+    fn test_embed_rejects_formats_without_metadata() {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(4, 4));
+        let mut gif = Vec::new();
+        img.write_to(&mut Cursor::new(&mut gif), ImageFormat::Gif)
+            .unwrap();
+
+        assert!(matches!(
+            sample_metadata().embed(gif.clone(), ImageFormat::Gif),
+            Err(CompressionError::UnsupportedFormat(_))
+        ));
+        // Nothing to embed, nothing to refuse
+        assert_eq!(
+            ImageMetadata::default()
+                .embed(gif.clone(), ImageFormat::Gif)
+                .unwrap(),
+            gif
+        );
+    }
+
+    #[test]
+    fn test_embed_round_trip_jpeg_and_png() {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(16, 16));
+        let metadata = sample_metadata();
+
+        for format in [ImageFormat::Jpeg, ImageFormat::Png] {
+            let embedded = metadata.embed(encode(&img, format), format).unwrap();
+            assert_eq!(ImageMetadata::from_bytes(&embedded), metadata);
+            assert_eq!(
+                image::load_from_memory(&embedded).unwrap().dimensions(),
+                (16, 16)
+            );
+
+            // Re-embedding replaces rather than duplicates
+            let stripped = ImageMetadata::default()
+                .embed(embedded.clone(), format)
+                .unwrap();
+            assert_eq!(stripped, embedded);
+            let replaced = ImageMetadata {
+                icc: Some(b"other".to_vec()),
+                ..ImageMetadata::default()
+            }
+            .embed(embedded, format)
+            .unwrap();
+            assert_eq!(
+                ImageMetadata::from_bytes(&replaced).icc,
+                Some(b"other".to_vec())
+            );
+        }
+    }
+
+    #[test]
+    fn test_embed_round_trip_webp() {
+        let metadata = sample_metadata();
+        let rgb = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 10, image::Rgb([9, 8, 7])));
+        let rgba =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 10, image::Rgba([9, 8, 7, 100])));
+
+        for (img, lossless) in [(&rgb, false), (&rgb, true), (&rgba, false), (&rgba, true)] {
+            let options = crate::processing::CompressionOptions::new(None, None, None, None)
+                .unwrap()
+                .with_webp_options(lossless, 4, 100, None);
+            let mut encoded = Vec::new();
+            crate::processing::encode_webp(img, &mut encoded, &options).unwrap();
+
+            let embedded = metadata.embed(encoded, ImageFormat::WebP).unwrap();
+            assert_eq!(ImageMetadata::from_bytes(&embedded), metadata);
+
+            let webp = WebP::from_bytes(Bytes::from(embedded.clone())).unwrap();
+            assert_eq!(webp_canvas_size(&webp), Some((20, 10)));
+            assert_eq!(webp_has_alpha(&webp), img.color().has_alpha());
+
+            let decoded = image::load_from_memory(&embedded).unwrap();
+            assert_eq!(decoded.dimensions(), (20, 10));
+        }
+    }
+
+    #[test]
+    fn test_embed_round_trip_avif() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, image::Rgba([1, 2, 3, 4])));
+        let encoded = encode(&img, ImageFormat::Avif);
+        let primary_data = |data: &[u8]| {
+            let top = iso_boxes(data).unwrap();
+            let meta = top.iter().find(|b| &b.kind == b"meta").unwrap();
+            let children = iso_boxes(&meta.payload[4..]).unwrap();
+            let child = |kind: &[u8; 4]| children.iter().find(|b| &b.kind == kind).unwrap();
+            let primary = parse_pitm(child(b"pitm").payload).unwrap();
+            let iloc = parse_iloc(child(b"iloc").payload).unwrap();
+            avif_item_data(data, &iloc, primary).unwrap().to_vec()
+        };
+
+        let full = sample_metadata();
+        let icc_only = full.clone().apply_policy(MetadataPolicy::KeepIcc);
+        let exif_only = ImageMetadata {
+            exif: full.exif.clone(),
+            ..ImageMetadata::default()
+        };
+        for metadata in [full, icc_only, exif_only] {
+            let embedded = metadata.embed(encoded.clone(), ImageFormat::Avif).unwrap();
+            assert_eq!(ImageMetadata::from_bytes(&embedded), metadata);
+            // Item offsets still point at the untouched AV1 payload
+            assert_eq!(primary_data(&embedded), primary_data(&encoded));
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_unknown_container() {
+        assert!(ImageMetadata::from_bytes(b"not an image").is_empty());
+    }
+}
@@ -1,21 +1,138 @@
 use crate::constants::{
-    DEFAULT_QUALITY, LIBDEFLATER_HIGH_LEVEL, LIBDEFLATER_LOW_LEVEL, MAX_FILE_SIZE,
-    MAX_IMAGE_DIMENSION, MAX_QUALITY, MIN_QUALITY, ZOPFLI_ITERATIONS,
+    DEFAULT_QUALITY, DEFAULT_WEBP_ALPHA_QUALITY, DEFAULT_WEBP_METHOD, LIBDEFLATER_HIGH_LEVEL,
+    LIBDEFLATER_LOW_LEVEL, MAX_FILE_SIZE, MAX_IMAGE_DIMENSION, MAX_QUALITY,
+    MAX_TARGET_SIZE_DOWNSCALES, MAX_WEBP_METHOD, MIN_QUALITY, STDIO_PATH,
+    TARGET_SIZE_DOWNSCALE_MARGIN, ZOPFLI_ITERATIONS,
 };
 use crate::error::{CompressionError, Result};
-use image::{DynamicImage, GenericImageView, ImageEncoder, ImageFormat, ImageReader};
+use crate::metadata::{
+    metadata_from_bytes_for_policy, read_metadata_for_policy, ImageMetadata, MetadataPolicy,
+};
+use crate::quality::ssim;
+use crate::upload::UploadedBlob;
+use clap::ValueEnum;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, GenericImageView, ImageDecoder, ImageEncoder, ImageFormat, ImageReader};
 use indicatif::{ProgressBar, ProgressStyle};
-use oxipng::{Deflaters, InFile, Options, OutFile};
-use std::fs;
+use jpeg_encoder::{ColorType as JpegColorType, Encoder as JpegEncoder, SamplingFactor};
+use oxipng::{Deflaters, Options};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Cursor, Read, Seek, Write};
 use std::num::NonZeroU8;
 use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Chroma subsampling applied to the Cb/Cr planes of JPEG output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+pub enum ChromaSubsampling {
+    /// 4:4:4 - full colour resolution, largest files
+    #[value(name = "444")]
+    #[serde(rename = "444")]
+    Yuv444,
+    /// 4:2:2 - half horizontal colour resolution
+    #[value(name = "422")]
+    #[serde(rename = "422")]
+    Yuv422,
+    /// 4:2:0 - half horizontal and vertical colour resolution
+    #[default]
+    #[value(name = "420")]
+    #[serde(rename = "420")]
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    fn sampling_factor(self) -> SamplingFactor {
+        match self {
+            ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+/// How the requested width/height constrain the output dimensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeMode {
+    /// Scale to fit within the box, preserving aspect ratio
+    #[default]
+    Fit,
+    /// Scale to cover the box, then center-crop to exactly the box
+    Fill,
+    /// Scale to cover the box, preserving aspect ratio without cropping
+    Cover,
+    /// Resize to exactly the given dimensions, ignoring aspect ratio
+    Exact,
+    /// Like fit, but never enlarge images that are already smaller
+    NoUpscale,
+}
+
+/// Resampling filter used when resizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeFilter {
+    /// Nearest neighbour - fastest, keeps hard edges (pixel art, screenshots)
+    Nearest,
+    /// Bilinear - fast, slightly soft
+    Triangle,
+    /// Bicubic Catmull-Rom - good balance of sharpness and speed
+    CatmullRom,
+    /// Gaussian - smooth, blurs fine detail
+    Gaussian,
+    /// Lanczos with window 3 - sharpest, slowest
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    fn filter_type(self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CompressionOptions {
     pub quality: u8,
     pub width: Option<u32>,
     pub height: Option<u32>,
     pub format: Option<String>,
+    /// How `width` and `height` are applied when resizing
+    pub resize_mode: ResizeMode,
+    /// Resampling filter used when resizing
+    pub filter: ResizeFilter,
+    /// JPEG chroma subsampling mode
+    pub chroma_subsampling: ChromaSubsampling,
+    /// Emit progressive JPEG scans instead of a single baseline scan
+    pub progressive: bool,
+    /// Build image-specific Huffman tables for JPEG output
+    pub optimize_huffman: bool,
+    /// Encode WebP losslessly instead of with the lossy VP8 codec
+    pub webp_lossless: bool,
+    /// WebP encoder effort (0 = fastest, 6 = smallest output)
+    pub webp_method: u8,
+    /// Quality of the WebP alpha plane (0-100)
+    pub webp_alpha_quality: u8,
+    /// WebP near-lossless preprocessing level (0-100, 100 = off); implies lossless
+    pub webp_near_lossless: Option<u8>,
+    /// Which source metadata (EXIF, ICC, XMP) is carried into the output
+    pub metadata: MetadataPolicy,
+    /// Rotate/flip pixels upright per the EXIF orientation tag when loading
+    pub auto_orient: bool,
+    /// Maximum output size in bytes; `quality` becomes the upper bound of a search
+    pub target_size: Option<u64>,
+    /// Shrink the image when `target_size` cannot be met at the minimum quality
+    pub target_downscale: bool,
+    /// Minimum SSIM against the resized source; `quality` becomes the upper bound of a search
+    pub target_ssim: Option<f64>,
 }
 
 impl CompressionOptions {
@@ -35,8 +152,152 @@ impl CompressionOptions {
             width,
             height,
             format,
+            resize_mode: ResizeMode::default(),
+            filter: ResizeFilter::default(),
+            chroma_subsampling: ChromaSubsampling::default(),
+            progressive: false,
+            optimize_huffman: false,
+            webp_lossless: false,
+            webp_method: DEFAULT_WEBP_METHOD,
+            webp_alpha_quality: DEFAULT_WEBP_ALPHA_QUALITY,
+            webp_near_lossless: None,
+            metadata: MetadataPolicy::default(),
+            auto_orient: true,
+            target_size: None,
+            target_downscale: false,
+            target_ssim: None,
         })
     }
+
+    /// Sets how `width` and `height` are applied when resizing.
+    pub fn with_resize_mode(mut self, resize_mode: ResizeMode) -> Self {
+        self.resize_mode = resize_mode;
+        self
+    }
+
+    /// Sets the resampling filter used when resizing.
+    pub fn with_filter(mut self, filter: ResizeFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Sets which source metadata is carried into the output.
+    pub fn with_metadata(mut self, metadata: MetadataPolicy) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Sets whether the EXIF orientation is applied to the pixels on load.
+    pub fn with_auto_orient(mut self, auto_orient: bool) -> Self {
+        self.auto_orient = auto_orient;
+        self
+    }
+
+    /// Sets the output size budget in bytes.
+    ///
+    /// With `allow_downscale`, images that exceed the budget even at the minimum
+    /// quality are shrunk until they fit.
+    pub fn with_target_size(mut self, target_size: Option<u64>, allow_downscale: bool) -> Self {
+        self.target_size = target_size;
+        self.target_downscale = allow_downscale;
+        self
+    }
+
+    /// Sets the minimum SSIM (0-1) the output must score against the resized source.
+    pub fn with_target_ssim(mut self, target_ssim: Option<f64>) -> Self {
+        self.target_ssim = target_ssim;
+        self
+    }
+
+    /// Sets the JPEG-specific encoder options.
+    ///
+    /// These settings are ignored for every other output format.
+    pub fn with_jpeg_options(
+        mut self,
+        chroma_subsampling: ChromaSubsampling,
+        progressive: bool,
+        optimize_huffman: bool,
+    ) -> Self {
+        self.chroma_subsampling = chroma_subsampling;
+        self.progressive = progressive;
+        self.optimize_huffman = optimize_huffman;
+        self
+    }
+
+    /// Sets the WebP-specific encoder options.
+    ///
+    /// `method` is clamped to 0-6 and `alpha_quality`/`near_lossless` to 0-100.
+    /// Setting `near_lossless` switches the encoder to lossless mode.
+    pub fn with_webp_options(
+        mut self,
+        lossless: bool,
+        method: u8,
+        alpha_quality: u8,
+        near_lossless: Option<u8>,
+    ) -> Self {
+        self.webp_lossless = lossless || near_lossless.is_some();
+        self.webp_method = method.min(MAX_WEBP_METHOD);
+        self.webp_alpha_quality = alpha_quality.min(MAX_QUALITY);
+        self.webp_near_lossless = near_lossless.map(|level| level.min(MAX_QUALITY));
+        self
+    }
+}
+
+/// What the encoder settled on for one output image.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EncodeReport {
+    /// Size of the written file in bytes
+    pub compressed_size: u64,
+    /// Quality the output was encoded at
+    pub quality: u8,
+    /// Dimensions of the encoded image
+    pub dimensions: (u32, u32),
+    /// Whether the output meets `target_size` or `target_ssim`; `None` when no target was set
+    pub target_met: Option<bool>,
+    /// SSIM of the output against the resized source, when `target_ssim` was set
+    pub ssim: Option<f64>,
+}
+
+/// An image compressed in memory by `compress_bytes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressedImage {
+    /// The encoded output
+    pub data: Vec<u8>,
+    /// Format detected from the input's magic bytes
+    pub input_format: ImageFormat,
+    pub output_format: ImageFormat,
+    /// Size of the input in bytes
+    pub original_size: u64,
+    /// Dimensions of the input after auto-orientation
+    pub original_dimensions: (u32, u32),
+    /// Size, quality and dimensions of the output
+    pub encode: EncodeReport,
+}
+
+/// Outcome of compressing one input file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompressionReport {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// Output container, e.g. `jpeg` or `webp`
+    pub format: String,
+    /// Size of the input file in bytes
+    pub original_size: u64,
+    /// Dimensions of the input after auto-orientation
+    pub original_dimensions: (u32, u32),
+    #[serde(flatten)]
+    pub encode: EncodeReport,
+    /// Percentage of the input size saved (negative when the output grew)
+    pub compression_ratio: f64,
+    /// Requested size ceiling, if any
+    pub target_size: Option<u64>,
+    /// Requested SSIM floor, if any
+    pub target_ssim: Option<f64>,
+    /// Wall-clock time spent on this file
+    pub elapsed_secs: f64,
+    /// Walrus blob the output was uploaded as (`--upload`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob: Option<UploadedBlob>,
 }
 
 /// Validates that a file exists at the given path.
@@ -63,40 +324,175 @@ pub fn validate_file_exists(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Whether `path` is `-`, which stands for stdin as an input and stdout as an output
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
 /// Core image processing pipeline that handles the common workflow:
 /// load -> resize -> process -> save
 ///
 /// # Arguments
-/// * `input_path` - Path to the input image file
-/// * `output_path` - Path where the processed image will be saved
+/// * `input_path` - Path to the input image file, or `-` to read it from stdin
+/// * `output_path` - Path where the processed image will be saved, or `-` for stdout
 /// * `options` - Compression and processing options
 ///
 /// # Returns
-/// * `Ok((original_size, compressed_size))` - Tuple of file sizes in bytes
+/// * `Ok(report)` - Sizes, dimensions and encoder settings of the written file
 /// * `Err(CompressionError)` - If any processing step fails
 ///
 /// # Security
 /// - Validates file existence and canonical paths to prevent directory traversal
 /// - Enforces maximum file size and image dimension limits
-/// - Uses secure temporary file handling
 pub fn process_image_pipeline(
     input_path: &Path,
     output_path: &Path,
     options: &CompressionOptions,
-) -> Result<(u64, u64)> {
+) -> Result<CompressionReport> {
+    let start_time = Instant::now();
+    let (compressed, options) = compress_file_for(input_path, output_path, options)?;
+    write_output(output_path, &compressed.data)?;
+
+    Ok(CompressionReport::new(
+        input_path,
+        output_path,
+        compressed.output_format,
+        (compressed.original_size, compressed.original_dimensions),
+        compressed.encode,
+        &options,
+        start_time,
+    ))
+}
+
+/// Reads and compresses `input_path` the way `output_path` calls for, without writing it
+///
+/// # Returns
+/// * `Ok((compressed, options))` - The encoded output and the options with its format filled in
+/// * `Err(CompressionError)` - If the input cannot be read or compressed
+pub(crate) fn compress_file_for(
+    input_path: &Path,
+    output_path: &Path,
+    options: &CompressionOptions,
+) -> Result<(CompressedImage, CompressionOptions)> {
+    let output_format = determine_output_format(output_path, &options.format)?;
+
     // Load and validate image
-    let (mut img, original_size) = load_image_with_metadata(input_path)?;
+    let data = if is_stdio(input_path) {
+        read_limited(io::stdin().lock())?
+    } else {
+        read_input_file(input_path)?
+    };
+
+    // The output path decides the format when --format does not
+    let options = CompressionOptions {
+        format: Some(format!("{:?}", output_format).to_lowercase()),
+        ..options.clone()
+    };
+    let compressed = compress_bytes(&data, &options)?;
+    Ok((compressed, options))
+}
+
+/// Compresses an encoded image entirely in memory: decode -> resize -> encode.
+///
+/// The input format is detected from its magic bytes. Without `options.format`
+/// the output keeps the input format when it can be encoded, and is JPEG otherwise.
+///
+/// # Arguments
+/// * `data` - The encoded input image
+/// * `options` - Compression and processing options
+///
+/// # Returns
+/// * `Ok(CompressedImage)` - The encoded output with its formats, dimensions and sizes
+/// * `Err(CompressionError)` - If decoding or encoding fails or security limits are exceeded
+///
+/// # Example
+/// ```
+/// use img_squeeze::{compress_bytes, CompressionOptions};
+/// use image::{ImageFormat, RgbImage};
+/// use std::io::Cursor;
+///
+/// let mut png = Vec::new();
+/// RgbImage::new(64, 32)
+///     .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+///     .unwrap();
+///
+/// let options = CompressionOptions::new(Some(75), Some(32), None, Some("webp".to_string())).unwrap();
+/// let compressed = compress_bytes(&png, &options).unwrap();
+/// assert_eq!(compressed.input_format, ImageFormat::Png);
+/// assert_eq!(compressed.output_format, ImageFormat::WebP);
+/// assert_eq!(compressed.encode.dimensions, (32, 16));
+/// ```
+pub fn compress_bytes(data: &[u8], options: &CompressionOptions) -> Result<CompressedImage> {
+    let (mut img, input_format) = load_image_from_bytes(data, options.auto_orient)?;
+    let output_format = match &options.format {
+        Some(format) => parse_output_format(format)?,
+        None => match input_format {
+            ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Avif => {
+                input_format
+            }
+            _ => ImageFormat::Jpeg,
+        },
+    };
+    let metadata = source_metadata(
+        metadata_from_bytes_for_policy(data, options.metadata),
+        options,
+    );
+    let original_dimensions = img.dimensions();
 
     // Resize if needed
     resize_image(&mut img, options);
 
-    // Process and save
-    let compressed_size = process_and_save_image(&img, output_path, options)?;
+    let (encoded, encode) = encode_for_output(&img, output_format, options, &metadata)?;
+    Ok(CompressedImage {
+        data: encoded,
+        input_format,
+        output_format,
+        original_size: data.len() as u64,
+        original_dimensions,
+        encode,
+    })
+}
 
-    Ok((original_size, compressed_size))
+impl CompressionReport {
+    /// Assembles the report for one written output
+    ///
+    /// # Arguments
+    /// * `source` - Input file size and dimensions after auto-orientation
+    /// * `start_time` - When work on this output began
+    pub(crate) fn new(
+        input_path: &Path,
+        output_path: &Path,
+        output_format: ImageFormat,
+        source: (u64, (u32, u32)),
+        encode: EncodeReport,
+        options: &CompressionOptions,
+        start_time: Instant,
+    ) -> Self {
+        let (original_size, original_dimensions) = source;
+        let compression_ratio = if original_size > 0 {
+            ((original_size as f64 - encode.compressed_size as f64) / original_size as f64) * 100.0
+        } else {
+            0.0
+        };
+
+        Self {
+            input: input_path.to_path_buf(),
+            output: output_path.to_path_buf(),
+            format: format!("{:?}", output_format).to_lowercase(),
+            original_size,
+            original_dimensions,
+            encode,
+            compression_ratio,
+            target_size: options.target_size,
+            target_ssim: options.target_ssim,
+            elapsed_secs: start_time.elapsed().as_secs_f64(),
+            blob: None,
+        }
+    }
 }
 
-/// Loads an image file and returns it along with file metadata.
+/// Loads an image file, upright as its EXIF orientation tag says, and returns
+/// it along with file metadata.
 ///
 /// # Arguments
 /// * `input_path` - Path to the image file to load
@@ -111,6 +507,32 @@ pub fn process_image_pipeline(
 /// - Validates image dimensions to prevent memory exhaustion
 /// - Checks file size before attempting to load the image
 pub fn load_image_with_metadata(input_path: &Path) -> Result<(DynamicImage, u64)> {
+    let data = read_input_file(input_path)?;
+    let (img, _) = load_image_from_bytes(&data, true)?;
+    Ok((img, data.len() as u64))
+}
+
+/// Loads an image file the way `options` asks for, e.g. without applying its
+/// EXIF orientation when `options.auto_orient` is off.
+///
+/// # Arguments
+/// * `input_path` - Path to the image file to load
+/// * `options` - Processing options; only the load-time ones are used
+///
+/// # Returns
+/// * `Ok((image, file_size))` - The loaded image and its file size in bytes
+/// * `Err(CompressionError)` - If loading fails or security limits are exceeded
+pub fn load_image_with_options(
+    input_path: &Path,
+    options: &CompressionOptions,
+) -> Result<(DynamicImage, u64)> {
+    let data = read_input_file(input_path)?;
+    let (img, _) = load_image_from_bytes(&data, options.auto_orient)?;
+    Ok((img, data.len() as u64))
+}
+
+/// Reads an input image file after the path and size checks of `load_image_with_metadata`
+fn read_input_file(input_path: &Path) -> Result<Vec<u8>> {
     validate_file_exists(input_path)?;
 
     // Check for unsupported input formats and provide helpful guidance
@@ -141,7 +563,69 @@ pub fn load_image_with_metadata(input_path: &Path) -> Result<(DynamicImage, u64)
         return Err(CompressionError::FileTooLarge(file_size, MAX_FILE_SIZE));
     }
 
-    let img = ImageReader::open(&canonical_path)?.decode()?;
+    read_limited(File::open(&canonical_path)?)
+}
+
+/// Decodes an encoded image held in memory, detecting its format from its magic bytes.
+///
+/// # Arguments
+/// * `data` - The encoded image, e.g. as read from stdin
+/// * `auto_orient` - Rotate/flip the pixels upright as the EXIF orientation tag says
+///
+/// # Returns
+/// * `Ok((image, format))` - The decoded image and the detected input format
+/// * `Err(CompressionError)` - If the format is not recognized, decoding fails or
+///   security limits are exceeded
+pub fn load_image_from_bytes(
+    data: &[u8],
+    auto_orient: bool,
+) -> Result<(DynamicImage, ImageFormat)> {
+    // Check the size before decoding to prevent DoS attacks
+    if data.len() as u64 > MAX_FILE_SIZE {
+        return Err(CompressionError::FileTooLarge(
+            data.len() as u64,
+            MAX_FILE_SIZE,
+        ));
+    }
+    let format = image::guess_format(data).map_err(|_| {
+        CompressionError::UnsupportedFormat(
+            "cannot detect the image format from its contents".to_string(),
+        )
+    })?;
+    let img = decode_oriented(
+        ImageReader::with_format(Cursor::new(data), format),
+        auto_orient,
+    )?;
+    Ok((img, format))
+}
+
+/// Reads a whole input stream, refusing streams larger than `MAX_FILE_SIZE`
+pub(crate) fn read_limited<R: Read>(reader: R) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(MAX_FILE_SIZE + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_FILE_SIZE {
+        return Err(CompressionError::FileTooLarge(
+            data.len() as u64,
+            MAX_FILE_SIZE,
+        ));
+    }
+    Ok(data)
+}
+
+/// Decodes an image, applies its EXIF orientation if asked to and enforces
+/// the dimension limit.
+fn decode_oriented<R: BufRead + Seek>(
+    reader: ImageReader<R>,
+    auto_orient: bool,
+) -> Result<DynamicImage> {
+    let mut decoder = reader.into_decoder()?;
+    let orientation = if auto_orient {
+        decoder.orientation()?
+    } else {
+        Orientation::NoTransforms
+    };
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
 
     // Security: Validate image dimensions to prevent DoS attacks
     let (width, height) = img.dimensions();
@@ -153,97 +637,488 @@ pub fn load_image_with_metadata(input_path: &Path) -> Result<(DynamicImage, u64)
         ));
     }
 
-    Ok((img, file_size))
+    Ok(img)
+}
+
+/// Reads the source metadata kept by `options.metadata`.
+///
+/// When `options.auto_orient` is set the pixels are already upright, so the
+/// kept EXIF orientation is reset to keep viewers from rotating them again.
+pub(crate) fn read_source_metadata(
+    input_path: &Path,
+    options: &CompressionOptions,
+) -> Result<ImageMetadata> {
+    Ok(source_metadata(
+        read_metadata_for_policy(input_path, options.metadata)?,
+        options,
+    ))
+}
+
+fn source_metadata(mut metadata: ImageMetadata, options: &CompressionOptions) -> ImageMetadata {
+    if options.auto_orient {
+        metadata.reset_orientation();
+    }
+    metadata
+}
+
+/// Calculates the dimensions an image should be resampled to.
+///
+/// # Arguments
+/// * `source` - Current `(width, height)` of the image
+/// * `width` - Requested width; `None` or `0` leaves it unconstrained
+/// * `height` - Requested height; `None` or `0` leaves it unconstrained
+/// * `mode` - How the requested box is applied
+///
+/// # Returns
+/// * `Some((width, height))` - Dimensions to resample to, before any `Fill` crop
+/// * `None` - If no resampling is needed
+pub fn calculate_resize_dimensions(
+    source: (u32, u32),
+    width: Option<u32>,
+    height: Option<u32>,
+    mode: ResizeMode,
+) -> Option<(u32, u32)> {
+    let (src_w, src_h) = source;
+    let width = width.filter(|&w| w > 0);
+    let height = height.filter(|&h| h > 0);
+    if src_w == 0 || src_h == 0 || (width.is_none() && height.is_none()) {
+        return None;
+    }
+
+    let target = if mode == ResizeMode::Exact {
+        (width.unwrap_or(src_w), height.unwrap_or(src_h))
+    } else {
+        let scale_w = width.map(|w| w as f64 / src_w as f64);
+        let scale_h = height.map(|h| h as f64 / src_h as f64);
+        let mut scale = match (scale_w, scale_h) {
+            (Some(sw), Some(sh)) => match mode {
+                ResizeMode::Fill | ResizeMode::Cover => sw.max(sh),
+                _ => sw.min(sh),
+            },
+            (Some(s), None) | (None, Some(s)) => s,
+            (None, None) => unreachable!(),
+        };
+        if mode == ResizeMode::NoUpscale {
+            scale = scale.min(1.0);
+        }
+        (
+            ((src_w as f64 * scale).round() as u32).max(1),
+            ((src_h as f64 * scale).round() as u32).max(1),
+        )
+    };
+
+    (target != source).then_some(target)
 }
 
+/// Resizes an image in a single resampling pass according to `options`.
+///
+/// `Fill` additionally center-crops the result to the requested box.
 pub fn resize_image(img: &mut DynamicImage, options: &CompressionOptions) {
-    if let Some(w) = options.width.filter(|&w| w > 0 && w != img.width()) {
-        println!("🔄 Resizing width...");
-        *img = img.resize_exact(w, img.height(), image::imageops::FilterType::Lanczos3);
-        println!("✅ Resized to width: {}", w);
+    let resized = match resized_image(img, options) {
+        Cow::Owned(resized) => resized,
+        Cow::Borrowed(_) => return,
+    };
+    *img = resized;
+}
+
+/// Like `resize_image`, but leaves `img` untouched.
+///
+/// # Returns
+/// * The resized image in a new buffer, or `img` itself if `options` leave it unchanged
+pub fn resized_image<'a>(
+    img: &'a DynamicImage,
+    options: &CompressionOptions,
+) -> Cow<'a, DynamicImage> {
+    let mut resized = calculate_resize_dimensions(
+        img.dimensions(),
+        options.width,
+        options.height,
+        options.resize_mode,
+    )
+    .map(|(w, h)| img.resize_exact(w, h, options.filter.filter_type()));
+
+    if options.resize_mode == ResizeMode::Fill {
+        if let (Some(w), Some(h)) = (
+            options.width.filter(|&w| w > 0),
+            options.height.filter(|&h| h > 0),
+        ) {
+            let current = resized.as_ref().unwrap_or(img);
+            let (cur_w, cur_h) = current.dimensions();
+            let (crop_w, crop_h) = (w.min(cur_w), h.min(cur_h));
+            if (crop_w, crop_h) != (cur_w, cur_h) {
+                let x = (cur_w - crop_w) / 2;
+                let y = (cur_h - crop_h) / 2;
+                resized = Some(current.crop_imm(x, y, crop_w, crop_h));
+            }
+        }
     }
 
-    if let Some(h) = options.height.filter(|&h| h > 0 && h != img.height()) {
-        println!("🔄 Resizing height...");
-        *img = img.resize_exact(img.width(), h, image::imageops::FilterType::Lanczos3);
-        println!("✅ Resized to height: {}", h);
+    match resized {
+        Some(resized) => Cow::Owned(resized),
+        None => Cow::Borrowed(img),
     }
 }
 
+/// Encodes an image per `options` and writes the result to `output_path`,
+/// or to stdout if it is `-`.
+///
+/// No source metadata is written; use `encode_for_output` to embed it.
+///
+/// # Returns
+/// * `Ok(size)` - Size of the written file in bytes
+/// * `Err(CompressionError)` - If encoding or writing fails
 pub fn process_and_save_image(
     img: &DynamicImage,
     output_path: &Path,
     options: &CompressionOptions,
 ) -> Result<u64> {
-    let output_buf = output_path.to_path_buf();
     let output_format = determine_output_format(output_path, &options.format)?;
-    save_image(img, &output_buf, output_format, options)?;
+    let (data, report) = encode_for_output(img, output_format, options, &ImageMetadata::default())?;
+    write_output(output_path, &data)?;
+    Ok(report.compressed_size)
+}
 
-    let compressed_size = fs::metadata(output_path)?.len();
-    Ok(compressed_size)
+/// Encodes an image as `format` per `options` and writes it to `output`,
+/// or to stdout if it is `-`. No source metadata is written.
+pub fn save_image(
+    img: &DynamicImage,
+    output: &Path,
+    format: ImageFormat,
+    options: &CompressionOptions,
+) -> Result<()> {
+    let (data, _) = encode_for_output(img, format, options, &ImageMetadata::default())?;
+    write_output(output, &data)
 }
 
-pub fn compress_image(input: PathBuf, output: PathBuf, options: CompressionOptions) -> Result<()> {
-    println!("🗜️  Compressing image: {:?}", input);
-    println!("📁 Output: {:?}", output);
+/// Writes encoded bytes to `output_path`, creating its directory, or to stdout for `-`
+pub(crate) fn write_output(output_path: &Path, data: &[u8]) -> Result<()> {
+    if is_stdio(output_path) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|_| CompressionError::DirectoryCreationFailed(parent.to_path_buf()))?;
+    }
+    fs::write(output_path, data)?;
+    Ok(())
+}
+
+/// Encodes an image in memory per `options` and embeds `metadata`.
+///
+/// With `options.target_ssim` or `options.target_size` set, the quality is
+/// searched instead of taken as is.
+///
+/// # Returns
+/// * `Ok((bytes, report))` - The encoded image and the settings that produced it
+/// * `Err(CompressionError)` - If encoding or re-muxing fails
+pub fn encode_for_output(
+    img: &DynamicImage,
+    format: ImageFormat,
+    options: &CompressionOptions,
+    metadata: &ImageMetadata,
+) -> Result<(Vec<u8>, EncodeReport)> {
+    if let Some(threshold) = options.target_ssim {
+        encode_to_target_ssim(img, format, options, metadata, threshold)
+    } else if let Some(target) = options.target_size {
+        encode_to_target_size(img, format, options, metadata, target)
+    } else {
+        let data = encode_with_metadata(img, format, options, metadata)?;
+        let report = EncodeReport {
+            compressed_size: data.len() as u64,
+            quality: options.quality,
+            dimensions: img.dimensions(),
+            target_met: None,
+            ssim: None,
+        };
+        Ok((data, report))
+    }
+}
 
+/// Encodes `img` at the highest quality up to `options.quality` whose output,
+/// metadata included, fits in `target` bytes.
+///
+/// Lossless outputs (PNG, lossless WebP) are not searched: quality only trades
+/// effort for size there, so they are encoded once at `options.quality`.
+/// If even the minimum quality is too large and `options.target_downscale` is set,
+/// the image is shrunk and the search repeated; otherwise the minimum-quality
+/// output is returned with `target_met: Some(false)`.
+///
+/// # Returns
+/// * `Ok((bytes, report))` - The encoded image and the settings that produced it
+/// * `Err(CompressionError)` - If encoding or re-muxing fails
+pub fn encode_to_target_size(
+    img: &DynamicImage,
+    format: ImageFormat,
+    options: &CompressionOptions,
+    metadata: &ImageMetadata,
+    target: u64,
+) -> Result<(Vec<u8>, EncodeReport)> {
+    // 只有缩小后才持有新的图像，原图直接借用
+    let mut candidate = Cow::Borrowed(img);
+    let mut downscales = 0;
+    loop {
+        let (quality, data) = search_quality(&candidate, format, options, metadata, target)?;
+        let size = data.len() as u64;
+        let fits = size <= target;
+        let (width, height) = candidate.dimensions();
+
+        if fits
+            || !options.target_downscale
+            || downscales == MAX_TARGET_SIZE_DOWNSCALES
+            || (width, height) == (1, 1)
+        {
+            let report = EncodeReport {
+                compressed_size: size,
+                quality,
+                dimensions: (width, height),
+                target_met: Some(fits),
+                ssim: None,
+            };
+            return Ok((data, report));
+        }
+
+        // Encoded size grows roughly with pixel count, so scale each side by the root
+        let scale = (target as f64 / size as f64).sqrt() * TARGET_SIZE_DOWNSCALE_MARGIN;
+        let new_width = ((width as f64 * scale).round() as u32).clamp(1, width);
+        let new_height = ((height as f64 * scale).round() as u32).clamp(1, height);
+        candidate =
+            Cow::Owned(img.resize_exact(new_width, new_height, options.filter.filter_type()));
+        downscales += 1;
+    }
+}
+
+/// Encodes `img` at the lowest quality up to `options.quality` whose decoded
+/// output scores at least `threshold` SSIM against `img`.
+///
+/// Lossless outputs (PNG, lossless WebP) are encoded once at `options.quality`,
+/// since quality only trades effort for size there. If even `options.quality`
+/// misses the threshold, that output is returned with `target_met: Some(false)`.
+///
+/// # Returns
+/// * `Ok((bytes, report))` - The encoded image and the settings that produced it
+/// * `Err(CompressionError)` - If encoding or decoding fails, or the format is AVIF,
+///   which cannot be decoded for scoring
+pub fn encode_to_target_ssim(
+    img: &DynamicImage,
+    format: ImageFormat,
+    options: &CompressionOptions,
+    metadata: &ImageMetadata,
+    threshold: f64,
+) -> Result<(Vec<u8>, EncodeReport)> {
+    if format == ImageFormat::Avif {
+        return Err(CompressionError::UnsupportedFormat(
+            "AVIF output cannot be decoded for SSIM scoring; use --target-size or --quality"
+                .to_string(),
+        ));
+    }
+
+    let score_at = |quality: u8| -> Result<(Vec<u8>, f64)> {
+        let options = CompressionOptions {
+            quality,
+            ..options.clone()
+        };
+        let data = encode_with_metadata(img, format, &options, metadata)?;
+        let decoded = image::load_from_memory_with_format(&data, format)?;
+        let score = ssim(img, &decoded)?;
+        Ok((data, score))
+    };
+    let report = |quality: u8, data: &[u8], score: f64| EncodeReport {
+        compressed_size: data.len() as u64,
+        quality,
+        dimensions: img.dimensions(),
+        target_met: Some(score >= threshold),
+        ssim: Some(score),
+    };
+
+    let max_quality = options.quality;
+    let (data, score) = score_at(max_quality)?;
+    if is_lossless(format, options) || score < threshold || max_quality == MIN_QUALITY {
+        let report = report(max_quality, &data, score);
+        return Ok((data, report));
+    }
+
+    // Invariant: `high` meets the threshold, `low` does not (or is below the range)
+    let (mut low, mut high) = (MIN_QUALITY - 1, max_quality);
+    let mut best = (data, score);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        let (data, score) = score_at(mid)?;
+        if score >= threshold {
+            high = mid;
+            best = (data, score);
+        } else {
+            low = mid;
+        }
+    }
+    let (data, score) = best;
+    let report = report(high, &data, score);
+    Ok((data, report))
+}
+
+/// Binary-searches the highest quality in `MIN_QUALITY..=options.quality` that fits `target`.
+///
+/// Returns the minimum-quality output when nothing fits, and the only
+/// output for lossless formats.
+fn search_quality(
+    img: &DynamicImage,
+    format: ImageFormat,
+    options: &CompressionOptions,
+    metadata: &ImageMetadata,
+    target: u64,
+) -> Result<(u8, Vec<u8>)> {
+    let encode_at = |quality: u8| {
+        let options = CompressionOptions {
+            quality,
+            ..options.clone()
+        };
+        encode_with_metadata(img, format, &options, metadata)
+    };
+    let fits = |data: &[u8]| data.len() as u64 <= target;
+
+    let max_quality = options.quality;
+    let data = encode_at(max_quality)?;
+    if fits(&data) || max_quality == MIN_QUALITY || is_lossless(format, options) {
+        return Ok((max_quality, data));
+    }
+
+    let data = encode_at(MIN_QUALITY)?;
+    if !fits(&data) {
+        return Ok((MIN_QUALITY, data));
+    }
+
+    // Invariant: `low` fits, `high` does not
+    let (mut low, mut high) = (MIN_QUALITY, max_quality);
+    let mut best = data;
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        let data = encode_at(mid)?;
+        if fits(&data) {
+            low = mid;
+            best = data;
+        } else {
+            high = mid;
+        }
+    }
+    Ok((low, best))
+}
+
+/// Whether `format` is encoded losslessly, so quality only changes encoder effort
+fn is_lossless(format: ImageFormat, options: &CompressionOptions) -> bool {
+    format == ImageFormat::Png || (format == ImageFormat::WebP && options.webp_lossless)
+}
+
+/// Encodes an image and embeds `metadata` into the result.
+fn encode_with_metadata(
+    img: &DynamicImage,
+    format: ImageFormat,
+    options: &CompressionOptions,
+    metadata: &ImageMetadata,
+) -> Result<Vec<u8>> {
+    let data = encode_image(img, format, options)?;
+    if metadata.is_empty() {
+        return Ok(data);
+    }
+    metadata.embed(data, format)
+}
+
+/// Compresses a single image with a progress spinner on stderr.
+///
+/// # Returns
+/// * `Ok(report)` - What was written; render it with `print_compression_report`
+/// * `Err(CompressionError)` - If any processing step fails
+pub fn compress_image(
+    input: PathBuf,
+    output: PathBuf,
+    options: CompressionOptions,
+) -> Result<CompressionReport> {
     let pb = ProgressBar::new_spinner();
     pb.set_style(
         ProgressStyle::default_spinner()
             .template("{spinner:.green} {msg}")
             .unwrap(),
     );
-    pb.set_message("Loading image...");
+    pb.set_message("Compressing image...");
 
-    let (mut img, original_size) = load_image_with_metadata(&input)?;
-    pb.finish_with_message("✅ Image loaded");
+    let report = process_image_pipeline(&input, &output, &options)?;
+    pb.finish_and_clear();
+
+    Ok(report)
+}
 
+/// Prints a compression report
+pub fn print_compression_report(report: &CompressionReport) {
+    let (orig_w, orig_h) = report.original_dimensions;
+    let (width, height) = report.encode.dimensions;
+
+    println!("🗜️  Compressing image: {:?}", report.input);
+    println!("📁 Output: {:?}", report.output);
     println!(
         "📊 Original size: {} bytes ({}x{})",
-        original_size,
-        img.width(),
-        img.height()
+        report.original_size, orig_w, orig_h
     );
+    if (width, height) != (orig_w, orig_h) {
+        println!("🔄 Resized to: {}x{}", width, height);
+    }
+    println!(
+        "📈 Compressed size: {} bytes",
+        report.encode.compressed_size
+    );
+    println!("🎯 Compression ratio: {:.1}%", report.compression_ratio);
 
-    // Resize if needed
-    resize_image(&mut img, &options);
-
-    pb.set_message("Saving compressed image...");
-    let compressed_size = process_and_save_image(&img, &output, &options)?;
-    pb.finish_with_message("✅ Compression complete");
-    let compression_ratio =
-        ((original_size as f64 - compressed_size as f64) / original_size as f64) * 100.0;
-
-    println!("📈 Compressed size: {} bytes", compressed_size);
-    println!("🎯 Compression ratio: {:.1}%", compression_ratio);
+    if let (Some(threshold), Some(score)) = (report.target_ssim, report.encode.ssim) {
+        println!(
+            "🎚️  Chosen quality: {} (SSIM {:.4}) for target SSIM {}",
+            report.encode.quality, score, threshold
+        );
+        if report.encode.target_met == Some(false) {
+            println!(
+                "⚠️  Could not reach the target SSIM even at quality {}",
+                report.encode.quality
+            );
+        }
+    } else if let Some(target) = report.target_size {
+        println!(
+            "🎚️  Chosen quality: {} ({}x{}) for target of {} bytes",
+            report.encode.quality, width, height, target
+        );
+        if report.encode.target_met == Some(false) {
+            println!("⚠️  Could not reach the target size even at minimum quality");
+        }
+    }
 
-    if compression_ratio > 0.0 {
+    if report.compression_ratio > 0.0 {
         println!(
             "✅ Successfully reduced file size by {:.1}%",
-            compression_ratio
+            report.compression_ratio
         );
     } else {
-        println!("⚠️  File size increased by {:.1}%", compression_ratio.abs());
+        println!(
+            "⚠️  File size increased by {:.1}%",
+            report.compression_ratio.abs()
+        );
     }
 
-    Ok(())
+    if let Some(blob) = &report.blob {
+        println!("🆔 Blob ID: {}", blob.blob_id);
+        println!("🌐 Access URL: {}", blob.access_url);
+        println!("📅 Stored until epoch: {}", blob.end_epoch);
+        if !blob.kept_local {
+            println!("☁️  Uploaded only, no local file was written");
+        }
+    }
 }
 
 pub fn determine_output_format(output: &Path, format: &Option<String>) -> Result<ImageFormat> {
+    if format.is_none() && is_stdio(output) {
+        return Err(CompressionError::UnsupportedFormat(
+            "--format is required when writing to stdout".to_string(),
+        ));
+    }
     if let Some(fmt) = format {
-        match fmt.to_lowercase().as_str() {
-            "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
-            "png" => Ok(ImageFormat::Png),
-            "webp" => Ok(ImageFormat::WebP),
-            "avif" => Ok(ImageFormat::Avif),
-            "heic" | "heif" => Err(CompressionError::UnsupportedFormat(
-                format!("{} format is not yet supported in this version. Use AVIF for modern compression", fmt)
-            )),
-            "jxl" | "jpegxl" => Err(CompressionError::UnsupportedFormat(
-                format!("{} format is not yet supported in this version. Use AVIF for modern compression", fmt)
-            )),
-            _ => Err(CompressionError::UnsupportedFormat(fmt.clone())),
-        }
+        parse_output_format(fmt)
     } else if let Some(ext) = output.extension().and_then(|ext| ext.to_str()) {
         let ext = ext.to_ascii_lowercase();
         match ext.as_str() {
@@ -251,12 +1126,14 @@ pub fn determine_output_format(output: &Path, format: &Option<String>) -> Result
             "png" => Ok(ImageFormat::Png),
             "webp" => Ok(ImageFormat::WebP),
             "avif" => Ok(ImageFormat::Avif),
-            "heic" | "heif" => Err(CompressionError::UnsupportedFormat(
-                format!("{} format is not yet supported in this version. Use AVIF for modern compression", ext)
-            )),
-            "jxl" | "jpegxl" => Err(CompressionError::UnsupportedFormat(
-                format!("{} format is not yet supported in this version. Use AVIF for modern compression", ext)
-            )),
+            "heic" | "heif" => Err(CompressionError::UnsupportedFormat(format!(
+                "{} format is not yet supported in this version. Use AVIF for modern compression",
+                ext
+            ))),
+            "jxl" | "jpegxl" => Err(CompressionError::UnsupportedFormat(format!(
+                "{} format is not yet supported in this version. Use AVIF for modern compression",
+                ext
+            ))),
             _ => Ok(ImageFormat::Jpeg),
         }
     } else {
@@ -264,41 +1141,50 @@ pub fn determine_output_format(output: &Path, format: &Option<String>) -> Result
     }
 }
 
-pub fn save_image(
+/// Parses a `--format` value
+fn parse_output_format(fmt: &str) -> Result<ImageFormat> {
+    match fmt.to_lowercase().as_str() {
+        "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+        "png" => Ok(ImageFormat::Png),
+        "webp" => Ok(ImageFormat::WebP),
+        "avif" => Ok(ImageFormat::Avif),
+        "heic" | "heif" => Err(CompressionError::UnsupportedFormat(format!(
+            "{} format is not yet supported in this version. Use AVIF for modern compression",
+            fmt
+        ))),
+        "jxl" | "jpegxl" => Err(CompressionError::UnsupportedFormat(format!(
+            "{} format is not yet supported in this version. Use AVIF for modern compression",
+            fmt
+        ))),
+        _ => Err(CompressionError::UnsupportedFormat(fmt.to_string())),
+    }
+}
+
+/// Encodes an image in memory in the given output format.
+///
+/// # Arguments
+/// * `img` - The image to encode
+/// * `format` - Output container; JPEG, PNG, WebP and AVIF are supported
+/// * `options` - Compression options controlling the encoder
+///
+/// # Returns
+/// * `Ok(bytes)` - The encoded image
+/// * `Err(CompressionError)` - If the format is unsupported or encoding fails
+pub fn encode_image(
     img: &DynamicImage,
-    output: &PathBuf,
     format: ImageFormat,
     options: &CompressionOptions,
-) -> Result<()> {
-    if let Some(parent) = output.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|_| CompressionError::DirectoryCreationFailed(parent.to_path_buf()))?;
-    }
+) -> Result<Vec<u8>> {
+    let mut encoded = Vec::new();
 
     match format {
-        ImageFormat::Jpeg => {
-            img.save_with_format(output, image::ImageFormat::Jpeg)?;
-        }
+        ImageFormat::Jpeg => encode_jpeg(img, &mut encoded, options)?,
         ImageFormat::Png => {
-            // 使用 oxipng 进行 PNG 优化
-            let (_width, _height) = img.dimensions();
-
-            // Performance: Use secure temp file with proper cleanup
-            let temp_path = output.with_extension("temp.png");
-            img.save_with_format(&temp_path, image::ImageFormat::Png)?;
-
-            // Performance: Ensure cleanup on any error using RAII pattern
-            struct TempFileGuard(PathBuf);
-            impl Drop for TempFileGuard {
-                fn drop(&mut self) {
-                    let _ = fs::remove_file(&self.0);
-                }
-            }
-            let _guard = TempFileGuard(temp_path.clone());
+            let mut png = Vec::new();
+            img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
 
             // 配置 oxipng 选项
             let mut oxipng_options = Options::from_preset(4); // 使用预设 4 (最高压缩)
-            oxipng_options.force = true; // 强制覆盖
 
             // 根据质量设置调整压缩级别
             if options.quality >= 90 {
@@ -315,42 +1201,119 @@ pub fn save_image(
                 };
             }
 
-            // 使用 oxipng 优化文件
-            let input = InFile::Path(temp_path.clone());
-            let out = OutFile::Path {
-                path: Some(output.clone()),
-                preserve_attrs: false,
-            };
-            oxipng::optimize(&input, &out, &oxipng_options)
+            // 使用 oxipng 优化
+            encoded = oxipng::optimize_from_memory(&png, &oxipng_options)
                 .map_err(|e| CompressionError::PngOptimization(e.to_string()))?;
-
-            // Temp file automatically cleaned up by guard
-        }
-        ImageFormat::WebP => {
-            img.save_with_format(output, image::ImageFormat::WebP)?;
         }
+        ImageFormat::WebP => encode_webp(img, &mut encoded, options)?,
         ImageFormat::Avif => {
             // Honor quality and enable parallel encoding (when "image/rayon" is enabled).
             use image::codecs::avif::AvifEncoder;
             let rgba = img.to_rgba8();
             let (w, h) = (rgba.width(), rgba.height());
-            let mut file = std::fs::File::create(output)?;
             // Heuristic speed; consider surfacing as an option later.
             let speed: u8 = 6;
-            let enc = AvifEncoder::new_with_speed_quality(&mut file, speed, options.quality);
+            let enc = AvifEncoder::new_with_speed_quality(&mut encoded, speed, options.quality);
             // If you later thread this via CLI, call: enc = enc.with_num_threads(Some(n));
-            enc.write_image(
-                rgba.as_raw(),
-                w,
-                h,
-                image::ExtendedColorType::Rgba8
-            )?;
+            enc.write_image(rgba.as_raw(), w, h, image::ExtendedColorType::Rgba8)?;
         }
         _ => {
             return Err(CompressionError::UnsupportedFormat(format!("{:?}", format)));
         }
     }
 
+    Ok(encoded)
+}
+
+/// Encodes an image as JPEG honoring quality, chroma subsampling, progressive
+/// scans and Huffman table optimization from `options`.
+///
+/// # Arguments
+/// * `img` - The image to encode. Alpha channels are discarded.
+/// * `writer` - Destination for the encoded JPEG stream
+/// * `options` - Compression options controlling the encoder
+///
+/// # Returns
+/// * `Ok(())` if encoding succeeds
+/// * `Err(CompressionError)` if the image is too large for JPEG or encoding fails
+pub fn encode_jpeg<W: Write>(
+    img: &DynamicImage,
+    writer: W,
+    options: &CompressionOptions,
+) -> Result<()> {
+    let (width, height) = img.dimensions();
+    // JPEG stores dimensions as 16-bit values
+    let (w, h) = match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(w), Ok(h)) => (w, h),
+        _ => {
+            return Err(CompressionError::InvalidDimensions(
+                width,
+                height,
+                u16::MAX as u32,
+            ))
+        }
+    };
+
+    let mut encoder = JpegEncoder::new(writer, options.quality);
+    encoder.set_sampling_factor(options.chroma_subsampling.sampling_factor());
+    encoder.set_progressive(options.progressive);
+    encoder.set_optimized_huffman_tables(options.optimize_huffman);
+
+    let result = if img.color().has_color() {
+        let rgb = img.to_rgb8();
+        encoder.encode(rgb.as_raw(), w, h, JpegColorType::Rgb)
+    } else {
+        let luma = img.to_luma8();
+        encoder.encode(luma.as_raw(), w, h, JpegColorType::Luma)
+    };
+
+    result.map_err(|e| CompressionError::JpegEncoding(e.to_string()))
+}
+
+/// Encodes an image as WebP using libwebp, lossy by default.
+///
+/// # Arguments
+/// * `img` - The image to encode. Alpha is preserved when present.
+/// * `writer` - Destination for the encoded WebP stream
+/// * `options` - Compression options; `quality` drives the lossy encoder
+///   (or compression effort in lossless mode)
+///
+/// # Returns
+/// * `Ok(())` if encoding succeeds
+/// * `Err(CompressionError)` if libwebp rejects the configuration or image
+pub fn encode_webp<W: Write>(
+    img: &DynamicImage,
+    mut writer: W,
+    options: &CompressionOptions,
+) -> Result<()> {
+    let mut config = webp::WebPConfig::new().map_err(|_| {
+        CompressionError::WebPEncoding("Failed to initialize encoder config".to_string())
+    })?;
+    config.quality = options.quality as f32;
+    config.method = options.webp_method.min(MAX_WEBP_METHOD) as i32;
+    config.alpha_quality = options.webp_alpha_quality as i32;
+    config.lossless = options.webp_lossless as i32;
+    if let Some(level) = options.webp_near_lossless {
+        config.lossless = 1;
+        config.near_lossless = level as i32;
+    } else if options.webp_lossless {
+        // Preserve RGB values under fully transparent pixels for true lossless output
+        config.exact = 1;
+    }
+    // Keep the alpha plane lossless too when the colour data is lossless
+    config.alpha_compression = if config.lossless == 1 { 0 } else { 1 };
+
+    let (width, height) = img.dimensions();
+    let encoded = if img.color().has_alpha() {
+        let rgba = img.to_rgba8();
+        webp::Encoder::from_rgba(rgba.as_raw(), width, height).encode_advanced(&config)
+    } else {
+        let rgb = img.to_rgb8();
+        webp::Encoder::from_rgb(rgb.as_raw(), width, height).encode_advanced(&config)
+    }
+    .map_err(|e| CompressionError::WebPEncoding(format!("{:?}", e)))?;
+
+    writer.write_all(&encoded)?;
     Ok(())
 }
 
@@ -378,6 +1341,87 @@ mod tests {
         assert_eq!(options.format, None);
     }
 
+    #[test]
+    fn test_compression_options_jpeg_defaults() {
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        assert_eq!(options.chroma_subsampling, ChromaSubsampling::Yuv420);
+        assert!(!options.progressive);
+        assert!(!options.optimize_huffman);
+
+        let options = options.with_jpeg_options(ChromaSubsampling::Yuv444, true, true);
+        assert_eq!(options.chroma_subsampling, ChromaSubsampling::Yuv444);
+        assert!(options.progressive);
+        assert!(options.optimize_huffman);
+    }
+
+    #[test]
+    fn test_encode_jpeg_quality_affects_size() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(128, 128, |x, y| {
+            image::Rgb([(x * 2) as u8, (y * 2) as u8, ((x ^ y) * 7) as u8])
+        }));
+
+        let mut high = Vec::new();
+        let options = CompressionOptions::new(Some(95), None, None, None).unwrap();
+        encode_jpeg(&img, &mut high, &options).unwrap();
+
+        let mut low = Vec::new();
+        let options = CompressionOptions::new(Some(20), None, None, None).unwrap();
+        encode_jpeg(&img, &mut low, &options).unwrap();
+
+        assert!(low.len() < high.len());
+        assert_eq!(&low[..2], &[0xFF, 0xD8]);
+    }
+
+    #[test]
+    fn test_compression_options_webp_options() {
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        assert!(!options.webp_lossless);
+        assert_eq!(options.webp_method, 4);
+        assert_eq!(options.webp_alpha_quality, 100);
+        assert_eq!(options.webp_near_lossless, None);
+
+        let options = options.with_webp_options(false, 9, 150, Some(60));
+        assert!(options.webp_lossless); // near-lossless implies lossless
+        assert_eq!(options.webp_method, 6);
+        assert_eq!(options.webp_alpha_quality, 100);
+        assert_eq!(options.webp_near_lossless, Some(60));
+    }
+
+    #[test]
+    fn test_encode_webp_lossy_quality_affects_size() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(128, 128, |x, y| {
+            image::Rgb([(x * 2) as u8, (y * 2) as u8, ((x ^ y) * 7) as u8])
+        }));
+
+        let mut high = Vec::new();
+        let options = CompressionOptions::new(Some(95), None, None, None).unwrap();
+        encode_webp(&img, &mut high, &options).unwrap();
+
+        let mut low = Vec::new();
+        let options = CompressionOptions::new(Some(20), None, None, None).unwrap();
+        encode_webp(&img, &mut low, &options).unwrap();
+
+        assert!(low.len() < high.len());
+        assert_eq!(&low[..4], b"RIFF");
+        assert_eq!(&low[8..12], b"WEBP");
+    }
+
+    #[test]
+    fn test_encode_webp_lossless_round_trip() {
+        let img = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(32, 32, |x, y| {
+            image::Rgba([x as u8 * 8, y as u8 * 8, 128, (x + y) as u8 * 4])
+        }));
+        let options = CompressionOptions::new(Some(80), None, None, None)
+            .unwrap()
+            .with_webp_options(true, 4, 100, None);
+
+        let mut encoded = Vec::new();
+        encode_webp(&img, &mut encoded, &options).unwrap();
+
+        let decoded = image::load_from_memory(&encoded).unwrap();
+        assert_eq!(decoded.to_rgba8(), img.to_rgba8());
+    }
+
     #[test]
     fn test_compression_options_invalid_quality() {
         let result = CompressionOptions::new(Some(0), None, None, None);
@@ -441,7 +1485,7 @@ mod tests {
             assert!(msg.contains("AVIF"));
         }
 
-        // Test JPEG XL recognition with helpful error message  
+        // Test JPEG XL recognition with helpful error message
         let result = determine_output_format(path, &Some("jxl".to_string()));
         assert!(matches!(
             result,
@@ -454,7 +1498,10 @@ mod tests {
 
         // Alias: jpegxl should behave like jxl
         let result = determine_output_format(path, &Some("jpegxl".to_string()));
-        assert!(matches!(result, Err(CompressionError::UnsupportedFormat(_))));
+        assert!(matches!(
+            result,
+            Err(CompressionError::UnsupportedFormat(_))
+        ));
         if let Err(CompressionError::UnsupportedFormat(msg)) = result {
             assert!(msg.contains("not yet supported"));
             assert!(msg.contains("AVIF"));
@@ -471,7 +1518,10 @@ mod tests {
         // Unsupported alias via extension
         let path = Path::new("photo.jpegxl");
         let result = determine_output_format(path, &None);
-        assert!(matches!(result, Err(CompressionError::UnsupportedFormat(_))));
+        assert!(matches!(
+            result,
+            Err(CompressionError::UnsupportedFormat(_))
+        ));
     }
 
     #[test]
@@ -481,7 +1531,8 @@ mod tests {
 
         resize_image(&mut img, &options);
 
-        assert_eq!(img.dimensions(), (1000, 1500));
+        // Default fit mode preserves the aspect ratio
+        assert_eq!(img.dimensions(), (1000, 750));
     }
 
     #[test]
@@ -491,7 +1542,134 @@ mod tests {
 
         resize_image(&mut img, &options);
 
-        assert_eq!(img.dimensions(), (2000, 750));
+        assert_eq!(img.dimensions(), (1000, 750));
+    }
+
+    #[test]
+    fn test_resize_image_exact_single_dimension() {
+        let mut img = DynamicImage::new_rgb8(2000, 1500);
+        let options = CompressionOptions::new(Some(80), Some(1000), None, None)
+            .unwrap()
+            .with_resize_mode(ResizeMode::Exact);
+
+        resize_image(&mut img, &options);
+
+        assert_eq!(img.dimensions(), (1000, 1500));
+    }
+
+    #[test]
+    fn test_resize_image_fit_within_box() {
+        let mut img = DynamicImage::new_rgb8(4000, 3000);
+        let options = CompressionOptions::new(Some(80), Some(800), Some(800), None).unwrap();
+
+        resize_image(&mut img, &options);
+
+        assert_eq!(img.dimensions(), (800, 600));
+    }
+
+    #[test]
+    fn test_resize_image_fill_crops_to_box() {
+        let mut img = DynamicImage::new_rgb8(4000, 3000);
+        let options = CompressionOptions::new(Some(80), Some(800), Some(800), None)
+            .unwrap()
+            .with_resize_mode(ResizeMode::Fill);
+
+        resize_image(&mut img, &options);
+
+        assert_eq!(img.dimensions(), (800, 800));
+    }
+
+    #[test]
+    fn test_resize_image_fill_crop_only() {
+        let mut img = DynamicImage::new_rgb8(2000, 1500);
+        let options = CompressionOptions::new(Some(80), Some(2000), Some(1000), None)
+            .unwrap()
+            .with_resize_mode(ResizeMode::Fill);
+
+        resize_image(&mut img, &options);
+
+        assert_eq!(img.dimensions(), (2000, 1000));
+    }
+
+    #[test]
+    fn test_resize_image_cover_keeps_aspect_ratio() {
+        let mut img = DynamicImage::new_rgb8(4000, 3000);
+        let options = CompressionOptions::new(Some(80), Some(800), Some(800), None)
+            .unwrap()
+            .with_resize_mode(ResizeMode::Cover);
+
+        resize_image(&mut img, &options);
+
+        assert_eq!(img.dimensions(), (1067, 800));
+    }
+
+    #[test]
+    fn test_resize_image_no_upscale() {
+        let mut img = DynamicImage::new_rgb8(400, 300);
+        let options = CompressionOptions::new(Some(80), Some(800), None, None)
+            .unwrap()
+            .with_resize_mode(ResizeMode::NoUpscale);
+
+        resize_image(&mut img, &options);
+        assert_eq!(img.dimensions(), (400, 300));
+
+        let options = options.with_resize_mode(ResizeMode::Fit);
+        resize_image(&mut img, &options);
+        assert_eq!(img.dimensions(), (800, 600));
+    }
+
+    #[test]
+    fn test_resize_image_nearest_filter_keeps_hard_edges() {
+        let checker = image::RgbImage::from_fn(4, 4, |x, y| {
+            if (x + y) % 2 == 0 {
+                image::Rgb([255, 255, 255])
+            } else {
+                image::Rgb([0, 0, 0])
+            }
+        });
+        let mut img = DynamicImage::ImageRgb8(checker);
+        let options = CompressionOptions::new(Some(80), Some(16), Some(16), None)
+            .unwrap()
+            .with_filter(ResizeFilter::Nearest);
+
+        resize_image(&mut img, &options);
+
+        assert_eq!(img.dimensions(), (16, 16));
+        assert!(img
+            .to_rgb8()
+            .pixels()
+            .all(|p| p.0 == [255, 255, 255] || p.0 == [0, 0, 0]));
+    }
+
+    #[test]
+    fn test_resize_filter_default() {
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        assert_eq!(options.filter, ResizeFilter::Lanczos3);
+        assert_eq!(options.filter.filter_type(), FilterType::Lanczos3);
+    }
+
+    #[test]
+    fn test_calculate_resize_dimensions() {
+        assert_eq!(
+            calculate_resize_dimensions((2000, 1500), None, None, ResizeMode::Fit),
+            None
+        );
+        assert_eq!(
+            calculate_resize_dimensions((2000, 1500), Some(0), Some(0), ResizeMode::Exact),
+            None
+        );
+        assert_eq!(
+            calculate_resize_dimensions((2000, 1500), Some(800), Some(800), ResizeMode::Exact),
+            Some((800, 800))
+        );
+        assert_eq!(
+            calculate_resize_dimensions((3000, 4000), Some(800), Some(800), ResizeMode::Fit),
+            Some((600, 800))
+        );
+        assert_eq!(
+            calculate_resize_dimensions((10, 5000), Some(100), Some(100), ResizeMode::Fit),
+            Some((1, 100))
+        );
     }
 
     #[test]
@@ -524,10 +1702,324 @@ mod tests {
         assert_eq!(img.dimensions(), (2000, 1500));
     }
 
+    fn noisy_image(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+            let n = x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503);
+            image::Rgb([n as u8, (n >> 8) as u8, (x * 2 + y) as u8])
+        }))
+    }
+
+    #[test]
+    fn test_encode_to_target_size_picks_highest_fitting_quality() {
+        let img = noisy_image(96, 96);
+        let options = CompressionOptions::new(Some(95), None, None, None).unwrap();
+        let metadata = ImageMetadata::default();
+        let at = |quality| {
+            let options = CompressionOptions::new(Some(quality), None, None, None).unwrap();
+            encode_image(&img, ImageFormat::Jpeg, &options)
+                .unwrap()
+                .len() as u64
+        };
+        let target = (at(40) + at(41)) / 2;
+
+        let (data, report) =
+            encode_to_target_size(&img, ImageFormat::Jpeg, &options, &metadata, target).unwrap();
+        assert_eq!(report.target_met, Some(true));
+        assert!(data.len() as u64 <= target);
+        assert_eq!(report.compressed_size, data.len() as u64);
+        assert!(at(report.quality + 1) > target);
+        assert_eq!(report.dimensions, (96, 96));
+
+        // A generous budget keeps the requested quality as the ceiling
+        let (_, report) =
+            encode_to_target_size(&img, ImageFormat::Jpeg, &options, &metadata, u64::MAX).unwrap();
+        assert_eq!(report.quality, 95);
+    }
+
+    #[test]
+    fn test_encode_to_target_size_unreachable() {
+        let img = noisy_image(96, 96);
+        let metadata = ImageMetadata::default();
+        let options = CompressionOptions::new(None, None, None, None)
+            .unwrap()
+            .with_target_size(Some(1500), false);
+
+        let (data, report) =
+            encode_to_target_size(&img, ImageFormat::Jpeg, &options, &metadata, 1500).unwrap();
+        assert_eq!(report.target_met, Some(false));
+        assert_eq!(report.quality, MIN_QUALITY);
+        assert!(data.len() > 1500);
+
+        let options = options.with_target_size(Some(1500), true);
+        let (data, report) =
+            encode_to_target_size(&img, ImageFormat::Jpeg, &options, &metadata, 1500).unwrap();
+        assert_eq!(report.target_met, Some(true));
+        assert!(data.len() <= 1500);
+        assert!(report.dimensions.0 < 96 && report.dimensions.1 < 96);
+        let decoded = image::load_from_memory(&data).unwrap();
+        assert_eq!(decoded.dimensions(), report.dimensions);
+    }
+
+    #[test]
+    fn test_encode_to_target_size_lossless_goes_straight_to_downscaling() {
+        let img = noisy_image(96, 96);
+        let metadata = ImageMetadata::default();
+        let options = CompressionOptions::new(Some(90), None, None, None).unwrap();
+        let full = encode_image(&img, ImageFormat::Png, &options)
+            .unwrap()
+            .len() as u64;
+        let target = full / 2;
+
+        let (data, report) =
+            encode_to_target_size(&img, ImageFormat::Png, &options, &metadata, target).unwrap();
+        assert_eq!(report.target_met, Some(false));
+        assert_eq!(report.quality, 90);
+        assert_eq!(data.len() as u64, full);
+
+        let options = options.with_target_size(Some(target), true);
+        let (data, report) =
+            encode_to_target_size(&img, ImageFormat::Png, &options, &metadata, target).unwrap();
+        assert_eq!(report.target_met, Some(true));
+        assert_eq!(report.quality, 90);
+        assert!(data.len() as u64 <= target);
+        assert!(report.dimensions.0 < 96);
+    }
+
+    #[test]
+    fn test_encode_to_target_ssim_picks_lowest_passing_quality() {
+        let img = noisy_image(64, 64);
+        let options = CompressionOptions::new(Some(95), None, None, None).unwrap();
+        let metadata = ImageMetadata::default();
+        let score_at = |quality| {
+            let options = CompressionOptions::new(Some(quality), None, None, None).unwrap();
+            let data = encode_image(&img, ImageFormat::Jpeg, &options).unwrap();
+            ssim(&img, &image::load_from_memory(&data).unwrap()).unwrap()
+        };
+
+        let (data, report) =
+            encode_to_target_ssim(&img, ImageFormat::Jpeg, &options, &metadata, 0.9).unwrap();
+        assert_eq!(report.target_met, Some(true));
+        assert_eq!(report.compressed_size, data.len() as u64);
+        let score = report.ssim.unwrap();
+        assert!(score >= 0.9);
+        assert!(report.quality < 95);
+        assert!(score_at(report.quality - 1) < 0.9);
+
+        // Unreachable thresholds fall back to the quality ceiling
+        let (_, report) =
+            encode_to_target_ssim(&img, ImageFormat::Jpeg, &options, &metadata, 1.0).unwrap();
+        assert_eq!(report.target_met, Some(false));
+        assert_eq!(report.quality, 95);
+    }
+
+    #[test]
+    fn test_encode_to_target_ssim_lossless_and_avif() {
+        let img = noisy_image(16, 16);
+        let options = CompressionOptions::new(Some(85), None, None, None).unwrap();
+        let metadata = ImageMetadata::default();
+
+        let (_, report) =
+            encode_to_target_ssim(&img, ImageFormat::Png, &options, &metadata, 0.99).unwrap();
+        assert_eq!(report.quality, 85);
+        assert_eq!(report.target_met, Some(true));
+
+        let result = encode_to_target_ssim(&img, ImageFormat::Avif, &options, &metadata, 0.99);
+        assert!(matches!(
+            result,
+            Err(CompressionError::UnsupportedFormat(_))
+        ));
+    }
+
+    /// Writes a 48x32 JPEG of 3x2 solid blocks tagged with `orientation`.
+    fn write_oriented_jpeg(path: &Path, orientation: u16) {
+        let colors = [
+            [[255, 0, 0], [0, 255, 0], [0, 0, 255]],
+            [[255, 255, 0], [0, 255, 255], [255, 0, 255]],
+        ];
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(48, 32, |x, y| {
+            image::Rgb(colors[(y / 16) as usize][(x / 16) as usize])
+        }));
+        let mut encoded = Vec::new();
+        let options = CompressionOptions::new(Some(95), None, None, None)
+            .unwrap()
+            .with_jpeg_options(ChromaSubsampling::Yuv444, false, false);
+        encode_jpeg(&img, &mut encoded, &options).unwrap();
+
+        let field = exif::Field {
+            tag: exif::Tag::Orientation,
+            ifd_num: exif::In::PRIMARY,
+            value: exif::Value::Short(vec![orientation]),
+        };
+        let mut writer = exif::experimental::Writer::new();
+        writer.push_field(&field);
+        let mut exif = std::io::Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+        let metadata = ImageMetadata {
+            exif: Some(exif.into_inner()),
+            ..ImageMetadata::default()
+        };
+        fs::write(path, metadata.embed(encoded, ImageFormat::Jpeg).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_load_image_applies_all_exif_orientations() {
+        let (red, blue) = ([255, 0, 0], [0, 0, 255]);
+        let (yellow, magenta) = ([255, 255, 0], [255, 0, 255]);
+        // (orientation, dimensions, top-left block, top-right block)
+        let cases = [
+            (1, (48, 32), red, blue),
+            (2, (48, 32), blue, red),
+            (3, (48, 32), magenta, yellow),
+            (4, (48, 32), yellow, magenta),
+            (5, (32, 48), red, yellow),
+            (6, (32, 48), yellow, red),
+            (7, (32, 48), magenta, blue),
+            (8, (32, 48), blue, magenta),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+
+        for (orientation, dimensions, top_left, top_right) in cases {
+            let path = dir.path().join(format!("orientation-{}.jpg", orientation));
+            write_oriented_jpeg(&path, orientation);
+
+            let (img, _) = load_image_with_metadata(&path).unwrap();
+            assert_eq!(img.dimensions(), dimensions, "orientation {}", orientation);
+            let rgb = img.to_rgb8();
+            let near = |pixel: &image::Rgb<u8>, expected: [u8; 3]| {
+                pixel
+                    .0
+                    .iter()
+                    .zip(expected)
+                    .all(|(&a, b)| a.abs_diff(b) < 40)
+            };
+            assert!(
+                near(rgb.get_pixel(8, 8), top_left),
+                "orientation {}",
+                orientation
+            );
+            assert!(
+                near(rgb.get_pixel(dimensions.0 - 8, 8), top_right),
+                "orientation {}",
+                orientation
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_image_without_auto_orient() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rotated.jpg");
+        write_oriented_jpeg(&path, 6);
+
+        let options = CompressionOptions::new(None, None, None, None)
+            .unwrap()
+            .with_auto_orient(false);
+        let (img, _) = load_image_with_options(&path, &options).unwrap();
+        assert_eq!(img.dimensions(), (48, 32));
+    }
+
+    #[test]
+    fn test_pipeline_resets_kept_orientation() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("rotated.jpg");
+        let output = dir.path().join("upright.jpg");
+        write_oriented_jpeg(&input, 6);
+
+        let options = CompressionOptions::new(None, None, None, None)
+            .unwrap()
+            .with_metadata(MetadataPolicy::Keep);
+        process_image_pipeline(&input, &output, &options).unwrap();
+
+        let (img, _) = load_image_with_metadata(&output).unwrap();
+        assert_eq!(img.dimensions(), (32, 48));
+        let exif = ImageMetadata::read_from_file(&output)
+            .unwrap()
+            .exif
+            .unwrap();
+        let exif = exif::Reader::new().read_raw(exif).unwrap();
+        let orientation = exif
+            .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0));
+        assert_eq!(orientation, Some(1));
+    }
+
     #[test]
     fn test_load_image_with_metadata_not_found() {
         let path = Path::new("nonexistent.jpg");
         let result = load_image_with_metadata(path);
         assert!(matches!(result, Err(CompressionError::FileNotFound(_))));
     }
+
+    #[test]
+    fn test_save_wrappers_write_plain_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let img = DynamicImage::ImageRgb8(image::RgbImage::new(16, 8));
+        let options = CompressionOptions::new(Some(80), None, None, None).unwrap();
+
+        let jpeg = dir.path().join("out.jpg");
+        let size = process_and_save_image(&img, &jpeg, &options).unwrap();
+        assert_eq!(size, fs::metadata(&jpeg).unwrap().len());
+
+        let png = dir.path().join("nested/out.png");
+        save_image(&img, &png, ImageFormat::Png, &options).unwrap();
+        assert_eq!(image::open(&png).unwrap().dimensions(), (16, 8));
+    }
+
+    #[test]
+    fn test_load_image_from_bytes_sniffs_format() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::new(12, 8));
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        let webp = encode_image(&img, ImageFormat::WebP, &options).unwrap();
+
+        let (decoded, format) = load_image_from_bytes(&webp, true).unwrap();
+        assert_eq!(format, ImageFormat::WebP);
+        assert_eq!(decoded.dimensions(), (12, 8));
+
+        let result = load_image_from_bytes(b"definitely not an image", true);
+        assert!(matches!(
+            result,
+            Err(CompressionError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_compress_bytes_keeps_input_format_by_default() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(40, 20, |x, y| {
+            image::Rgb([(x * 6) as u8, (y * 12) as u8, 90])
+        }));
+        let options = CompressionOptions::new(Some(70), Some(20), None, None).unwrap();
+        let png = encode_image(&img, ImageFormat::Png, &options).unwrap();
+
+        let compressed = compress_bytes(&png, &options).unwrap();
+        assert_eq!(compressed.input_format, ImageFormat::Png);
+        assert_eq!(compressed.output_format, ImageFormat::Png);
+        assert_eq!(compressed.original_size, png.len() as u64);
+        assert_eq!(compressed.original_dimensions, (40, 20));
+        assert_eq!(compressed.encode.dimensions, (20, 10));
+        assert_eq!(
+            compressed.encode.compressed_size,
+            compressed.data.len() as u64
+        );
+        assert_eq!(
+            image::guess_format(&compressed.data).unwrap(),
+            ImageFormat::Png
+        );
+
+        let options = CompressionOptions::new(None, None, None, Some("gif".to_string())).unwrap();
+        assert!(matches!(
+            compress_bytes(&png, &options),
+            Err(CompressionError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_stdout_output_requires_format() {
+        let result = determine_output_format(Path::new("-"), &None);
+        assert!(matches!(
+            result,
+            Err(CompressionError::UnsupportedFormat(_))
+        ));
+        let format = determine_output_format(Path::new("-"), &Some("png".to_string())).unwrap();
+        assert_eq!(format, ImageFormat::Png);
+    }
 }
@@ -0,0 +1,367 @@
+use crate::error::{CompressionError, Result};
+use image::{DynamicImage, GenericImageView, RgbImage};
+
+/// Gaussian window used for SSIM statistics (11 taps, sigma 1.5, as in Wang et al.)
+const SSIM_WINDOW_RADIUS: i64 = 5;
+const SSIM_WINDOW_SIGMA: f64 = 1.5;
+/// Stabilizing constants for 8-bit samples: (0.01 * 255)^2 and (0.03 * 255)^2
+const SSIM_C1: f64 = 6.5025;
+const SSIM_C2: f64 = 58.5225;
+/// Scale applied to pixel deltas in the diff heatmap (a delta of 64 saturates)
+const HEATMAP_AMPLIFICATION: f64 = 4.0;
+
+/// Mean structural similarity between two images of the same size.
+///
+/// Computed on the Rec. 601 luma plane with an 11x11 Gaussian window. Returns
+/// 1.0 for identical images and decreases towards 0 as they diverge.
+///
+/// The statistics are blurred row by row over a sliding window of 11 rows, so
+/// the working set is `ssim_working_set_bytes(width)` rather than a copy of the
+/// image per statistic.
+///
+/// # Returns
+/// * `Ok(ssim)` - Mean SSIM over all pixels
+/// * `Err(CompressionError::DimensionMismatch)` - If the images differ in size
+pub fn ssim(reference: &DynamicImage, candidate: &DynamicImage) -> Result<f64> {
+    check_dimensions(reference, candidate)?;
+    let (width, height) = reference.dimensions();
+    if width == 0 || height == 0 {
+        return Ok(1.0);
+    }
+
+    let kernel = gaussian_kernel();
+    let mut window = SsimWindow::new(width as usize);
+    let mut next_row = 0;
+    let mut total = 0.0;
+    for y in 0..height {
+        // Rows below the image edge are clamped to the last row
+        while next_row <= (y + SSIM_WINDOW_RADIUS as u32).min(height - 1) {
+            window.push_row(reference, candidate, next_row, &kernel);
+            next_row += 1;
+        }
+        total += window.row_score(y, height, &kernel);
+    }
+    Ok(total / (width as f64 * height as f64))
+}
+
+/// Bytes `ssim` allocates for images `width` pixels wide
+pub(crate) fn ssim_working_set_bytes(width: u32) -> u64 {
+    // Five blurred statistics per window row, plus the two luma rows being blurred
+    let floats = (SSIM_STATISTICS * SSIM_WINDOW_ROWS + 2) as u64 * width as u64;
+    floats * std::mem::size_of::<f32>() as u64
+}
+
+/// Statistics blurred for SSIM: x, y, x², y² and xy
+const SSIM_STATISTICS: usize = 5;
+const SSIM_WINDOW_ROWS: usize = 2 * SSIM_WINDOW_RADIUS as usize + 1;
+
+/// The horizontally blurred statistics of the last `SSIM_WINDOW_ROWS` rows
+struct SsimWindow {
+    width: usize,
+    /// Row `r` lives in slot `r % SSIM_WINDOW_ROWS`, statistics side by side
+    rows: Vec<f32>,
+    luma_x: Vec<f32>,
+    luma_y: Vec<f32>,
+}
+
+impl SsimWindow {
+    fn new(width: usize) -> Self {
+        Self {
+            width,
+            rows: vec![0.0; SSIM_WINDOW_ROWS * SSIM_STATISTICS * width],
+            luma_x: vec![0.0; width],
+            luma_y: vec![0.0; width],
+        }
+    }
+
+    fn slot(&self, row: u32, statistic: usize) -> usize {
+        ((row as usize % SSIM_WINDOW_ROWS) * SSIM_STATISTICS + statistic) * self.width
+    }
+
+    /// Blurs row `row` of both images horizontally into its slot
+    fn push_row(
+        &mut self,
+        reference: &DynamicImage,
+        candidate: &DynamicImage,
+        row: u32,
+        kernel: &[f32],
+    ) {
+        for x in 0..self.width {
+            self.luma_x[x] = luma(reference, x as u32, row);
+            self.luma_y[x] = luma(candidate, x as u32, row);
+        }
+        let base = self.slot(row, 0);
+        for x in 0..self.width {
+            let mut sums = [0.0f32; SSIM_STATISTICS];
+            for (k, w) in kernel.iter().enumerate() {
+                let tap = clamped_tap(x, k, self.width);
+                let (a, b) = (self.luma_x[tap], self.luma_y[tap]);
+                for (sum, value) in sums.iter_mut().zip([a, b, a * a, b * b, a * b]) {
+                    *sum += w * value;
+                }
+            }
+            for (statistic, sum) in sums.into_iter().enumerate() {
+                self.rows[base + statistic * self.width + x] = sum;
+            }
+        }
+    }
+
+    /// Sum of the SSIM of every pixel in row `y`, blurring the window vertically
+    fn row_score(&self, y: u32, height: u32, kernel: &[f32]) -> f64 {
+        let taps: Vec<usize> = (0..kernel.len())
+            .map(|k| self.slot(clamped_tap(y as usize, k, height as usize) as u32, 0))
+            .collect();
+        (0..self.width)
+            .map(|x| {
+                let mut means = [0.0f32; SSIM_STATISTICS];
+                for (w, base) in kernel.iter().zip(&taps) {
+                    for (statistic, mean) in means.iter_mut().enumerate() {
+                        *mean += w * self.rows[base + statistic * self.width + x];
+                    }
+                }
+                let [mx, my, mean_xx, mean_yy, mean_xy] = means.map(f64::from);
+                let var_x = mean_xx - mx * mx;
+                let var_y = mean_yy - my * my;
+                let cov = mean_xy - mx * my;
+                ((2.0 * mx * my + SSIM_C1) * (2.0 * cov + SSIM_C2))
+                    / ((mx * mx + my * my + SSIM_C1) * (var_x + var_y + SSIM_C2))
+            })
+            .sum()
+    }
+}
+
+/// Peak signal-to-noise ratio in decibels over the RGB channels.
+///
+/// # Returns
+/// * `Ok(psnr)` - PSNR in dB, or `f64::INFINITY` for identical images
+/// * `Err(CompressionError::DimensionMismatch)` - If the images differ in size
+pub fn psnr(reference: &DynamicImage, candidate: &DynamicImage) -> Result<f64> {
+    check_dimensions(reference, candidate)?;
+    let (x, y) = (reference.to_rgb8(), candidate.to_rgb8());
+
+    let samples = x.as_raw().len().max(1) as f64;
+    let squared_error: f64 = x
+        .as_raw()
+        .iter()
+        .zip(y.as_raw())
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum();
+    let mse = squared_error / samples;
+    if mse == 0.0 {
+        return Ok(f64::INFINITY);
+    }
+    Ok(10.0 * (255.0 * 255.0 / mse).log10())
+}
+
+/// Largest absolute difference of any RGB channel between the two images.
+///
+/// # Returns
+/// * `Ok(delta)` - 0 for identical images, up to 255
+/// * `Err(CompressionError::DimensionMismatch)` - If the images differ in size
+pub fn max_pixel_delta(reference: &DynamicImage, candidate: &DynamicImage) -> Result<u8> {
+    check_dimensions(reference, candidate)?;
+    let (x, y) = (reference.to_rgb8(), candidate.to_rgb8());
+    Ok(x.as_raw()
+        .iter()
+        .zip(y.as_raw())
+        .map(|(&a, &b)| a.abs_diff(b))
+        .max()
+        .unwrap_or(0))
+}
+
+/// Renders the per-pixel difference as a heatmap.
+///
+/// Each pixel takes the largest channel delta, amplified by
+/// `HEATMAP_AMPLIFICATION` and mapped black → red → yellow → white, so
+/// compression artifacts stand out even when they are visually subtle.
+pub fn diff_heatmap(reference: &DynamicImage, candidate: &DynamicImage) -> Result<RgbImage> {
+    check_dimensions(reference, candidate)?;
+    let (x, y) = (reference.to_rgb8(), candidate.to_rgb8());
+
+    Ok(RgbImage::from_fn(x.width(), x.height(), |px, py| {
+        let (a, b) = (x.get_pixel(px, py), y.get_pixel(px, py));
+        let delta = (0..3).map(|c| a[c].abs_diff(b[c])).max().unwrap_or(0);
+        let t = (delta as f64 * HEATMAP_AMPLIFICATION / 255.0).min(1.0);
+        let ramp = |offset: f64| ((3.0 * t - offset).clamp(0.0, 1.0) * 255.0).round() as u8;
+        image::Rgb([ramp(0.0), ramp(1.0), ramp(2.0)])
+    }))
+}
+
+/// Converts a DSSIM threshold (`1 / ssim - 1`) into the equivalent SSIM threshold.
+pub fn dssim_to_ssim(dssim: f64) -> f64 {
+    1.0 / (1.0 + dssim)
+}
+
+fn check_dimensions(reference: &DynamicImage, candidate: &DynamicImage) -> Result<()> {
+    let (rw, rh) = reference.dimensions();
+    let (cw, ch) = candidate.dimensions();
+    if (rw, rh) != (cw, ch) {
+        return Err(CompressionError::DimensionMismatch(rw, rh, cw, ch));
+    }
+    Ok(())
+}
+
+fn luma(img: &DynamicImage, x: u32, y: u32) -> f32 {
+    let p = img.get_pixel(x, y);
+    0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32
+}
+
+fn gaussian_kernel() -> Vec<f32> {
+    let weights: Vec<f64> = (-SSIM_WINDOW_RADIUS..=SSIM_WINDOW_RADIUS)
+        .map(|i| (-((i * i) as f64) / (2.0 * SSIM_WINDOW_SIGMA * SSIM_WINDOW_SIGMA)).exp())
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    weights.into_iter().map(|w| (w / sum) as f32).collect()
+}
+
+/// Index of kernel tap `offset` around `pos`, clamped to the image edge
+fn clamped_tap(pos: usize, offset: usize, len: usize) -> usize {
+    (pos as i64 + offset as i64 - SSIM_WINDOW_RADIUS).clamp(0, len as i64 - 1) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn gradient(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 8) as u8, (y * 8) as u8, ((x + y) * 4) as u8])
+        }))
+    }
+
+    /// The textbook full-plane SSIM in f64, to check the windowed version against
+    fn full_plane_ssim(reference: &DynamicImage, candidate: &DynamicImage) -> f64 {
+        let (width, height) = reference.dimensions();
+        let (width, height) = (width as usize, height as usize);
+        let plane = |img: &DynamicImage| -> Vec<f64> {
+            img.to_rgb8()
+                .pixels()
+                .map(|p| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64)
+                .collect()
+        };
+        let kernel: Vec<f64> = gaussian_kernel().into_iter().map(f64::from).collect();
+        let blur = |plane: &[f64]| -> Vec<f64> {
+            let mut horizontal = vec![0.0; plane.len()];
+            for y in 0..height {
+                for x in 0..width {
+                    horizontal[y * width + x] = (0..kernel.len())
+                        .map(|k| kernel[k] * plane[y * width + clamped_tap(x, k, width)])
+                        .sum();
+                }
+            }
+            let mut out = vec![0.0; plane.len()];
+            for y in 0..height {
+                for x in 0..width {
+                    out[y * width + x] = (0..kernel.len())
+                        .map(|k| kernel[k] * horizontal[clamped_tap(y, k, height) * width + x])
+                        .sum();
+                }
+            }
+            out
+        };
+
+        let (x, y) = (plane(reference), plane(candidate));
+        let product =
+            |a: &[f64], b: &[f64]| -> Vec<f64> { a.iter().zip(b).map(|(a, b)| a * b).collect() };
+        let (mu_x, mu_y) = (blur(&x), blur(&y));
+        let (xx, yy, xy) = (
+            blur(&product(&x, &x)),
+            blur(&product(&y, &y)),
+            blur(&product(&x, &y)),
+        );
+        let total: f64 = (0..width * height)
+            .map(|i| {
+                let (mx, my) = (mu_x[i], mu_y[i]);
+                ((2.0 * mx * my + SSIM_C1) * (2.0 * (xy[i] - mx * my) + SSIM_C2))
+                    / ((mx * mx + my * my + SSIM_C1)
+                        * (xx[i] - mx * mx + yy[i] - my * my + SSIM_C2))
+            })
+            .sum();
+        total / (width * height) as f64
+    }
+
+    #[test]
+    fn test_windowed_ssim_matches_full_plane() {
+        // Taller than the window, and noisy enough for the variances to matter
+        let img = gradient(37, 29);
+        let noisy = DynamicImage::ImageRgb8(RgbImage::from_fn(37, 29, |x, y| {
+            let p = img.to_rgb8().get_pixel(x, y).0;
+            let noise = ((x * 7919 + y * 104_729) % 23) as u8;
+            image::Rgb(p.map(|c| c.saturating_add(noise)))
+        }));
+
+        let windowed = ssim(&img, &noisy).unwrap();
+        let full = full_plane_ssim(&img, &noisy);
+        assert!((windowed - full).abs() < 1e-4, "{} vs {}", windowed, full);
+        assert!(windowed < 0.99);
+        assert!(ssim_working_set_bytes(16_384) < 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let img = gradient(32, 24);
+        assert!((ssim(&img, &img).unwrap() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_scores_drop_with_distortion() {
+        let img = gradient(32, 24);
+        let distort = |amount: u8| {
+            let mut noisy = img.to_rgb8();
+            for (i, pixel) in noisy.pixels_mut().enumerate() {
+                let delta = if i % 2 == 0 { amount } else { 0 };
+                pixel.0 = pixel.0.map(|c| c.saturating_add(delta));
+            }
+            DynamicImage::ImageRgb8(noisy)
+        };
+        let (mild, strong) = (distort(4), distort(40));
+
+        let (ssim_mild, ssim_strong) = (ssim(&img, &mild).unwrap(), ssim(&img, &strong).unwrap());
+        assert!(ssim_mild < 1.0 && ssim_strong < ssim_mild);
+    }
+
+    #[test]
+    fn test_psnr_and_max_delta() {
+        let img = gradient(32, 24);
+        assert_eq!(psnr(&img, &img).unwrap(), f64::INFINITY);
+        assert_eq!(max_pixel_delta(&img, &img).unwrap(), 0);
+
+        let mut shifted = img.to_rgb8();
+        shifted.get_pixel_mut(3, 4).0[1] = shifted.get_pixel(3, 4)[1].wrapping_add(17);
+        let shifted = DynamicImage::ImageRgb8(shifted);
+        assert_eq!(max_pixel_delta(&img, &shifted).unwrap(), 17);
+
+        // A single channel off by 17 in 32*24*3 samples: MSE = 289 / 2304
+        let expected = 10.0 * (255.0_f64.powi(2) / (289.0 / 2304.0)).log10();
+        assert!((psnr(&img, &shifted).unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_diff_heatmap() {
+        let img = gradient(8, 8);
+        let mut changed = img.to_rgb8();
+        changed.put_pixel(2, 2, image::Rgb([255, 255, 255]));
+        let changed = DynamicImage::ImageRgb8(changed);
+
+        let heatmap = diff_heatmap(&img, &changed).unwrap();
+        assert_eq!(heatmap.dimensions(), (8, 8));
+        assert_eq!(heatmap.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(heatmap.get_pixel(2, 2).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_dssim_to_ssim() {
+        assert_eq!(dssim_to_ssim(0.0), 1.0);
+        assert!((dssim_to_ssim(0.01) - 1.0 / 1.01).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let result = ssim(&gradient(8, 8), &gradient(8, 9));
+        assert!(matches!(
+            result,
+            Err(CompressionError::DimensionMismatch(8, 8, 8, 9))
+        ));
+    }
+}
@@ -0,0 +1,398 @@
+use crate::error::{CompressionError, Result};
+use crate::processing::{
+    determine_output_format, encode_for_output, load_image_with_options, read_source_metadata,
+    resized_image, write_output, CompressionOptions, CompressionReport,
+};
+use image::GenericImageView;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+/// Placeholders every rendition file name template must contain
+const REQUIRED_PLACEHOLDERS: [&str; 3] = ["{stem}", "{width}", "{ext}"];
+
+/// Widths and formats of a responsive image set, written as
+/// `320,640,1280:avif,webp,jpeg`.
+///
+/// Without a format list every width is written in the `--format` or input format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenditionSpec {
+    /// Requested widths in pixels, ascending and without duplicates
+    pub widths: Vec<u32>,
+    /// Output formats (`jpeg`, `png`, `webp`, `avif`); empty for the default format
+    pub formats: Vec<String>,
+}
+
+/// A rendition spec together with the template its files are named by
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Renditions {
+    pub spec: RenditionSpec,
+    /// File name template with `{stem}`, `{width}`, `{ext}` and optionally `{format}`
+    pub template: String,
+}
+
+/// One file of a rendition set, before it is written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedRendition {
+    /// Requested width; the output is narrower when the source is, and if the
+    /// source is narrower than every width it is named after the source width
+    pub width: u32,
+    /// Normalized output format, e.g. `jpeg`
+    pub format: String,
+    pub output: PathBuf,
+}
+
+/// Outcome of rendering every rendition of one source
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenditionReport {
+    pub input: PathBuf,
+    /// Size of the input file in bytes
+    pub original_size: u64,
+    /// Dimensions of the input after auto-orientation
+    pub original_dimensions: (u32, u32),
+    /// One report per written file, in width then format order
+    pub renditions: Vec<CompressionReport>,
+    pub elapsed_secs: f64,
+}
+
+impl FromStr for RenditionSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let (widths, formats) = match value.split_once(':') {
+            Some((widths, formats)) => (widths, Some(formats)),
+            None => (value, None),
+        };
+
+        let mut widths = widths
+            .split(',')
+            .map(|width| match width.trim().parse::<u32>() {
+                Ok(width) if width > 0 => Ok(width),
+                _ => Err(format!("invalid rendition width '{}'", width.trim())),
+            })
+            .collect::<std::result::Result<Vec<u32>, String>>()?;
+        widths.sort_unstable();
+        widths.dedup();
+
+        let mut formats_seen = Vec::new();
+        for format in formats.into_iter().flat_map(|formats| formats.split(',')) {
+            let format = match format.trim().to_lowercase().as_str() {
+                "jpeg" | "jpg" => "jpeg",
+                "png" => "png",
+                "webp" => "webp",
+                "avif" => "avif",
+                other => return Err(format!("unsupported rendition format '{}'", other)),
+            };
+            if !formats_seen.iter().any(|seen| seen == format) {
+                formats_seen.push(format.to_string());
+            }
+        }
+
+        Ok(Self {
+            widths,
+            formats: formats_seen,
+        })
+    }
+}
+
+impl Renditions {
+    /// Combines a spec with a file name template
+    ///
+    /// # Returns
+    /// * `Ok(Renditions)` - If the template names every file of every source uniquely
+    /// * `Err(CompressionError::Config)` - If the template lacks a required placeholder
+    pub fn new(spec: RenditionSpec, template: String) -> Result<Self> {
+        if let Some(missing) = REQUIRED_PLACEHOLDERS
+            .iter()
+            .find(|placeholder| !template.contains(*placeholder))
+        {
+            return Err(CompressionError::Config(format!(
+                "rendition template '{}' must contain {}",
+                template, missing
+            )));
+        }
+        if template.contains('/') || template.contains('\\') {
+            return Err(CompressionError::Config(format!(
+                "rendition template '{}' must be a file name, not a path",
+                template
+            )));
+        }
+        Ok(Self { spec, template })
+    }
+
+    /// Lists the files `input` would be rendered to inside `output_dir`
+    ///
+    /// # Arguments
+    /// * `input` - Source image
+    /// * `output_dir` - Directory the renditions are written to
+    /// * `default_format` - `--format`, used when the spec names no formats
+    ///
+    /// # Returns
+    /// * `Ok(renditions)` - Every width and format combination, in width then format order
+    /// * `Err(CompressionError)` - If the output format cannot be determined
+    pub fn plan(
+        &self,
+        input: &Path,
+        output_dir: &Path,
+        default_format: &Option<String>,
+    ) -> Result<Vec<PlannedRendition>> {
+        let stem = file_stem(input)?;
+        let formats = if self.spec.formats.is_empty() {
+            let format = determine_output_format(input, default_format)?;
+            vec![format!("{:?}", format).to_lowercase()]
+        } else {
+            self.spec.formats.clone()
+        };
+
+        let mut planned = Vec::new();
+        for &width in &self.spec.widths {
+            for format in &formats {
+                planned.push(PlannedRendition {
+                    width,
+                    format: format.clone(),
+                    output: output_dir.join(self.file_name(&stem, width, format)),
+                });
+            }
+        }
+        Ok(planned)
+    }
+
+    /// Expands the template for one width and format
+    fn file_name(&self, stem: &str, width: u32, format: &str) -> String {
+        let ext = if format == "jpeg" { "jpg" } else { format };
+        self.template
+            .replace("{stem}", stem)
+            .replace("{width}", &width.to_string())
+            .replace("{format}", format)
+            .replace("{ext}", ext)
+    }
+}
+
+fn file_stem(input: &Path) -> Result<String> {
+    Ok(input
+        .file_stem()
+        .ok_or_else(|| CompressionError::UnsupportedFormat("Invalid file name".to_string()))?
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// Decodes `input` once and writes every rendition into `output_dir`.
+///
+/// Widths larger than the source are skipped rather than upscaled; if every
+/// width is larger, only the smallest is written, at the source width and
+/// named after it (so its files differ from what `Renditions::plan` listed).
+///
+/// # Arguments
+/// * `input` - Source image
+/// * `output_dir` - Directory the renditions are written to, created if missing
+/// * `options` - Encoder options; `width`, `height` and `format` come from the renditions
+/// * `renditions` - Widths, formats and file name template
+///
+/// # Returns
+/// * `Ok(RenditionReport)` - Sizes and dimensions of every written file
+/// * `Err(CompressionError)` - If decoding or any encode fails
+pub fn process_renditions(
+    input: &Path,
+    output_dir: &Path,
+    options: &CompressionOptions,
+    renditions: &Renditions,
+) -> Result<RenditionReport> {
+    let start_time = Instant::now();
+    let planned = renditions.plan(input, output_dir, &options.format)?;
+
+    let (img, original_size) = load_image_with_options(input, options)?;
+    let metadata = read_source_metadata(input, options)?;
+    let original_dimensions = img.dimensions();
+    let source_width = original_dimensions.0;
+    let smallest = renditions
+        .spec
+        .widths
+        .first()
+        .copied()
+        .unwrap_or(source_width);
+
+    let mut reports = Vec::with_capacity(planned.len());
+    for &width in &renditions.spec.widths {
+        if width > source_width && width != smallest {
+            continue;
+        }
+        let actual_width = width.min(source_width);
+        let mut width_options = options.clone();
+        width_options.width = Some(actual_width);
+        width_options.height = None;
+        let resized = resized_image(&img, &width_options);
+
+        for rendition in planned.iter().filter(|planned| planned.width == width) {
+            let rendition_start = Instant::now();
+            let output = if actual_width == width {
+                rendition.output.clone()
+            } else {
+                output_dir.join(renditions.file_name(
+                    &file_stem(input)?,
+                    actual_width,
+                    &rendition.format,
+                ))
+            };
+            let mut rendition_options = width_options.clone();
+            rendition_options.format = Some(rendition.format.clone());
+            let output_format = determine_output_format(&output, &rendition_options.format)?;
+            let (data, encode) =
+                encode_for_output(&resized, output_format, &rendition_options, &metadata)?;
+            write_output(&output, &data)?;
+            reports.push(CompressionReport::new(
+                input,
+                &output,
+                output_format,
+                (original_size, original_dimensions),
+                encode,
+                &rendition_options,
+                rendition_start,
+            ));
+        }
+    }
+
+    Ok(RenditionReport {
+        input: input.to_path_buf(),
+        original_size,
+        original_dimensions,
+        renditions: reports,
+        elapsed_secs: start_time.elapsed().as_secs_f64(),
+    })
+}
+
+/// Prints the files written for one source
+pub fn print_rendition_report(report: &RenditionReport) {
+    let (orig_w, orig_h) = report.original_dimensions;
+    println!("🖼️  Renditions of: {:?}", report.input);
+    println!(
+        "📊 Original size: {} bytes ({}x{})",
+        report.original_size, orig_w, orig_h
+    );
+    for rendition in &report.renditions {
+        let (width, height) = rendition.encode.dimensions;
+        println!(
+            "  ✅ {:?} ({}, {}x{}): {} bytes",
+            rendition.output, rendition.format, width, height, rendition.encode.compressed_size
+        );
+    }
+    let total: u64 = report
+        .renditions
+        .iter()
+        .map(|rendition| rendition.encode.compressed_size)
+        .sum();
+    println!(
+        "📈 {} files, {} bytes in total, {:.2}s",
+        report.renditions.len(),
+        total,
+        report.elapsed_secs
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::DEFAULT_RENDITION_TEMPLATE;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_rendition_spec() {
+        let spec: RenditionSpec = "640, 320,640:WebP,jpg,jpeg".parse().unwrap();
+        assert_eq!(spec.widths, [320, 640]);
+        assert_eq!(spec.formats, ["webp", "jpeg"]);
+
+        let spec: RenditionSpec = "800".parse().unwrap();
+        assert!(spec.formats.is_empty());
+
+        assert!("".parse::<RenditionSpec>().is_err());
+        assert!("0,320".parse::<RenditionSpec>().is_err());
+        assert!("320:gif".parse::<RenditionSpec>().is_err());
+    }
+
+    #[test]
+    fn test_template_validation_and_naming() {
+        let spec: RenditionSpec = "320,640:avif,jpeg".parse().unwrap();
+        assert!(Renditions::new(spec.clone(), "{stem}.{ext}".to_string()).is_err());
+        assert!(Renditions::new(spec.clone(), "a/{stem}-{width}.{ext}".to_string()).is_err());
+
+        let renditions = Renditions::new(spec, DEFAULT_RENDITION_TEMPLATE.to_string()).unwrap();
+        let planned = renditions
+            .plan(Path::new("photos/hero.png"), Path::new("out"), &None)
+            .unwrap();
+        let names: Vec<_> = planned.iter().map(|p| p.output.clone()).collect();
+        assert_eq!(
+            names,
+            [
+                PathBuf::from("out/hero-320w.avif"),
+                PathBuf::from("out/hero-320w.jpg"),
+                PathBuf::from("out/hero-640w.avif"),
+                PathBuf::from("out/hero-640w.jpg"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_renditions_decodes_once_and_skips_upscaling() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("hero.png");
+        image::RgbImage::from_fn(400, 200, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+        })
+        .save(&input)
+        .unwrap();
+
+        let renditions = Renditions::new(
+            "100,200,800:webp,jpeg".parse().unwrap(),
+            DEFAULT_RENDITION_TEMPLATE.to_string(),
+        )
+        .unwrap();
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        let output_dir = temp_dir.path().join("out");
+        let report = process_renditions(&input, &output_dir, &options, &renditions).unwrap();
+
+        assert_eq!(report.original_dimensions, (400, 200));
+        let written: Vec<_> = report
+            .renditions
+            .iter()
+            .map(|r| (r.format.as_str(), r.encode.dimensions))
+            .collect();
+        assert_eq!(
+            written,
+            [
+                ("webp", (100, 50)),
+                ("jpeg", (100, 50)),
+                ("webp", (200, 100)),
+                ("jpeg", (200, 100)),
+            ]
+        );
+        assert!(output_dir.join("hero-200w.jpg").exists());
+        assert!(!output_dir.join("hero-800w.webp").exists());
+    }
+
+    #[test]
+    fn test_process_renditions_names_fallback_after_source_width() {
+        let temp_dir = TempDir::new().unwrap();
+        let input = temp_dir.path().join("icon.png");
+        image::RgbImage::from_pixel(300, 150, image::Rgb([40, 80, 120]))
+            .save(&input)
+            .unwrap();
+
+        let renditions = Renditions::new(
+            "640,1280:webp".parse().unwrap(),
+            DEFAULT_RENDITION_TEMPLATE.to_string(),
+        )
+        .unwrap();
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        let output_dir = temp_dir.path().join("out");
+        let report = process_renditions(&input, &output_dir, &options, &renditions).unwrap();
+
+        assert_eq!(report.renditions.len(), 1);
+        assert_eq!(report.renditions[0].encode.dimensions, (300, 150));
+        assert_eq!(
+            report.renditions[0].output,
+            output_dir.join("icon-300w.webp")
+        );
+        assert!(output_dir.join("icon-300w.webp").exists());
+        assert!(!output_dir.join("icon-640w.webp").exists());
+    }
+}
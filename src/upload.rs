@@ -1,68 +1,472 @@
-use crate::constants::TEMP_EPOCHS;
-use crate::error::Result;
-use crate::processing::validate_file_exists;
-use crate::walrus::{upload_to_walrus_sync, WalrusOptions};
-use std::path::Path;
+use crate::assets::relative_url;
+use crate::batch::{collect_image_files, BatchFailure, BatchOutcome};
+use crate::constants::{TEMP_EPOCHS, UPLOAD_MANIFEST_VERSION};
+use crate::error::{CompressionError, Result};
+use crate::processing::{
+    compress_file_for, validate_file_exists, write_output, CompressionOptions, CompressionReport,
+};
+use crate::walrus::{
+    upload_files_to_walrus_sync, upload_to_walrus_sync_with_progress, StoredBlob, WalrusOptions,
+    WalrusUploader,
+};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Outcome of uploading one file to Walrus
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UploadReport {
+    pub input: PathBuf,
+    pub blob_id: String,
+    pub access_url: String,
+    pub aggregator_url: String,
+    pub publisher_url: String,
+    pub epochs: Option<u64>,
+    /// Uploaded with `TEMP_EPOCHS` storage
+    pub temporary: bool,
+    pub file_size: u64,
+    /// Hex SHA-256 of the uploaded file, for `download --sha256`
+    pub sha256: String,
+    /// Last epoch the blob is stored for
+    pub end_epoch: u64,
+}
+
+/// Where `compress --upload` and `batch --upload` send their outputs
+#[derive(Debug, Clone)]
+pub struct UploadTarget {
+    pub walrus: WalrusOptions,
+    /// Also write the output file; otherwise it only exists on Walrus
+    pub keep_local: bool,
+}
+
+/// The Walrus blob a compressed output was uploaded as
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UploadedBlob {
+    pub blob_id: String,
+    pub access_url: String,
+    /// Last epoch the blob is stored for
+    pub end_epoch: u64,
+    /// Hex SHA-256 of the uploaded output, for `download --sha256`
+    pub sha256: String,
+    /// Whether the output file was written too
+    pub kept_local: bool,
+}
+
+/// How a directory or glob is uploaded
+#[derive(Debug, Clone)]
+pub struct BatchUploadOptions {
+    /// Walk subdirectories of a directory input
+    pub recursive: bool,
+    /// Maximum number of simultaneous uploads
+    pub concurrency: usize,
+    /// Where to write the blob manifest, if anywhere
+    pub manifest: Option<PathBuf>,
+}
+
+/// Outcome of uploading every image of a directory or glob
+#[derive(Debug, Serialize)]
+pub struct BatchUploadReport {
+    pub outcome: BatchOutcome,
+    pub input: String,
+    pub aggregator_url: String,
+    pub publisher_url: String,
+    pub epochs: Option<u64>,
+    pub temporary: bool,
+    pub concurrency: usize,
+    /// Successfully uploaded files, in input order
+    pub files: Vec<UploadReport>,
+    pub failures: Vec<BatchFailure>,
+    /// Manifest written after the uploads
+    pub manifest: Option<PathBuf>,
+    pub total_size: u64,
+    pub elapsed_secs: f64,
+}
+
+/// Maps local files to the Walrus blobs they were uploaded as
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadManifest {
+    pub version: u32,
+    pub aggregator_url: String,
+    pub publisher_url: String,
+    /// Keyed by the file's path relative to the uploaded directory (or to the
+    /// deepest directory containing every file for globs), with `/` separators
+    pub blobs: BTreeMap<String, UploadManifestEntry>,
+}
+
+/// One uploaded file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadManifestEntry {
+    pub blob_id: String,
+    pub access_url: String,
+    pub size: u64,
+    /// Hex SHA-256 of the file contents
+    pub sha256: String,
+    /// Storage duration requested for the upload
+    pub epochs: Option<u64>,
+    /// Last epoch the blob is stored for
+    pub end_epoch: u64,
+}
+
+impl UploadManifest {
+    /// Collects the uploaded files of a batch
+    ///
+    /// # Arguments
+    /// * `report` - Finished batch upload
+    /// * `base` - Directory the manifest keys are made relative to
+    pub fn from_report(report: &BatchUploadReport, base: &Path) -> Self {
+        let blobs = report
+            .files
+            .iter()
+            .map(|file| {
+                (
+                    relative_url(&file.input, base),
+                    UploadManifestEntry {
+                        blob_id: file.blob_id.clone(),
+                        access_url: file.access_url.clone(),
+                        size: file.file_size,
+                        sha256: file.sha256.clone(),
+                        epochs: file.epochs,
+                        end_epoch: file.end_epoch,
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            version: UPLOAD_MANIFEST_VERSION,
+            aggregator_url: report.aggregator_url.clone(),
+            publisher_url: report.publisher_url.clone(),
+            blobs,
+        }
+    }
+
+    /// Reads a manifest written by `upload --manifest`
+    ///
+    /// # Returns
+    /// * `Ok(manifest)` - The recorded blobs
+    /// * `Err(CompressionError)` - If the file cannot be read, is not a manifest or has another version
+    pub fn load(path: &Path) -> Result<Self> {
+        let manifest: Self = serde_json::from_slice(&fs::read(path)?)?;
+        if manifest.version != UPLOAD_MANIFEST_VERSION {
+            return Err(CompressionError::Config(format!(
+                "{:?} is an upload manifest of version {}, expected {}",
+                path, manifest.version, UPLOAD_MANIFEST_VERSION
+            )));
+        }
+        Ok(manifest)
+    }
+
+    /// The entry of the file uploaded as `blob_id`, if any
+    pub fn find_blob(&self, blob_id: &str) -> Option<&UploadManifestEntry> {
+        self.blobs.values().find(|entry| entry.blob_id == blob_id)
+    }
+}
 
 /// Handles uploading an image to Walrus decentralized storage
 ///
 /// # Arguments
 /// * `input_path` - Path to the image file to upload
-/// * `aggregator_url` - Optional custom aggregator URL (uses default if None)
-/// * `publisher_url` - Optional custom publisher URL (uses default if None)
-/// * `epochs` - Optional number of epochs for storage (uses default if None)
+/// * `options` - Walrus endpoints, storage duration and retry policy
 /// * `temp` - If true, uploads as temporary file with 1 epoch storage
 ///
 /// # Returns
-/// * `Ok(())` if upload succeeds
+/// * `Ok(UploadReport)` with the blob ID and access URL if upload succeeds
 /// * `Err(CompressionError)` if upload fails
 pub fn upload_image_to_walrus(
     input_path: &Path,
-    aggregator_url: Option<String>,
-    publisher_url: Option<String>,
-    epochs: Option<u64>,
+    mut options: WalrusOptions,
     temp: bool,
-) -> Result<()> {
-    println!("📤 Uploading to Walrus: {:?}", input_path);
-
+) -> Result<UploadReport> {
     validate_file_exists(input_path)?;
 
     // 处理临时文件选项
-    let final_epochs = if temp {
-        Some(TEMP_EPOCHS) // 临时文件只存储 1 个 epoch
+    if temp {
+        options.epochs = Some(TEMP_EPOCHS); // 临时文件只存储 1 个 epoch
+    }
+    let progress = upload_progress(fs::metadata(input_path)?.len());
+    let stored = upload_to_walrus_sync_with_progress(input_path, &options, &progress);
+    progress.finish_and_clear();
+
+    Ok(upload_report(input_path, stored?, &options, temp))
+}
+
+/// Uploads every image matched by a directory or glob to Walrus
+///
+/// # Arguments
+/// * `input` - Image file, directory or glob pattern
+/// * `options` - Walrus endpoints, storage duration and retry policy
+/// * `temp` - If true, uploads as temporary files with 1 epoch storage
+/// * `batch_options` - Recursion, concurrency and manifest path
+///
+/// # Returns
+/// * `Ok(BatchUploadReport)` - The uploaded files and the files that failed
+/// * `Err(CompressionError)` - If the input cannot be read or the manifest cannot be written
+pub fn upload_images_to_walrus(
+    input: &str,
+    mut options: WalrusOptions,
+    temp: bool,
+    batch_options: &BatchUploadOptions,
+) -> Result<BatchUploadReport> {
+    let start_time = Instant::now();
+    let files = collect_image_files(input, batch_options.recursive)?;
+    if temp {
+        options.epochs = Some(TEMP_EPOCHS);
+    }
+
+    let mut report = BatchUploadReport {
+        outcome: BatchOutcome::Success,
+        input: input.to_string(),
+        aggregator_url: options.aggregator_url.clone(),
+        publisher_url: options.publisher_url.clone(),
+        epochs: options.epochs,
+        temporary: temp,
+        concurrency: batch_options.concurrency,
+        files: Vec::new(),
+        failures: Vec::new(),
+        manifest: None,
+        total_size: 0,
+        elapsed_secs: 0.0,
+    };
+
+    let total_bytes = files
+        .iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let progress = upload_progress(total_bytes);
+    let results =
+        upload_files_to_walrus_sync(&files, &options, batch_options.concurrency, &progress);
+    progress.finish_and_clear();
+    let results = results?;
+    for (file, result) in files.into_iter().zip(results) {
+        match result.map(|stored| upload_report(&file, stored, &options, temp)) {
+            Ok(uploaded) => report.files.push(uploaded),
+            Err(error) => report.failures.push(BatchFailure { path: file, error }),
+        }
+    }
+    report.total_size = report.files.iter().map(|file| file.file_size).sum();
+    report.outcome = if report.failures.is_empty() {
+        BatchOutcome::Success
+    } else if report.files.is_empty() {
+        BatchOutcome::TotalFailure
     } else {
-        epochs
+        BatchOutcome::PartialFailure
     };
 
-    let options = WalrusOptions::new(aggregator_url, publisher_url, final_epochs);
+    if let Some(manifest_path) = &batch_options.manifest {
+        // collect_image_files returns canonical paths
+        let base = if Path::new(input).is_dir() {
+            Path::new(input).canonicalize()?
+        } else {
+            common_parent(report.files.iter().map(|file| file.input.as_path()))
+        };
+        let manifest = UploadManifest::from_report(&report, &base);
+        fs::write(manifest_path, serde_json::to_vec_pretty(&manifest)?)?;
+        report.manifest = Some(manifest_path.clone());
+    }
+
+    report.elapsed_secs = start_time.elapsed().as_secs_f64();
+    Ok(report)
+}
+
+/// Compresses an image and uploads the encoded output to Walrus.
+///
+/// The output is uploaded straight from memory; the file at `output_path` is
+/// only written when the target keeps local copies, and before the upload, so
+/// a failed upload does not lose the compressed file.
+///
+/// # Arguments
+/// * `input_path` - Image to compress
+/// * `output_path` - Where the output is (or would be) written; decides the format without `--format`
+/// * `options` - Compression options
+/// * `target` - Walrus endpoints and whether to keep the local file
+///
+/// # Returns
+/// * `Ok(CompressionReport)` - Compression stats with the uploaded blob
+/// * `Err(CompressionError)` - If compression, writing or the upload fails
+pub fn compress_and_upload_image(
+    input_path: &Path,
+    output_path: &Path,
+    options: &CompressionOptions,
+    target: &UploadTarget,
+) -> Result<CompressionReport> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} {msg}")
+            .unwrap(),
+    );
+    pb.set_message("Compressing image...");
 
-    println!("🔗 Aggregator URL: {}", options.aggregator_url);
-    println!("🔗 Publisher URL: {}", options.publisher_url);
-    println!("⏰ Epochs: {:?}", options.epochs);
+    let uploader = WalrusUploader::new(&target.walrus)?;
+    let result = compress_and_upload_with(input_path, output_path, options, target, &uploader, &pb);
+    pb.finish_and_clear();
+    result
+}
 
-    let blob_id = upload_to_walrus_sync(input_path, &options)?;
+/// As `compress_and_upload_image`, storing the image through `uploader` and
+/// turning `progress` into an upload bar once the image is compressed; batches
+/// share one uploader and pass a hidden bar
+pub(crate) fn compress_and_upload_with(
+    input_path: &Path,
+    output_path: &Path,
+    options: &CompressionOptions,
+    target: &UploadTarget,
+    uploader: &WalrusUploader,
+    progress: &ProgressBar,
+) -> Result<CompressionReport> {
+    let start_time = Instant::now();
+    let (compressed, options) = compress_file_for(input_path, output_path, options)?;
+    if target.keep_local {
+        write_output(output_path, &compressed.data)?;
+    }
 
-    println!("✅ Upload successful!");
-    println!("🆔 Blob ID: {}", blob_id);
+    progress.set_style(upload_style());
+    progress.set_length(compressed.data.len() as u64);
+    progress.set_message("Uploading to Walrus...");
+    // 压缩结果直接从内存上传，不再复制
+    let stored = uploader.upload_bytes(compressed.data, progress)?;
+    let mut report = CompressionReport::new(
+        input_path,
+        output_path,
+        compressed.output_format,
+        (compressed.original_size, compressed.original_dimensions),
+        compressed.encode,
+        &options,
+        start_time,
+    );
+    report.blob = Some(UploadedBlob {
+        access_url: build_walrus_access_url(&target.walrus.aggregator_url, &stored.blob_id),
+        blob_id: stored.blob_id,
+        end_epoch: stored.end_epoch,
+        sha256: stored.sha256,
+        kept_local: target.keep_local,
+    });
+    Ok(report)
+}
+
+/// Bar showing the bytes sent to Walrus
+fn upload_progress(total_bytes: u64) -> ProgressBar {
+    let pb = ProgressBar::new(total_bytes);
+    pb.set_style(upload_style());
+    pb.set_message("Uploading to Walrus...");
+    pb
+}
+
+fn upload_style() -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template("{spinner:.green} {msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec})")
+        .unwrap()
+}
 
+/// Describes a file the publisher accepted.
+///
+/// Size and hash are those of the bytes actually sent, so nothing is read
+/// again and a completed upload is always reported.
+fn upload_report(
+    input_path: &Path,
+    stored: StoredBlob,
+    options: &WalrusOptions,
+    temp: bool,
+) -> UploadReport {
     // 构建访问地址
-    let access_url = build_walrus_access_url(&options.aggregator_url, &blob_id);
-    println!("🌐 Access URL: {}", access_url);
+    let access_url = build_walrus_access_url(&options.aggregator_url, &stored.blob_id);
+
+    UploadReport {
+        input: input_path.to_path_buf(),
+        blob_id: stored.blob_id,
+        access_url,
+        aggregator_url: options.aggregator_url.clone(),
+        publisher_url: stored.publisher_url,
+        epochs: options.epochs,
+        temporary: temp,
+        file_size: stored.size,
+        sha256: stored.sha256,
+        end_epoch: stored.end_epoch,
+    }
+}
+
+/// Prints an upload report
+pub fn print_upload_report(report: &UploadReport) {
+    println!("📤 Uploaded to Walrus: {:?}", report.input);
+    println!("🔗 Aggregator URL: {}", report.aggregator_url);
+    println!("🔗 Publisher URL: {}", report.publisher_url);
+    println!("⏰ Epochs: {:?}", report.epochs);
+
+    println!("✅ Upload successful!");
+    println!("🆔 Blob ID: {}", report.blob_id);
+    println!("🌐 Access URL: {}", report.access_url);
+    println!("📅 Stored until epoch: {}", report.end_epoch);
 
     // 临时文件提示
-    if temp {
+    if report.temporary {
         println!("⏰ Temporary file: Will expire after 1 epoch (~24 hours)");
         println!("🔄 Use without -t flag for longer storage");
     }
 
-    // 显示文件信息
-    if let Ok(metadata) = std::fs::metadata(input_path) {
-        println!("📊 File size: {} bytes", metadata.len());
+    println!("📊 File size: {} bytes", report.file_size);
+    println!("🔒 SHA-256: {}", report.sha256);
+    println!(
+        "💡 Retrieve it later with: img-squeeze download {} <output> --sha256 {}",
+        report.blob_id, report.sha256
+    );
+}
+
+/// Prints a directory or glob upload report
+pub fn print_batch_upload_report(report: &BatchUploadReport) {
+    println!("📤 Uploading to Walrus: {}", report.input);
+    println!("🔗 Aggregator URL: {}", report.aggregator_url);
+    println!("🔗 Publisher URL: {}", report.publisher_url);
+    println!("⏰ Epochs: {:?}", report.epochs);
+    println!("⚙️  Concurrent uploads: {}", report.concurrency);
+
+    if report.files.is_empty() && report.failures.is_empty() {
+        println!("⚠️  No image files found in the input path");
+        return;
     }
 
-    println!("💡 You can use the blob ID to retrieve the file later");
+    for file in &report.files {
+        println!(
+            "  ✅ {:?} -> {} ({} bytes, until epoch {})",
+            file.input, file.blob_id, file.file_size, file.end_epoch
+        );
+    }
+    for failure in &report.failures {
+        println!("  ❌ {:?}: {}", failure.path, failure.error);
+    }
 
-    Ok(())
+    println!("\n📊 Upload Summary:");
+    println!("  📁 Files uploaded: {}", report.files.len());
+    if !report.failures.is_empty() {
+        println!("  ❌ Failed: {}", report.failures.len());
+    }
+    println!("  📊 Total size: {} bytes", report.total_size);
+    println!("  ⏱️  Total time: {:.2}s", report.elapsed_secs);
+    if report.temporary {
+        println!("⏰ Temporary files: Will expire after 1 epoch (~24 hours)");
+    }
+    if let Some(manifest) = &report.manifest {
+        println!("📝 Blob manifest written: {:?}", manifest);
+    }
+}
+
+/// Deepest directory containing every path
+fn common_parent<'a>(mut paths: impl Iterator<Item = &'a Path>) -> PathBuf {
+    let Some(first) = paths.next() else {
+        return PathBuf::new();
+    };
+    let mut common = first.parent().unwrap_or(first).to_path_buf();
+    for path in paths {
+        while !path.starts_with(&common) {
+            if !common.pop() {
+                break;
+            }
+        }
+    }
+    common
 }
 
 /// Builds a Walrus network access URL from aggregator URL and blob ID
@@ -87,6 +491,21 @@ fn build_walrus_access_url(aggregator_url: &str, blob_id: &str) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_common_parent_keys_glob_uploads() {
+        let files = [
+            PathBuf::from("/assets/img/a.png"),
+            PathBuf::from("/assets/img/icons/b.png"),
+            PathBuf::from("/assets/img/icons/c.png"),
+        ];
+        let base = common_parent(files.iter().map(PathBuf::as_path));
+        assert_eq!(base, PathBuf::from("/assets/img"));
+        assert_eq!(relative_url(&files[1], &base), "icons/b.png");
+
+        let single = common_parent(files[2..].iter().map(PathBuf::as_path));
+        assert_eq!(relative_url(&files[2], &single), "c.png");
+    }
+
     #[test]
     fn test_build_walrus_access_url_with_trailing_slash() {
         let aggregator = "https://example.com/";
@@ -1,27 +1,126 @@
-use crate::constants::{DEFAULT_EPOCHS, DEFAULT_WALRUS_AGGREGATOR, DEFAULT_WALRUS_PUBLISHER};
+use crate::constants::{
+    DEFAULT_EPOCHS, DEFAULT_WALRUS_AGGREGATOR, DEFAULT_WALRUS_PUBLISHER, DEFAULT_WALRUS_RETRIES,
+    DEFAULT_WALRUS_TIMEOUT_SECS, WALRUS_FORMAT_SNIFF_BYTES, WALRUS_INITIAL_BACKOFF_MS,
+    WALRUS_MAX_BACKOFF_MS, WALRUS_UPLOAD_CHUNK_SIZE,
+};
 use crate::error::{CompressionError, Result};
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
-use walrus_rs::WalrusClient;
+use crate::incremental::to_hex;
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use image::ImageFormat;
+use indicatif::ProgressBar;
+use reqwest::{Body, Url};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio_util::io::ReaderStream;
+use walrus_rs::{WalrusClient, WalrusError};
 
 #[derive(Debug, Clone)]
 pub struct WalrusOptions {
     pub aggregator_url: String,
+    /// Aggregators tried, in order, when `aggregator_url` fails
+    pub fallback_aggregators: Vec<String>,
     pub publisher_url: String,
+    /// Publishers tried, in order, when `publisher_url` fails
+    pub fallback_publishers: Vec<String>,
     pub epochs: Option<u64>,
+    pub retry: RetryPolicy,
+}
+
+/// How uploads and downloads deal with slow and failing publishers and aggregators
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Limit of one store or read request, including transferring the blob
+    pub timeout: Duration,
+    /// Rounds over the publishers after the first; only transient failures
+    /// (network errors, timeouts, HTTP 408, 429 and 5xx) are retried
+    pub retries: u32,
+    /// Wait before the first retry round, doubled every round
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
 }
 
 impl Default for WalrusOptions {
     fn default() -> Self {
         Self {
             aggregator_url: DEFAULT_WALRUS_AGGREGATOR.to_string(),
+            fallback_aggregators: Vec::new(),
             publisher_url: DEFAULT_WALRUS_PUBLISHER.to_string(),
+            fallback_publishers: Vec::new(),
             epochs: Some(DEFAULT_EPOCHS),
+            retry: RetryPolicy::default(),
         }
     }
 }
 
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(DEFAULT_WALRUS_TIMEOUT_SECS),
+            retries: DEFAULT_WALRUS_RETRIES,
+            initial_backoff: Duration::from_millis(WALRUS_INITIAL_BACKOFF_MS),
+            max_backoff: Duration::from_millis(WALRUS_MAX_BACKOFF_MS),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry round `round` (1-based): the exponential backoff,
+    /// capped at `max_backoff`, scaled by a random factor in [0.5, 1] so that
+    /// parallel uploads do not retry in lockstep
+    pub fn backoff(&self, round: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(round.saturating_sub(1)))
+            .min(self.max_backoff);
+        exponential.mul_f64(0.5 + 0.5 * random_fraction())
+    }
+}
+
+/// Uniform in [0, 1), from the randomly keyed std hasher
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A blob accepted by the Walrus publisher
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredBlob {
+    pub blob_id: String,
+    /// Last epoch the blob is stored for
+    pub end_epoch: u64,
+    /// `false` if Walrus already held a certified copy of the same contents
+    pub newly_created: bool,
+    /// Publisher that accepted the blob
+    pub publisher_url: String,
+    /// Bytes sent in the accepted request
+    pub size: u64,
+    /// Hex SHA-256 of the bytes sent, computed while streaming them
+    pub sha256: String,
+}
+
+/// A blob read back from Walrus and written to disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadedBlob {
+    /// Where the blob was written
+    pub path: PathBuf,
+    /// Image format detected from the blob's magic bytes
+    pub format: ImageFormat,
+    pub size: u64,
+    /// Hex SHA-256 of the blob contents
+    pub sha256: String,
+    /// Aggregator that served the blob
+    pub aggregator_url: String,
+}
+
 impl WalrusOptions {
     pub fn new(
         aggregator_url: Option<String>,
@@ -30,52 +129,735 @@ impl WalrusOptions {
     ) -> Self {
         Self {
             aggregator_url: aggregator_url.unwrap_or_else(|| DEFAULT_WALRUS_AGGREGATOR.to_string()),
+            fallback_aggregators: Vec::new(),
             publisher_url: publisher_url.unwrap_or_else(|| DEFAULT_WALRUS_PUBLISHER.to_string()),
+            fallback_publishers: Vec::new(),
             epochs,
+            retry: RetryPolicy::default(),
         }
     }
 }
 
-pub async fn upload_to_walrus_async(file_path: &Path, options: &WalrusOptions) -> Result<String> {
+/// What one store attempt has sent so far
+#[derive(Default)]
+struct Sent {
+    bytes: AtomicU64,
+    /// Fed every chunk as it is sent; covers the whole blob once it is accepted
+    hasher: Mutex<Sha256>,
+}
+
+impl Sent {
+    fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    fn sha256(&self) -> String {
+        let mut hasher = self.hasher.lock().unwrap_or_else(PoisonError::into_inner);
+        to_hex(&std::mem::take(&mut *hasher).finalize())
+    }
+}
+
+/// Contents of a blob, sent to the publisher as a stream of chunks
+#[derive(Debug, Clone)]
+enum BlobSource {
+    /// Read from disk while it is sent; reopened for every attempt
+    File(PathBuf),
+    /// Already in memory, e.g. a freshly encoded image; attempts share the buffer
+    Memory(Bytes),
+}
+
+impl BlobSource {
+    /// A request body that advances `progress` and `sent` as chunks are sent
+    async fn body(&self, progress: &ProgressBar, sent: &Arc<Sent>) -> Result<Body> {
+        let chunks = match self {
+            BlobSource::File(path) => {
+                let file = tokio::fs::File::open(path).await?;
+                ReaderStream::with_capacity(file, WALRUS_UPLOAD_CHUNK_SIZE).boxed()
+            }
+            BlobSource::Memory(data) => {
+                let data = data.clone();
+                let len = data.len();
+                stream::iter(
+                    (0..len)
+                        .step_by(WALRUS_UPLOAD_CHUNK_SIZE)
+                        .map(move |start| {
+                            Ok(data.slice(start..(start + WALRUS_UPLOAD_CHUNK_SIZE).min(len)))
+                        }),
+                )
+                .boxed()
+            }
+        };
+
+        let progress = progress.clone();
+        let sent = Arc::clone(sent);
+        Ok(Body::wrap_stream(chunks.inspect_ok(move |chunk| {
+            progress.inc(chunk.len() as u64);
+            sent.bytes.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            sent.hasher
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .update(chunk);
+        })))
+    }
+}
+
+/// A publisher with the client every attempt against it goes through
+struct Publisher {
+    url: String,
+    /// Why no client could be built, reported when the publisher is tried
+    client: std::result::Result<WalrusClient, String>,
+}
+
+/// The publishers of `options`, in the order they are tried, each with a
+/// client built once so its connections are reused across attempts and blobs.
+/// Empty URLs are left out.
+fn publishers(options: &WalrusOptions) -> Vec<Publisher> {
+    std::iter::once(&options.publisher_url)
+        .chain(&options.fallback_publishers)
+        .filter(|url| !url.is_empty())
+        .map(|url| Publisher {
+            url: url.clone(),
+            client: WalrusClient::new(&options.aggregator_url, url).map_err(|e| e.to_string()),
+        })
+        .collect()
+}
+
+/// Uploads blobs from synchronous code, e.g. rayon workers, through one tokio
+/// runtime and one client per publisher, however many blobs are stored
+pub struct WalrusUploader {
+    runtime: tokio::runtime::Runtime,
+    publishers: Vec<Publisher>,
+    options: WalrusOptions,
+}
+
+impl WalrusUploader {
+    /// Starts the runtime and builds the publisher clients.
+    ///
+    /// # Arguments
+    /// * `options` - Walrus endpoints, storage duration and retry policy used for every blob
+    ///
+    /// # Returns
+    /// * `Ok(WalrusUploader)` - Ready to upload
+    /// * `Err(CompressionError)` - If the runtime cannot be started
+    pub fn new(options: &WalrusOptions) -> Result<Self> {
+        Ok(Self {
+            runtime: tokio::runtime::Runtime::new()?,
+            publishers: publishers(options),
+            options: options.clone(),
+        })
+    }
+
+    /// As `upload_bytes_to_walrus_async`, blocking the calling thread until
+    /// the blob is stored. May be called from several threads at once.
+    pub fn upload_bytes(
+        &self,
+        data: impl Into<Bytes>,
+        progress: &ProgressBar,
+    ) -> Result<StoredBlob> {
+        self.runtime.block_on(upload_blob(
+            BlobSource::Memory(data.into()),
+            &self.publishers,
+            &self.options,
+            progress,
+        ))
+    }
+}
+
+/// Streams a file to Walrus without reading it into memory first.
+///
+/// # Arguments
+/// * `file_path` - File to upload
+/// * `options` - Walrus endpoints, storage duration and retry policy
+///
+/// # Returns
+/// * `Ok(StoredBlob)` - The blob ID, how long the blob is stored and which publisher took it
+/// * `Err(CompressionError)` - If the file is missing or no publisher accepts it
+pub async fn upload_to_walrus_async(
+    file_path: &Path,
+    options: &WalrusOptions,
+) -> Result<StoredBlob> {
+    upload_to_walrus_async_with_progress(file_path, options, &ProgressBar::hidden()).await
+}
+
+/// As `upload_to_walrus_async`, advancing `progress` by the bytes sent;
+/// failed attempts are taken back off
+pub async fn upload_to_walrus_async_with_progress(
+    file_path: &Path,
+    options: &WalrusOptions,
+    progress: &ProgressBar,
+) -> Result<StoredBlob> {
     if !file_path.exists() {
         return Err(CompressionError::FileNotFound(file_path.to_path_buf()));
     }
 
-    let mut file = File::open(file_path).map_err(CompressionError::Io)?;
+    upload_blob(
+        BlobSource::File(file_path.to_path_buf()),
+        &publishers(options),
+        options,
+        progress,
+    )
+    .await
+}
+
+/// Stores an in-memory blob, e.g. a freshly encoded image, on Walrus.
+///
+/// The buffer is streamed as is; retries reuse it instead of copying it.
+///
+/// # Arguments
+/// * `data` - Blob contents
+/// * `options` - Walrus endpoints, storage duration and retry policy
+/// * `progress` - Advanced by the bytes sent; failed attempts are taken back off
+///
+/// # Returns
+/// * `Ok(StoredBlob)` - The blob ID, how long the blob is stored and which publisher took it
+/// * `Err(CompressionError)` - The last publisher error once no publisher is left to try
+pub async fn upload_bytes_to_walrus_async(
+    data: impl Into<Bytes>,
+    options: &WalrusOptions,
+    progress: &ProgressBar,
+) -> Result<StoredBlob> {
+    upload_blob(
+        BlobSource::Memory(data.into()),
+        &publishers(options),
+        options,
+        progress,
+    )
+    .await
+}
+
+/// Each publisher is tried in turn. Publishers that fail with a transient error
+/// are tried again in later rounds, after an exponential backoff with jitter;
+/// publishers that refuse the blob outright are not.
+async fn upload_blob(
+    source: BlobSource,
+    publishers: &[Publisher],
+    options: &WalrusOptions,
+    progress: &ProgressBar,
+) -> Result<StoredBlob> {
+    if publishers.is_empty() {
+        return Err(CompressionError::Config(
+            "no Walrus publisher configured".to_string(),
+        ));
+    }
+    let mut retryable = vec![true; publishers.len()];
+    let mut last_error = None;
+
+    for round in 0..=options.retry.retries {
+        if round > 0 {
+            tokio::time::sleep(options.retry.backoff(round)).await;
+        }
+        for (publisher, retryable) in publishers.iter().zip(retryable.iter_mut()) {
+            if !*retryable {
+                continue;
+            }
+            let sent = Arc::new(Sent::default());
+            let body = source.body(progress, &sent).await?;
+            match store_blob_once(publisher, body, &sent, options).await {
+                Ok(stored) => return Ok(stored),
+                Err(error) => {
+                    // 下一次尝试会重新发送整个 blob
+                    progress.dec(sent.bytes());
+                    *retryable = is_transient(&error);
+                    last_error = Some(error);
+                }
+            }
+        }
+        if !retryable.contains(&true) {
+            break;
+        }
+    }
 
-    let mut data = Vec::new();
-    file.read_to_end(&mut data).map_err(CompressionError::Io)?;
+    Err(last_error
+        .unwrap_or_else(|| CompressionError::Config("no Walrus publisher configured".to_string())))
+}
 
-    let client =
-        WalrusClient::new(&options.aggregator_url, &options.publisher_url).map_err(|e| {
-            CompressionError::WalrusUpload(format!("Failed to create Walrus client: {}", e))
-        })?;
+/// One store request to one publisher, bounded by the retry policy's timeout
+async fn store_blob_once(
+    publisher: &Publisher,
+    body: Body,
+    sent: &Sent,
+    options: &WalrusOptions,
+) -> Result<StoredBlob> {
+    let publisher_url = publisher.url.as_str();
+    let client = publisher
+        .client
+        .as_ref()
+        .map_err(|e| CompressionError::Config(format!("Invalid Walrus URL: {}", e)))?;
 
     // 设置 deletable 标志，便于未来可能的删除功能
-    let store_result = client
-        .store_blob(data, options.epochs, Some(true), None, None)
+    let store = client.store_blob(body, options.epochs, Some(true), None, None);
+    let store_result = tokio::time::timeout(options.retry.timeout, store)
         .await
-        .map_err(|e| CompressionError::WalrusUpload(format!("Failed to store blob: {}", e)))?;
+        .map_err(|_| {
+            CompressionError::WalrusNetwork(
+                publisher_url.to_string(),
+                format!("timed out after {:?}", options.retry.timeout),
+            )
+        })?
+        .map_err(|e| classify_walrus_error(publisher_url, e))?;
 
     if let Some(newly_created) = store_result.newly_created {
-        Ok(newly_created.blob_object.blob_id)
+        Ok(StoredBlob {
+            blob_id: newly_created.blob_object.blob_id,
+            end_epoch: newly_created.blob_object.storage.end_epoch,
+            newly_created: true,
+            publisher_url: publisher_url.to_string(),
+            size: sent.bytes(),
+            sha256: sent.sha256(),
+        })
+    } else if let Some(already_certified) = store_result.already_certified {
+        // 相同内容已存储过，Walrus 直接返回已有的 blob
+        Ok(StoredBlob {
+            blob_id: already_certified.blob_id,
+            end_epoch: already_certified.end_epoch,
+            newly_created: false,
+            publisher_url: publisher_url.to_string(),
+            size: sent.bytes(),
+            sha256: sent.sha256(),
+        })
     } else {
-        Err(CompressionError::WalrusUpload(
-            "Failed to create new blob".to_string(),
+        Err(CompressionError::WalrusBadResponse(
+            publisher_url.to_string(),
+            "neither a new nor an already certified blob".to_string(),
         ))
     }
 }
 
-pub fn upload_to_walrus_sync(file_path: &Path, options: &WalrusOptions) -> Result<String> {
-    let runtime = tokio::runtime::Runtime::new()
-        .map_err(|e| CompressionError::WalrusUpload(format!("Failed to create runtime: {}", e)))?;
+/// Maps a client error to the typed variant for `url`
+fn classify_walrus_error(url: &str, error: WalrusError) -> CompressionError {
+    let status = match &error {
+        WalrusError::HttpRequest(e) => e.status(),
+        WalrusError::ApiError(status, _) => Some(*status),
+        _ => None,
+    };
+    match (status, error) {
+        (Some(status), _) => match status.as_u16() {
+            // Payment required / payload too large: the publisher will not store this blob
+            code @ (402 | 413) => CompressionError::WalrusQuota(url.to_string(), code),
+            code => CompressionError::WalrusHttpStatus(url.to_string(), code),
+        },
+        (None, WalrusError::HttpRequest(e)) if e.is_decode() => {
+            CompressionError::WalrusBadResponse(url.to_string(), e.to_string())
+        }
+        (None, WalrusError::HttpRequest(e)) => {
+            CompressionError::WalrusNetwork(url.to_string(), e.to_string())
+        }
+        (None, WalrusError::InvalidUrl(message)) => {
+            CompressionError::Config(format!("Invalid Walrus URL: {}", message))
+        }
+        (None, error) => CompressionError::WalrusBadResponse(url.to_string(), error.to_string()),
+    }
+}
+
+/// Whether retrying the same publisher later may succeed
+fn is_transient(error: &CompressionError) -> bool {
+    match error {
+        CompressionError::WalrusNetwork(_, _) => true,
+        CompressionError::WalrusHttpStatus(_, code) => {
+            matches!(code, 408 | 429) || (500..600).contains(code)
+        }
+        _ => false,
+    }
+}
+
+pub fn upload_to_walrus_sync(file_path: &Path, options: &WalrusOptions) -> Result<StoredBlob> {
+    upload_to_walrus_sync_with_progress(file_path, options, &ProgressBar::hidden())
+}
+
+pub fn upload_to_walrus_sync_with_progress(
+    file_path: &Path,
+    options: &WalrusOptions,
+    progress: &ProgressBar,
+) -> Result<StoredBlob> {
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    runtime.block_on(upload_to_walrus_async_with_progress(
+        file_path, options, progress,
+    ))
+}
+
+pub fn upload_bytes_to_walrus_sync(
+    data: impl Into<Bytes>,
+    options: &WalrusOptions,
+    progress: &ProgressBar,
+) -> Result<StoredBlob> {
+    let runtime = tokio::runtime::Runtime::new()?;
 
-    runtime.block_on(upload_to_walrus_async(file_path, options))
+    runtime.block_on(upload_bytes_to_walrus_async(data, options, progress))
+}
+
+/// Uploads several files with at most `concurrency` requests in flight.
+///
+/// A failed file does not stop the others.
+///
+/// # Arguments
+/// * `files` - Files to upload
+/// * `options` - Walrus endpoints and storage duration shared by every file
+/// * `concurrency` - Maximum number of simultaneous uploads (at least 1)
+/// * `progress` - Shared by all files and advanced by the bytes sent
+///
+/// # Returns
+/// * One result per file, in the order of `files`
+pub async fn upload_files_to_walrus_async(
+    files: &[PathBuf],
+    options: &WalrusOptions,
+    concurrency: usize,
+    progress: &ProgressBar,
+) -> Vec<Result<StoredBlob>> {
+    let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+    let options = Arc::new(options.clone());
+    // 所有文件共用同一组客户端
+    let publishers = Arc::new(publishers(&options));
+    let tasks: Vec<_> = files
+        .iter()
+        .map(|file| {
+            let permits = Arc::clone(&permits);
+            let options = Arc::clone(&options);
+            let publishers = Arc::clone(&publishers);
+            let progress = progress.clone();
+            let file = file.clone();
+            tokio::spawn(async move {
+                // The semaphore is never closed, so acquiring only waits
+                let _permit = permits.acquire_owned().await;
+                if !file.exists() {
+                    return Err(CompressionError::FileNotFound(file));
+                }
+                upload_blob(BlobSource::File(file), &publishers, &options, &progress).await
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(
+            task.await
+                .unwrap_or_else(|e| Err(CompressionError::Io(std::io::Error::other(e)))),
+        );
+    }
+    results
+}
+
+pub fn upload_files_to_walrus_sync(
+    files: &[PathBuf],
+    options: &WalrusOptions,
+    concurrency: usize,
+    progress: &ProgressBar,
+) -> Result<Vec<Result<StoredBlob>>> {
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    Ok(runtime.block_on(upload_files_to_walrus_async(
+        files,
+        options,
+        concurrency,
+        progress,
+    )))
+}
+
+/// Reads a blob from Walrus and writes it to `output_path`.
+///
+/// The blob is streamed into a temporary file next to its destination and
+/// hashed on the way. It is renamed into place only once it is complete, is an
+/// image (its format is detected from its magic bytes) and matches
+/// `expected_sha256`, so a failed or rejected download never leaves a partial
+/// file behind. If `output_path` is an existing directory the file is named
+/// `<blob_id>.<ext>`.
+///
+/// Aggregators are tried the way uploads try publishers: `aggregator_url`,
+/// then `fallback_aggregators`, for up to `retry.retries` more rounds after
+/// transient failures, each read bounded by `retry.timeout`.
+///
+/// # Arguments
+/// * `blob_id` - ID returned when the blob was stored
+/// * `output_path` - Output file or directory
+/// * `options` - Walrus aggregators and retry policy; publishers are not used
+/// * `expected_sha256` - Hex SHA-256 recorded at upload time, checked before the file is kept
+///
+/// # Returns
+/// * `Ok(DownloadedBlob)` - Where the blob was written, its format, size and hash
+/// * `Err(CompressionError::HashMismatch)` - If the contents differ from `expected_sha256`
+/// * `Err(CompressionError)` - If the blob cannot be read, is not an image or cannot be written
+pub async fn download_from_walrus_async(
+    blob_id: &str,
+    output_path: &Path,
+    options: &WalrusOptions,
+    expected_sha256: Option<&str>,
+) -> Result<DownloadedBlob> {
+    // Blob IDs are URL-safe base64; anything else could escape the URL or the output directory
+    if blob_id.is_empty()
+        || !blob_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(CompressionError::WalrusDownload(format!(
+            "Invalid blob ID: {:?}",
+            blob_id
+        )));
+    }
+
+    let into_dir = output_path.is_dir();
+    let temp_path = if into_dir {
+        output_path.join(format!(".{}.part", blob_id))
+    } else {
+        temp_path_for(output_path)?
+    };
+    let result = read_blob(blob_id, &temp_path, options)
+        .await
+        .and_then(|read| {
+            let format = image::guess_format(&read.head).map_err(|_| {
+                CompressionError::UnsupportedFormat(format!(
+                    "blob {} is not a recognized image",
+                    blob_id
+                ))
+            })?;
+            if let Some(expected) = expected_sha256 {
+                if !expected.eq_ignore_ascii_case(&read.sha256) {
+                    return Err(CompressionError::HashMismatch(
+                        expected.to_string(),
+                        read.sha256,
+                    ));
+                }
+            }
+
+            let path = if into_dir {
+                let ext = format.extensions_str().first().copied().unwrap_or("bin");
+                output_path.join(format!("{}.{}", blob_id, ext))
+            } else {
+                output_path.to_path_buf()
+            };
+            fs::rename(&temp_path, &path)?;
+            Ok(DownloadedBlob {
+                path,
+                format,
+                size: read.size,
+                sha256: read.sha256,
+                aggregator_url: read.aggregator_url,
+            })
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// A blob streamed into a temporary file
+struct ReadBlob {
+    aggregator_url: String,
+    size: u64,
+    /// Hex SHA-256 of the contents
+    sha256: String,
+    /// The first `WALRUS_FORMAT_SNIFF_BYTES` bytes, to detect the format
+    head: Vec<u8>,
+}
+
+/// Each aggregator is tried in turn, with the retry rounds of `upload_blob`.
+/// Every attempt rewrites `temp_path` from the start.
+async fn read_blob(blob_id: &str, temp_path: &Path, options: &WalrusOptions) -> Result<ReadBlob> {
+    let aggregators: Vec<&String> = std::iter::once(&options.aggregator_url)
+        .chain(&options.fallback_aggregators)
+        .filter(|url| !url.is_empty())
+        .collect();
+    if aggregators.is_empty() {
+        return Err(CompressionError::Config(
+            "no Walrus aggregator configured".to_string(),
+        ));
+    }
+    let client = reqwest::Client::new();
+    let mut retryable = vec![true; aggregators.len()];
+    let mut last_error = None;
+
+    for round in 0..=options.retry.retries {
+        if round > 0 {
+            tokio::time::sleep(options.retry.backoff(round)).await;
+        }
+        for (aggregator, retryable) in aggregators.iter().zip(retryable.iter_mut()) {
+            if !*retryable {
+                continue;
+            }
+            let read = read_blob_once(&client, aggregator, blob_id, temp_path);
+            let result = tokio::time::timeout(options.retry.timeout, read)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(CompressionError::WalrusNetwork(
+                        aggregator.to_string(),
+                        format!("timed out after {:?}", options.retry.timeout),
+                    ))
+                });
+            match result {
+                Ok(read) => return Ok(read),
+                Err(error @ CompressionError::Io(_)) => return Err(error),
+                Err(error) => {
+                    *retryable = is_transient(&error);
+                    last_error = Some(error);
+                }
+            }
+        }
+        if !retryable.contains(&true) {
+            break;
+        }
+    }
+
+    let error = last_error.map_or_else(String::new, |error| error.to_string());
+    Err(CompressionError::WalrusDownload(format!(
+        "Failed to read blob {}: {}",
+        blob_id, error
+    )))
+}
+
+/// One read from one aggregator, streamed into `temp_path` and hashed on the way
+async fn read_blob_once(
+    client: &reqwest::Client,
+    aggregator_url: &str,
+    blob_id: &str,
+    temp_path: &Path,
+) -> Result<ReadBlob> {
+    let url = Url::parse(aggregator_url)
+        .and_then(|url| url.join(&format!("v1/blobs/{}", blob_id)))
+        .map_err(|e| CompressionError::Config(format!("Invalid Walrus URL: {}", e)))?;
+    let network = |e: reqwest::Error| {
+        CompressionError::WalrusNetwork(aggregator_url.to_string(), e.to_string())
+    };
+    let response = client.get(url).send().await.map_err(network)?;
+    if !response.status().is_success() {
+        return Err(CompressionError::WalrusHttpStatus(
+            aggregator_url.to_string(),
+            response.status().as_u16(),
+        ));
+    }
+
+    let mut file = tokio::fs::File::create(temp_path).await?;
+    let mut hasher = Sha256::new();
+    let mut head = Vec::with_capacity(WALRUS_FORMAT_SNIFF_BYTES);
+    let mut size = 0;
+    let mut chunks = response.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(network)?;
+        hasher.update(&chunk);
+        let missing = WALRUS_FORMAT_SNIFF_BYTES - head.len();
+        head.extend_from_slice(&chunk[..missing.min(chunk.len())]);
+        file.write_all(&chunk).await?;
+        size += chunk.len() as u64;
+    }
+    file.flush().await?;
+
+    Ok(ReadBlob {
+        aggregator_url: aggregator_url.to_string(),
+        size,
+        sha256: to_hex(&hasher.finalize()),
+        head,
+    })
+}
+
+pub fn download_from_walrus_sync(
+    blob_id: &str,
+    output_path: &Path,
+    options: &WalrusOptions,
+    expected_sha256: Option<&str>,
+) -> Result<DownloadedBlob> {
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| {
+        CompressionError::WalrusDownload(format!("Failed to create runtime: {}", e))
+    })?;
+
+    runtime.block_on(download_from_walrus_async(
+        blob_id,
+        output_path,
+        options,
+        expected_sha256,
+    ))
+}
+
+/// Hidden temporary file next to `path`, creating its directory if needed
+fn temp_path_for(path: &Path) -> Result<PathBuf> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .map_err(|_| CompressionError::DirectoryCreationFailed(parent.to_path_buf()))?;
+    }
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| CompressionError::FileNotFound(path.to_path_buf()))?;
+    Ok(path.with_file_name(format!(".{}.part", file_name.to_string_lossy())))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::incremental::hash_bytes;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Reads an HTTP/1.1 request, returning its request line and body; streamed
+    /// uploads arrive with chunked transfer encoding
+    fn read_request(stream: &TcpStream) -> (String, Vec<u8>) {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut content_length = 0;
+        let mut chunked = false;
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            let header = line.to_lowercase();
+            if let Some(value) = header.strip_prefix("content-length:") {
+                content_length = value.trim().parse().unwrap();
+            }
+            chunked |= header.starts_with("transfer-encoding:") && header.contains("chunked");
+            line.clear();
+        }
+        if !chunked {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            return (request_line, body);
+        }
+
+        let mut body = Vec::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            let size = usize::from_str_radix(line.trim(), 16).unwrap();
+            let mut chunk = vec![0; size + 2]; // data and its CRLF
+            reader.read_exact(&mut chunk).unwrap();
+            if size == 0 {
+                return (request_line, body);
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    }
+
+    /// A publisher answering every request with `status` and `body`, and the
+    /// number of requests it received
+    fn scripted_publisher(status: &'static str, body: &'static str) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                read_request(&stream);
+                counter.fetch_add(1, Ordering::SeqCst);
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    fn fast_retry(retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            timeout: Duration::from_secs(5),
+            retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+        }
+    }
 
     #[test]
     fn test_walrus_options_default() {
@@ -110,4 +892,403 @@ mod tests {
         let result = upload_to_walrus_async(Path::new("nonexistent.jpg"), &options).await;
         assert!(matches!(result, Err(CompressionError::FileNotFound(_))));
     }
+
+    /// Stands in for Walrus: serves `GET /v1/blobs/<blob_id>` with `body` like an
+    /// aggregator and stores `PUT /v1/blobs` like a publisher, naming each blob
+    /// `stored-<sha256 prefix>` and storing it until epoch 100 + `epochs`
+    fn walrus_stand_in(blob_id: &str, body: Vec<u8>) -> String {
+        use std::io::Write;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let blob_path = format!("/v1/blobs/{}", blob_id);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let (request_line, request_body) = read_request(&stream);
+
+                let (status, response) = if request_line.starts_with(&format!("GET {} ", blob_path))
+                {
+                    ("200 OK", body.clone())
+                } else if request_line.starts_with("PUT /v1/blobs?") {
+                    let epochs: u64 = request_line
+                        .split(['?', '&', ' '])
+                        .find_map(|pair| pair.strip_prefix("epochs="))
+                        .map_or(1, |epochs| epochs.parse().unwrap());
+                    let stored = serde_json::json!({
+                        "newlyCreated": {
+                            "blobObject": {
+                                "id": "0x1",
+                                "registeredEpoch": 100,
+                                "blobId": format!("stored-{}", &hash_bytes(&request_body)[..12]),
+                                "size": request_body.len(),
+                                "encodingType": "RS2",
+                                "certifiedEpoch": 100,
+                                "storage": {
+                                    "id": "0x2",
+                                    "startEpoch": 100,
+                                    "endEpoch": 100 + epochs,
+                                    "storageSize": request_body.len()
+                                },
+                                "deletable": true
+                            },
+                            "resourceOperation": { "registerFromScratch": null },
+                            "cost": 1
+                        }
+                    });
+                    ("200 OK", stored.to_string().into_bytes())
+                } else {
+                    ("404 Not Found", b"not found".to_vec())
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    response.len()
+                )
+                .unwrap();
+                stream.write_all(&response).unwrap();
+            }
+        });
+        url
+    }
+
+    fn png_bytes() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::RgbImage::new(4, 4)
+            .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[tokio::test]
+    async fn test_download_from_walrus_names_file_after_blob() {
+        let png = png_bytes();
+        let url = walrus_stand_in("blob_1", png.clone());
+        let options = WalrusOptions::new(Some(url), None, None);
+        let dir = tempfile::tempdir().unwrap();
+
+        let blob = download_from_walrus_async("blob_1", dir.path(), &options, None)
+            .await
+            .unwrap();
+        assert_eq!(blob.path, dir.path().join("blob_1.png"));
+        assert_eq!(blob.format, ImageFormat::Png);
+        assert_eq!(fs::read(&blob.path).unwrap(), png);
+
+        // Verified against the hash recorded for the blob
+        let output = dir.path().join("copy.png");
+        let verified = download_from_walrus_async(
+            "blob_1",
+            &output,
+            &options,
+            Some(&blob.sha256.to_uppercase()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(verified.sha256, blob.sha256);
+
+        let missing = download_from_walrus_async("other", &output, &options, None).await;
+        assert!(matches!(missing, Err(CompressionError::WalrusDownload(_))));
+        let invalid = download_from_walrus_async("../x", &output, &options, None).await;
+        assert!(matches!(invalid, Err(CompressionError::WalrusDownload(_))));
+    }
+
+    #[tokio::test]
+    async fn test_download_from_walrus_rejects_bad_content_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("image.png");
+
+        let url = walrus_stand_in("blob", png_bytes());
+        let options = WalrusOptions::new(Some(url), None, None);
+        let result = download_from_walrus_async("blob", &output, &options, Some("00ff")).await;
+        assert!(matches!(result, Err(CompressionError::HashMismatch(_, _))));
+
+        let url = walrus_stand_in("blob", b"plain text".to_vec());
+        let options = WalrusOptions::new(Some(url), None, None);
+        let result = download_from_walrus_async("blob", &output, &options, None).await;
+        assert!(matches!(
+            result,
+            Err(CompressionError::UnsupportedFormat(_))
+        ));
+
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_download_retries_and_fails_over_to_other_aggregators() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("image.png");
+        let png = png_bytes();
+
+        let (busy, busy_requests) = scripted_publisher("503 Service Unavailable", "busy");
+        let mut options = WalrusOptions::new(Some(busy.clone()), None, None);
+        options.fallback_aggregators = vec![walrus_stand_in("blob", png.clone())];
+        options.retry = fast_retry(2);
+        let blob = download_from_walrus_async("blob", &output, &options, None)
+            .await
+            .unwrap();
+        assert_eq!(blob.aggregator_url, options.fallback_aggregators[0]);
+        assert_eq!(busy_requests.load(Ordering::SeqCst), 1);
+        assert_eq!(fs::read(&output).unwrap(), png);
+
+        // A lone busy aggregator is retried, then the download gives up
+        options.fallback_aggregators.clear();
+        let result =
+            download_from_walrus_async("blob", &dir.path().join("other.png"), &options, None).await;
+        assert!(matches!(result, Err(CompressionError::WalrusDownload(_))));
+        assert_eq!(busy_requests.load(Ordering::SeqCst), 4);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        options.aggregator_url.clear();
+        let result = download_from_walrus_async("blob", &output, &options, None).await;
+        assert!(matches!(result, Err(CompressionError::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn test_download_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        // Accepts the connection but never answers
+        std::thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+        let mut options = WalrusOptions::new(Some(url), None, None);
+        options.retry = RetryPolicy {
+            timeout: Duration::from_millis(200),
+            ..fast_retry(0)
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let result =
+            download_from_walrus_async("blob", &dir.path().join("image.png"), &options, None).await;
+        match result {
+            Err(CompressionError::WalrusDownload(message)) => {
+                assert!(message.contains("timed out"), "{}", message)
+            }
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_files_to_walrus_keeps_order_and_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = Vec::new();
+        for (index, size) in [3u32, 5, 7].into_iter().enumerate() {
+            let path = dir.path().join(format!("{}.png", index));
+            image::RgbImage::new(size, size).save(&path).unwrap();
+            files.push(path);
+        }
+        files.insert(1, dir.path().join("missing.png"));
+
+        let url = walrus_stand_in("unused", Vec::new());
+        let options = WalrusOptions::new(Some(url.clone()), Some(url), Some(5));
+        let progress = ProgressBar::hidden();
+        let results = upload_files_to_walrus_async(&files, &options, 2, &progress).await;
+
+        assert_eq!(results.len(), 4);
+        assert!(matches!(results[1], Err(CompressionError::FileNotFound(_))));
+        for (file, result) in files.iter().zip(&results) {
+            if let Ok(stored) = result {
+                let expected = hash_bytes(&fs::read(file).unwrap());
+                assert_eq!(stored.blob_id, format!("stored-{}", &expected[..12]));
+                assert_eq!(stored.end_epoch, 105);
+                assert!(stored.newly_created);
+            }
+        }
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 3);
+        let sent: u64 = files
+            .iter()
+            .filter_map(|file| fs::metadata(file).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        assert_eq!(progress.position(), sent);
+    }
+
+    #[tokio::test]
+    async fn test_upload_streams_files_and_buffers_in_chunks() {
+        // Several chunks, the last one partial
+        let data: Vec<u8> = (0..WALRUS_UPLOAD_CHUNK_SIZE * 3 + 17)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let expected = format!("stored-{}", &hash_bytes(&data)[..12]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large.bin");
+        fs::write(&path, &data).unwrap();
+
+        let url = walrus_stand_in("unused", Vec::new());
+        let options = WalrusOptions::new(Some(url.clone()), Some(url), Some(1));
+        let progress = ProgressBar::hidden();
+        let stored = upload_to_walrus_async_with_progress(&path, &options, &progress)
+            .await
+            .unwrap();
+        assert_eq!(stored.blob_id, expected);
+        assert_eq!(progress.position(), data.len() as u64);
+        // Hashed on the way out, not read again
+        assert_eq!(stored.sha256, hash_bytes(&data));
+        assert_eq!(stored.size, data.len() as u64);
+
+        let progress = ProgressBar::hidden();
+        let stored = upload_bytes_to_walrus_async(data.clone(), &options, &progress)
+            .await
+            .unwrap();
+        assert_eq!(stored.blob_id, expected);
+        assert_eq!(progress.position(), data.len() as u64);
+        assert_eq!(stored.sha256, hash_bytes(&data));
+    }
+
+    #[test]
+    fn test_uploader_is_shared_by_threads() {
+        let url = walrus_stand_in("unused", Vec::new());
+        let options = WalrusOptions::new(Some(url.clone()), Some(url), Some(1));
+        let uploader = WalrusUploader::new(&options).unwrap();
+
+        std::thread::scope(|scope| {
+            for index in 0..4u8 {
+                let uploader = &uploader;
+                scope.spawn(move || {
+                    let data = vec![index; 16 + index as usize];
+                    let progress = ProgressBar::hidden();
+                    let stored = uploader.upload_bytes(data.clone(), &progress).unwrap();
+                    assert_eq!(
+                        stored.blob_id,
+                        format!("stored-{}", &hash_bytes(&data)[..12])
+                    );
+                    assert_eq!(stored.sha256, hash_bytes(&data));
+                    assert_eq!(progress.position(), data.len() as u64);
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_with_jitter_up_to_the_cap() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+            ..RetryPolicy::default()
+        };
+        for (round, full) in [(1, 100), (2, 200), (3, 350), (10, 350)] {
+            let full = Duration::from_millis(full);
+            for _ in 0..20 {
+                let delay = policy.backoff(round);
+                assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_retries_transient_errors_then_fails_over() {
+        let (primary, primary_requests) = scripted_publisher("503 Service Unavailable", "busy");
+        let mut options = WalrusOptions::new(Some(primary.clone()), Some(primary), None);
+        options.retry = fast_retry(2);
+
+        let result =
+            upload_bytes_to_walrus_async(b"blob".to_vec(), &options, &ProgressBar::hidden()).await;
+        assert!(matches!(
+            result,
+            Err(CompressionError::WalrusHttpStatus(_, 503))
+        ));
+        assert_eq!(primary_requests.load(Ordering::SeqCst), 3);
+
+        let fallback = walrus_stand_in("unused", Vec::new());
+        options.fallback_publishers = vec![fallback.clone()];
+        let progress = ProgressBar::hidden();
+        let stored = upload_bytes_to_walrus_async(b"blob".to_vec(), &options, &progress)
+            .await
+            .unwrap();
+        assert_eq!(stored.publisher_url, fallback);
+        // The attempt rejected by the primary is not counted, nor hashed twice
+        assert_eq!(progress.position(), 4);
+        assert_eq!((stored.size, stored.sha256), (4, hash_bytes(b"blob")));
+        assert_eq!(primary_requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_upload_without_publisher_is_a_config_error() {
+        let url = walrus_stand_in("unused", Vec::new());
+        let mut options = WalrusOptions::new(Some(url), Some(String::new()), None);
+        let result =
+            upload_bytes_to_walrus_async(b"blob".to_vec(), &options, &ProgressBar::hidden()).await;
+        match result {
+            Err(CompressionError::Config(message)) => {
+                assert_eq!(message, "no Walrus publisher configured")
+            }
+            other => panic!("expected a config error, got {:?}", other),
+        }
+
+        // An empty primary is skipped in favour of the fallbacks
+        let fallback = walrus_stand_in("unused", Vec::new());
+        options.fallback_publishers = vec![String::new(), fallback.clone()];
+        let stored =
+            upload_bytes_to_walrus_async(b"blob".to_vec(), &options, &ProgressBar::hidden())
+                .await
+                .unwrap();
+        assert_eq!(stored.publisher_url, fallback);
+    }
+
+    #[tokio::test]
+    async fn test_upload_does_not_retry_permanent_errors() {
+        let (quota, quota_requests) = scripted_publisher("413 Payload Too Large", "too big");
+        let (bad, bad_requests) = scripted_publisher("200 OK", "{}");
+        let (missing, missing_requests) = scripted_publisher("404 Not Found", "");
+        let mut options = WalrusOptions::new(Some(quota.clone()), Some(quota), None);
+        options.fallback_publishers = vec![bad, missing];
+        options.retry = fast_retry(3);
+
+        let result =
+            upload_bytes_to_walrus_async(b"blob".to_vec(), &options, &ProgressBar::hidden()).await;
+        assert!(matches!(
+            result,
+            Err(CompressionError::WalrusHttpStatus(_, 404))
+        ));
+        for requests in [quota_requests, bad_requests, missing_requests] {
+            assert_eq!(requests.load(Ordering::SeqCst), 1);
+        }
+
+        options.fallback_publishers.clear();
+        let result =
+            upload_bytes_to_walrus_async(b"blob".to_vec(), &options, &ProgressBar::hidden()).await;
+        assert!(matches!(result, Err(CompressionError::WalrusQuota(_, 413))));
+
+        let (bad, _) = scripted_publisher("200 OK", "{}");
+        options.publisher_url = bad;
+        let result =
+            upload_bytes_to_walrus_async(b"blob".to_vec(), &options, &ProgressBar::hidden()).await;
+        assert!(matches!(
+            result,
+            Err(CompressionError::WalrusBadResponse(_, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_upload_times_out_and_reports_network_errors() {
+        // Accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let silent = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let _streams: Vec<_> = listener.incoming().collect();
+        });
+        let mut options = WalrusOptions::new(Some(silent.clone()), Some(silent), None);
+        options.retry = RetryPolicy {
+            timeout: Duration::from_millis(200),
+            ..fast_retry(1)
+        };
+        let result =
+            upload_bytes_to_walrus_async(b"blob".to_vec(), &options, &ProgressBar::hidden()).await;
+        assert!(
+            matches!(&result, Err(CompressionError::WalrusNetwork(_, message)) if message.contains("timed out")),
+            "{:?}",
+            result
+        );
+
+        // Nothing listens on a port that was just released
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        options.publisher_url = format!("http://{}", closed);
+        let result =
+            upload_bytes_to_walrus_async(b"blob".to_vec(), &options, &ProgressBar::hidden()).await;
+        assert!(matches!(result, Err(CompressionError::WalrusNetwork(_, _))));
+    }
 }
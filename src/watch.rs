@@ -0,0 +1,433 @@
+use crate::batch::{generate_output_path, is_image_file, BatchFailure};
+use crate::constants::WATCH_POLL_INTERVAL_MS;
+use crate::error::{CompressionError, Result};
+use crate::processing::{process_image_pipeline, CompressionOptions, CompressionReport};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Settings for a watch session beyond the per-image `CompressionOptions`
+#[derive(Debug, Clone, Copy)]
+pub struct WatchOptions {
+    /// Also watch subdirectories and mirror them under the output directory
+    pub recursive: bool,
+    /// How long a file must stay untouched, with a stable size, before it is compressed
+    pub debounce: Duration,
+}
+
+/// Summary of a watch session, produced on shutdown
+#[derive(Debug, Serialize)]
+pub struct WatchReport {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// Every compression performed, in order; a file saved twice appears twice
+    pub files: Vec<CompressionReport>,
+    /// Files whose latest attempt failed
+    pub failures: Vec<BatchFailure>,
+    /// Files still being written when the session stopped
+    pub pending: Vec<PathBuf>,
+    pub total_original_size: u64,
+    pub total_compressed_size: u64,
+    /// Percentage of the total input size saved
+    pub compression_ratio: f64,
+    pub elapsed_secs: f64,
+}
+
+/// A changed file waiting for its writes to settle
+struct PendingFile {
+    last_event: Instant,
+    size: Option<u64>,
+}
+
+/// State of a running watch session
+struct WatchSession<'a> {
+    input_root: PathBuf,
+    output_root: PathBuf,
+    output_dir: &'a Path,
+    options: &'a CompressionOptions,
+    watch_options: WatchOptions,
+    pending: HashMap<PathBuf, PendingFile>,
+    /// Size and modification time of each input when it was last compressed
+    processed: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+    /// Output path -> input that owns it
+    claimed: HashMap<PathBuf, PathBuf>,
+    files: Vec<CompressionReport>,
+    failures: BTreeMap<PathBuf, CompressionError>,
+}
+
+/// Watches `input_dir` and compresses new or modified images into `output_dir`
+/// until `stop` is set.
+///
+/// A file is compressed once no event has been seen for it during
+/// `watch_options.debounce` and its size has stopped changing, so partially
+/// written exports are not picked up. Files that fail (e.g. because they were
+/// still incomplete) are retried on their next change. A `👀 Watching` line
+/// is printed to stderr once the watcher is running.
+///
+/// # Arguments
+/// * `input_dir` - Directory to watch
+/// * `output_dir` - Output directory, created if missing
+/// * `options` - Compression options applied to every file
+/// * `watch_options` - Recursion and debounce settings
+/// * `stop` - Set (e.g. from a Ctrl+C handler) to end the session
+///
+/// # Returns
+/// * `Ok(WatchReport)` - Everything compressed during the session
+/// * `Err(CompressionError)` - If the directories cannot be set up or watched
+pub fn watch_directory(
+    input_dir: &Path,
+    output_dir: &Path,
+    options: &CompressionOptions,
+    watch_options: WatchOptions,
+    stop: &AtomicBool,
+) -> Result<WatchReport> {
+    let start_time = Instant::now();
+    let mut session = WatchSession::new(input_dir, output_dir, options, watch_options)?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).map_err(|e| CompressionError::Watch(e.to_string()))?;
+    let mode = if watch_options.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher
+        .watch(&session.input_root, mode)
+        .map_err(|e| CompressionError::Watch(e.to_string()))?;
+    // Printed once events are being delivered, so callers can wait for it
+    eprintln!(
+        "👀 Watching {:?} -> {:?} (Ctrl+C to stop)",
+        input_dir, output_dir
+    );
+
+    let poll_interval = Duration::from_millis(WATCH_POLL_INTERVAL_MS);
+    while !stop.load(Ordering::SeqCst) {
+        match receiver.recv_timeout(poll_interval) {
+            Ok(Ok(event)) => session.observe(event),
+            Ok(Err(e)) => eprintln!("⚠️  Watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        session.process_settled();
+    }
+
+    Ok(session.into_report(input_dir, start_time))
+}
+
+impl<'a> WatchSession<'a> {
+    /// Checks the input directory and creates the output directory
+    fn new(
+        input_dir: &Path,
+        output_dir: &'a Path,
+        options: &'a CompressionOptions,
+        watch_options: WatchOptions,
+    ) -> Result<Self> {
+        if !input_dir.is_dir() {
+            return Err(CompressionError::FileNotFound(input_dir.to_path_buf()));
+        }
+        fs::create_dir_all(output_dir)
+            .map_err(|_| CompressionError::DirectoryCreationFailed(output_dir.to_path_buf()))?;
+
+        Ok(Self {
+            // Event paths are reported below the canonical watched path
+            input_root: input_dir.canonicalize()?,
+            output_root: output_dir.canonicalize()?,
+            output_dir,
+            options,
+            watch_options,
+            pending: HashMap::new(),
+            processed: HashMap::new(),
+            claimed: HashMap::new(),
+            files: Vec::new(),
+            failures: BTreeMap::new(),
+        })
+    }
+
+    /// Queues the images touched by a file system event
+    fn observe(&mut self, event: Event) {
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            return;
+        }
+        for path in event.paths {
+            // Outputs written inside the watched tree must not be picked up again
+            if !is_image_file(&path) || path.starts_with(&self.output_root) {
+                continue;
+            }
+            let size = fs::metadata(&path).map(|m| m.len()).ok();
+            self.pending.insert(
+                path,
+                PendingFile {
+                    last_event: Instant::now(),
+                    size,
+                },
+            );
+        }
+    }
+
+    /// Compresses the pending files whose writes have finished
+    fn process_settled(&mut self) {
+        let debounce = self.watch_options.debounce;
+        let settled: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.last_event.elapsed() >= debounce)
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in settled {
+            let Ok(metadata) = fs::metadata(&path) else {
+                // Removed or renamed away before it settled
+                self.pending.remove(&path);
+                continue;
+            };
+            let pending = self
+                .pending
+                .get_mut(&path)
+                .expect("settled file is pending");
+            if pending.size != Some(metadata.len()) {
+                // Still growing: wait for another quiet period
+                pending.size = Some(metadata.len());
+                pending.last_event = Instant::now();
+                continue;
+            }
+            self.pending.remove(&path);
+
+            let state = (metadata.len(), metadata.modified().ok());
+            if self.processed.get(&path) == Some(&state) {
+                continue;
+            }
+            match self.compress(&path) {
+                Ok(report) => {
+                    eprintln!(
+                        "✅ {:?} -> {:?} ({} -> {} bytes)",
+                        report.input,
+                        report.output,
+                        report.original_size,
+                        report.encode.compressed_size
+                    );
+                    self.failures.remove(&path);
+                    self.processed.insert(path, state);
+                    self.files.push(report);
+                }
+                Err(e) => {
+                    eprintln!("❌ Failed to process {:?}: {}", path, e);
+                    self.processed.remove(&path);
+                    self.failures.insert(path, e);
+                }
+            }
+        }
+    }
+
+    fn compress(&mut self, input_path: &Path) -> Result<CompressionReport> {
+        let relative_dir = input_path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(&self.input_root).ok())
+            .unwrap_or(Path::new(""));
+        let output_dir = self.output_dir.join(relative_dir);
+        let output_path = generate_output_path(input_path, &output_dir, &self.options.format)?;
+
+        // An output stays with its first input for as long as that input exists
+        if let Some(owner) = self.claimed.get(&output_path) {
+            if owner != input_path && owner.exists() {
+                return Err(CompressionError::OutputCollision(
+                    output_path,
+                    owner.clone(),
+                ));
+            }
+        }
+        fs::create_dir_all(&output_dir)
+            .map_err(|_| CompressionError::DirectoryCreationFailed(output_dir.clone()))?;
+
+        let report = process_image_pipeline(input_path, &output_path, self.options)?;
+        self.claimed.insert(output_path, input_path.to_path_buf());
+        Ok(report)
+    }
+
+    fn into_report(self, input_dir: &Path, start_time: Instant) -> WatchReport {
+        let total_original_size = self.files.iter().map(|f| f.original_size).sum();
+        let total_compressed_size = self.files.iter().map(|f| f.encode.compressed_size).sum();
+        let compression_ratio = if total_original_size > 0 {
+            ((total_original_size as f64 - total_compressed_size as f64)
+                / total_original_size as f64)
+                * 100.0
+        } else {
+            0.0
+        };
+        let mut pending: Vec<PathBuf> = self.pending.into_keys().collect();
+        pending.sort();
+
+        WatchReport {
+            input: input_dir.to_path_buf(),
+            output: self.output_dir.to_path_buf(),
+            files: self.files,
+            failures: self
+                .failures
+                .into_iter()
+                .map(|(path, error)| BatchFailure { path, error })
+                .collect(),
+            pending,
+            total_original_size,
+            total_compressed_size,
+            compression_ratio,
+            elapsed_secs: start_time.elapsed().as_secs_f64(),
+        }
+    }
+}
+
+/// Prints the summary of a finished watch session
+pub fn print_watch_report(report: &WatchReport) {
+    println!("\n📊 Watch Summary:");
+    println!("  📁 Watched: {:?} -> {:?}", report.input, report.output);
+    println!("  ✅ Compressions: {}", report.files.len());
+    println!(
+        "  📏 Total original size: {} bytes",
+        report.total_original_size
+    );
+    println!(
+        "  📏 Total compressed size: {} bytes",
+        report.total_compressed_size
+    );
+    println!("  📊 Overall compression: {:.1}%", report.compression_ratio);
+    println!("  ⏱️  Watched for: {:.1}s", report.elapsed_secs);
+
+    if !report.failures.is_empty() {
+        println!("  ⚠️  Failed files: {}", report.failures.len());
+        for failure in &report.failures {
+            println!("    ❌ {:?}: {}", failure.path, failure.error);
+        }
+    }
+    if !report.pending.is_empty() {
+        println!(
+            "  ⏳ Still being written at shutdown: {}",
+            report.pending.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, ModifyKind};
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, PathBuf, PathBuf, CompressionOptions) {
+        let temp_dir = TempDir::new().unwrap();
+        let input_dir = temp_dir.path().join("input");
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir(&input_dir).unwrap();
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        (temp_dir, input_dir, output_dir, options)
+    }
+
+    fn watch_options() -> WatchOptions {
+        WatchOptions {
+            recursive: false,
+            // Everything observed has settled by the next `process_settled`
+            debounce: Duration::ZERO,
+        }
+    }
+
+    fn created(path: PathBuf) -> Event {
+        Event::new(EventKind::Create(CreateKind::File)).add_path(path)
+    }
+
+    fn save_png(path: &Path, size: u32) {
+        image::RgbImage::from_fn(size, size, |x, y| {
+            image::Rgb([x as u8 * 16, y as u8 * 16, 64])
+        })
+        .save(path)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_watch_compresses_new_files() {
+        let (_temp_dir, input_dir, output_dir, options) = setup();
+        let mut session =
+            WatchSession::new(&input_dir, &output_dir, &options, watch_options()).unwrap();
+
+        let image_path = session.input_root.join("export.png");
+        let notes_path = session.input_root.join("notes.txt");
+        save_png(&image_path, 16);
+        fs::write(&notes_path, b"not an image").unwrap();
+        session.observe(created(image_path));
+        session.observe(created(notes_path));
+        session.process_settled();
+
+        let report = session.into_report(&input_dir, Instant::now());
+        assert!(output_dir.join("export.png").exists());
+        assert_eq!(report.files.len(), 1);
+        assert!(report.failures.is_empty() && report.pending.is_empty());
+        assert!(!output_dir.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_watch_waits_for_growing_files() {
+        let (_temp_dir, input_dir, output_dir, options) = setup();
+        let mut session =
+            WatchSession::new(&input_dir, &output_dir, &options, watch_options()).unwrap();
+
+        let image_path = session.input_root.join("export.png");
+        save_png(&image_path, 8);
+        session.observe(created(image_path.clone()));
+        // The export grows after its last event: it needs another quiet period
+        save_png(&image_path, 16);
+        session.process_settled();
+        assert!(!output_dir.join("export.png").exists());
+
+        session.process_settled();
+        assert!(output_dir.join("export.png").exists());
+
+        // A modify event without any change is not compressed again
+        session.observe(Event::new(EventKind::Modify(ModifyKind::Any)).add_path(image_path));
+        session.process_settled();
+        assert_eq!(session.files.len(), 1);
+    }
+
+    #[test]
+    fn test_watch_ignores_outputs_inside_input_dir() {
+        let (_temp_dir, input_dir, _, options) = setup();
+        let output_dir = input_dir.join("compressed");
+        let mut session =
+            WatchSession::new(&input_dir, &output_dir, &options, watch_options()).unwrap();
+
+        let output_path = session.output_root.join("export.png");
+        save_png(&output_path, 16);
+        session.observe(created(output_path));
+        assert!(session.pending.is_empty());
+    }
+
+    #[test]
+    fn test_watch_directory_returns_when_stopped() {
+        let (_temp_dir, input_dir, output_dir, options) = setup();
+        let report = watch_directory(
+            &input_dir,
+            &output_dir,
+            &options,
+            watch_options(),
+            &AtomicBool::new(true),
+        )
+        .unwrap();
+        assert!(output_dir.is_dir());
+        assert!(report.files.is_empty() && report.pending.is_empty());
+    }
+
+    #[test]
+    fn test_watch_missing_input_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let options = CompressionOptions::new(None, None, None, None).unwrap();
+        let result = watch_directory(
+            &temp_dir.path().join("missing"),
+            &temp_dir.path().join("output"),
+            &options,
+            watch_options(),
+            &AtomicBool::new(true),
+        );
+        assert!(matches!(result, Err(CompressionError::FileNotFound(_))));
+    }
+}
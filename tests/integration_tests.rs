@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use predicates::prelude::*;
 use std::fs::File;
 use std::io::Write;
 use tempfile::TempDir;
@@ -218,3 +219,1257 @@ fn test_info_with_fake_image() {
     cmd.args(["info", &test_file.to_string_lossy()]);
     cmd.assert().failure(); // Will fail because it's not a real image, but shouldn't panic
 }
+
+fn create_real_png(path: &std::path::Path) {
+    let img = image::RgbImage::from_fn(256, 256, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x * y) % 251) as u8])
+    });
+    img.save(path).unwrap();
+}
+
+fn compress_with_quality(input: &std::path::Path, output: &std::path::Path, quality: &str) -> u64 {
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compress",
+        &input.to_string_lossy(),
+        &output.to_string_lossy(),
+    ]);
+    cmd.args(["--quality", quality]);
+    cmd.assert().success();
+    std::fs::metadata(output).unwrap().len()
+}
+
+#[test]
+fn test_compress_jpeg_lower_quality_is_smaller() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("source.png");
+    create_real_png(&input);
+
+    let high = compress_with_quality(&input, &temp_dir.path().join("q90.jpg"), "90");
+    let low = compress_with_quality(&input, &temp_dir.path().join("q30.jpg"), "30");
+
+    assert!(
+        low < high,
+        "quality 30 ({} bytes) should be smaller than quality 90 ({} bytes)",
+        low,
+        high
+    );
+}
+
+#[test]
+fn test_compress_jpeg_encoder_options() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("source.png");
+    create_real_png(&input);
+
+    let output = temp_dir.path().join("progressive.jpg");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compress",
+        &input.to_string_lossy(),
+        &output.to_string_lossy(),
+    ]);
+    cmd.args([
+        "--subsampling",
+        "444",
+        "--progressive",
+        "--optimize-huffman",
+    ]);
+    cmd.assert().success();
+
+    let decoded = image::open(&output).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (256, 256));
+}
+
+#[test]
+fn test_compress_invalid_subsampling() {
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["compress", "in.png", "out.jpg", "--subsampling", "411"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_compress_webp_lossy_quality_and_lossless() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("source.png");
+    create_real_png(&input);
+
+    let high = compress_with_quality(&input, &temp_dir.path().join("q90.webp"), "90");
+    let low = compress_with_quality(&input, &temp_dir.path().join("q30.webp"), "30");
+    assert!(low < high);
+
+    let lossless = temp_dir.path().join("lossless.webp");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compress",
+        &input.to_string_lossy(),
+        &lossless.to_string_lossy(),
+    ]);
+    cmd.args(["--lossless", "--webp-method", "6"]);
+    cmd.assert().success();
+
+    let original = image::open(&input).unwrap().to_rgb8();
+    let decoded = image::open(&lossless).unwrap().to_rgb8();
+    assert_eq!(original, decoded);
+}
+
+#[test]
+fn test_compress_invalid_webp_method() {
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["compress", "in.png", "out.webp", "--webp-method", "7"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_compress_target_size() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("source.png");
+    create_real_png(&input);
+
+    let output = temp_dir.path().join("budget.jpg");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compress",
+        &input.to_string_lossy(),
+        &output.to_string_lossy(),
+    ]);
+    cmd.args(["--quality", "95", "--target-size", "8K"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Chosen quality"));
+
+    assert!(std::fs::metadata(&output).unwrap().len() <= 8 * 1024);
+}
+
+#[test]
+fn test_compress_invalid_target_size() {
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["compress", "in.png", "out.jpg", "--target-size", "12XB"]);
+    cmd.assert().failure();
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["compress", "in.png", "out.jpg", "--target-downscale"]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "target-downscale requires target-size",
+    ));
+}
+
+#[test]
+fn test_compress_target_ssim() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("source.png");
+    create_real_png(&input);
+
+    let output = temp_dir.path().join("perceptual.webp");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compress",
+        &input.to_string_lossy(),
+        &output.to_string_lossy(),
+    ]);
+    cmd.args(["--target-ssim", "0.9"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("SSIM"));
+
+    let original = image::open(&input).unwrap();
+    let decoded = image::open(&output).unwrap();
+    assert!(img_squeeze::ssim(&original, &decoded).unwrap() >= 0.9);
+}
+
+#[test]
+fn test_compress_target_ssim_conflicts() {
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["compress", "in.png", "out.jpg", "--target-ssim", "1.5"]);
+    cmd.assert().failure();
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compress",
+        "in.png",
+        "out.jpg",
+        "--target-ssim",
+        "0.98",
+        "--target-size",
+        "100K",
+    ]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_compare_command() {
+    let temp_dir = TempDir::new().unwrap();
+    let original = temp_dir.path().join("original.png");
+    create_real_png(&original);
+
+    let compressed = temp_dir.path().join("compressed.jpg");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compress",
+        &original.to_string_lossy(),
+        &compressed.to_string_lossy(),
+    ]);
+    cmd.assert().success();
+
+    let diff = temp_dir.path().join("diff.png");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compare",
+        &original.to_string_lossy(),
+        &compressed.to_string_lossy(),
+        "--diff",
+        &diff.to_string_lossy(),
+    ]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("PSNR"))
+        .stdout(predicate::str::contains("SSIM"))
+        .stdout(predicate::str::contains("Max pixel delta"))
+        .stdout(predicate::str::contains("Size savings"));
+
+    let heatmap = image::open(&diff).unwrap();
+    let source = image::open(&original).unwrap();
+    assert_eq!(heatmap.width(), source.width());
+    assert_eq!(heatmap.height(), source.height());
+}
+
+#[test]
+fn test_compare_dimension_mismatch() {
+    let temp_dir = TempDir::new().unwrap();
+    let original = temp_dir.path().join("original.png");
+    create_real_png(&original);
+
+    let resized = temp_dir.path().join("resized.png");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compress",
+        &original.to_string_lossy(),
+        &resized.to_string_lossy(),
+        "-w",
+        "10",
+    ]);
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compare",
+        &original.to_string_lossy(),
+        &resized.to_string_lossy(),
+    ]);
+    cmd.assert().failure();
+}
+
+fn run_json(args: &[&str]) -> serde_json::Value {
+    run_json_with_code(args, 0)
+}
+
+fn run_json_with_code(args: &[&str], code: i32) -> serde_json::Value {
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(args).args(["--output-format", "json"]);
+    let output = cmd.assert().code(code).get_output().stdout.clone();
+    serde_json::from_slice(&output).expect("stdout should be a single JSON document")
+}
+
+#[test]
+fn test_fatal_errors_are_json_documents() {
+    let temp_dir = TempDir::new().unwrap();
+    let missing = temp_dir.path().join("missing.png");
+    let output = temp_dir.path().join("out.jpg");
+
+    let report = run_json_with_code(
+        &[
+            "compress",
+            &missing.to_string_lossy(),
+            &output.to_string_lossy(),
+        ],
+        1,
+    );
+    assert_eq!(report["kind"], "file_not_found");
+    assert!(report["error"].as_str().unwrap().contains("missing.png"));
+
+    let config = temp_dir.path().join("img-squeeze.toml");
+    std::fs::write(&config, "").unwrap();
+    let report = run_json_with_code(
+        &[
+            "--config",
+            &config.to_string_lossy(),
+            "--preset",
+            "missing",
+            "config",
+            "show",
+        ],
+        1,
+    );
+    assert_eq!(report["kind"], "config");
+    assert!(report["error"].as_str().unwrap().contains("unknown preset"));
+}
+
+#[test]
+fn test_compress_json_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("source.png");
+    create_real_png(&input);
+    let output = temp_dir.path().join("out.jpg");
+
+    let report = run_json(&[
+        "compress",
+        &input.to_string_lossy(),
+        &output.to_string_lossy(),
+        "-w",
+        "128",
+    ]);
+    assert_eq!(report["format"], "jpeg");
+    assert_eq!(report["original_dimensions"], serde_json::json!([256, 256]));
+    assert_eq!(report["dimensions"], serde_json::json!([128, 128]));
+    assert_eq!(report["quality"], 80);
+    assert_eq!(
+        report["compressed_size"].as_u64().unwrap(),
+        std::fs::metadata(&output).unwrap().len()
+    );
+    assert!(report["compression_ratio"].is_f64());
+    assert!(report["elapsed_secs"].is_f64());
+}
+
+#[test]
+fn test_batch_json_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    create_real_png(&input_dir.join("good.png"));
+    File::create(input_dir.join("broken.png"))
+        .unwrap()
+        .write_all(b"not a png")
+        .unwrap();
+    let output_dir = temp_dir.path().join("output");
+
+    let report = run_json_with_code(
+        &[
+            "batch",
+            &input_dir.to_string_lossy(),
+            &output_dir.to_string_lossy(),
+        ],
+        2,
+    );
+    assert_eq!(report["outcome"], "partial_failure");
+    assert_eq!(report["total_files"], 2);
+    assert_eq!(report["files"].as_array().unwrap().len(), 1);
+    let failures = report["failures"].as_array().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0]["path"]
+        .as_str()
+        .unwrap()
+        .ends_with("broken.png"));
+    assert!(!failures[0]["error"].as_str().unwrap().is_empty());
+}
+
+#[test]
+fn test_info_and_compare_json_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("source.png");
+    create_real_png(&input);
+
+    let info = run_json(&["info", &input.to_string_lossy()]);
+    assert_eq!(info["format"], "png");
+    assert_eq!(info["dimensions"], serde_json::json!([256, 256]));
+    assert_eq!(info["total_pixels"], 65536);
+    assert!(!info["suggestions"].as_array().unwrap().is_empty());
+
+    let comparison = run_json(&[
+        "compare",
+        &input.to_string_lossy(),
+        &input.to_string_lossy(),
+    ]);
+    assert_eq!(comparison["max_delta"], 0);
+    assert_eq!(comparison["psnr"], serde_json::Value::Null);
+    assert_eq!(comparison["ssim"], 1.0);
+}
+
+#[test]
+fn test_invalid_output_format() {
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["info", "image.png", "--output-format", "yaml"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_batch_recursive_mirrors_directories() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    for sub in ["a", "b/nested"] {
+        std::fs::create_dir_all(input_dir.join(sub)).unwrap();
+        create_real_png(&input_dir.join(sub).join("logo.png"));
+    }
+    let output_dir = temp_dir.path().join("output");
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "batch",
+        &input_dir.to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "-r",
+    ]);
+    cmd.assert().success();
+
+    assert!(output_dir.join("a/logo.png").exists());
+    assert!(output_dir.join("b/nested/logo.png").exists());
+    assert!(!output_dir.join("logo.png").exists());
+}
+
+#[test]
+fn test_batch_reports_output_collisions() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    create_real_png(&input_dir.join("photo.png"));
+    image::open(input_dir.join("photo.png"))
+        .unwrap()
+        .save(input_dir.join("photo.jpg"))
+        .unwrap();
+    let output_dir = temp_dir.path().join("output");
+
+    let report = run_json_with_code(
+        &[
+            "batch",
+            &input_dir.to_string_lossy(),
+            &output_dir.to_string_lossy(),
+            "-f",
+            "webp",
+        ],
+        2,
+    );
+    assert_eq!(report["files"].as_array().unwrap().len(), 1);
+    let failures = report["failures"].as_array().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0]["path"].as_str().unwrap().ends_with("photo.png"));
+    assert!(failures[0]["error"]
+        .as_str()
+        .unwrap()
+        .contains("already claimed"));
+}
+
+#[test]
+fn test_batch_total_failure_exit_code() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    for name in ["one.png", "two.jpg"] {
+        File::create(input_dir.join(name))
+            .unwrap()
+            .write_all(b"garbage")
+            .unwrap();
+    }
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "batch",
+        &input_dir.to_string_lossy(),
+        &temp_dir.path().join("output").to_string_lossy(),
+    ]);
+    cmd.assert()
+        .code(3)
+        .stdout(predicate::str::contains("Failed files: 2"));
+}
+
+#[test]
+fn test_batch_fail_fast_cancels_remaining_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    File::create(input_dir.join("a_broken.png"))
+        .unwrap()
+        .write_all(b"garbage")
+        .unwrap();
+    create_real_png(&input_dir.join("b_good.png"));
+    create_real_png(&input_dir.join("c_good.png"));
+
+    let report = run_json_with_code(
+        &[
+            "batch",
+            &input_dir.to_string_lossy(),
+            &temp_dir.path().join("output").to_string_lossy(),
+            "--fail-fast",
+            "-j",
+            "1",
+        ],
+        3,
+    );
+    assert_eq!(report["outcome"], "total_failure");
+    assert_eq!(report["failures"].as_array().unwrap().len(), 1);
+    assert_eq!(report["cancelled"].as_array().unwrap().len(), 2);
+    assert!(report["files"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_batch_incremental_skips_unchanged_inputs() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    create_real_png(&input_dir.join("one.png"));
+    create_real_png(&input_dir.join("two.png"));
+    let output_dir = temp_dir.path().join("output");
+    let batch = |extra: &[&str]| {
+        let mut args = vec![
+            "batch".to_string(),
+            input_dir.to_string_lossy().into_owned(),
+            output_dir.to_string_lossy().into_owned(),
+            "--incremental".to_string(),
+        ];
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        run_json(&args)
+    };
+
+    let first = batch(&[]);
+    assert_eq!(first["files"].as_array().unwrap().len(), 2);
+    assert!(output_dir.join(".img-squeeze-manifest.json").exists());
+
+    let second = batch(&[]);
+    assert!(second["files"].as_array().unwrap().is_empty());
+    assert_eq!(second["skipped"].as_array().unwrap().len(), 2);
+    assert_eq!(second["outcome"], "success");
+
+    // A changed input and changed options both force recompression
+    image::RgbImage::new(8, 8)
+        .save(input_dir.join("one.png"))
+        .unwrap();
+    let third = batch(&[]);
+    assert_eq!(third["files"].as_array().unwrap().len(), 1);
+    assert_eq!(third["skipped"].as_array().unwrap().len(), 1);
+
+    let fourth = batch(&["-q", "60"]);
+    assert_eq!(fourth["files"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_batch_dry_run_rejects_what_the_run_rejects() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    create_real_png(&input_dir.join("photo.png"));
+    let output_dir = temp_dir.path().join("output");
+
+    for flags in [
+        ["--upload", "--renditions", "64:webp"].as_slice(),
+        ["--upload", "--incremental"].as_slice(),
+    ] {
+        let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+        cmd.arg("batch")
+            .arg(&input_dir)
+            .arg(&output_dir)
+            .args(flags)
+            .arg("--dry-run");
+        cmd.assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+    assert!(!output_dir.exists());
+}
+
+#[test]
+fn test_batch_dry_run_writes_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    create_real_png(&input_dir.join("photo.png"));
+    image::open(input_dir.join("photo.png"))
+        .unwrap()
+        .save(input_dir.join("photo.jpg"))
+        .unwrap();
+    create_real_png(&input_dir.join("other.png"));
+    let output_dir = temp_dir.path().join("output");
+
+    let plan = run_json(&[
+        "batch",
+        &input_dir.to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "-f",
+        "webp",
+        "--dry-run",
+    ]);
+    assert!(!output_dir.exists());
+    assert!(plan["threads"].as_u64().unwrap() >= 1);
+    assert!(plan["estimated_memory_mib"].as_f64().unwrap() > 0.0);
+
+    let files = plan["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert!(files.iter().all(|file| file["format"] == "webp"));
+    assert!(files[0]["output"].as_str().unwrap().ends_with("other.webp"));
+    assert_eq!(files[0]["overwrites"], false);
+    let failures = plan["failures"].as_array().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0]["error"]
+        .as_str()
+        .unwrap()
+        .contains("already claimed"));
+
+    // Existing outputs are flagged as overwrites
+    std::fs::create_dir(&output_dir).unwrap();
+    File::create(output_dir.join("other.webp")).unwrap();
+    let plan = run_json(&[
+        "batch",
+        &input_dir.to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "-f",
+        "webp",
+        "--dry-run",
+    ]);
+    assert_eq!(plan["files"][0]["overwrites"], true);
+    assert_eq!(
+        std::fs::metadata(output_dir.join("other.webp"))
+            .unwrap()
+            .len(),
+        0
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_watch_compresses_dropped_files_and_summarizes_on_sigterm() {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command as StdCommand, Stdio};
+    use std::time::{Duration, Instant};
+
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    let output_dir = temp_dir.path().join("output");
+
+    let mut child = StdCommand::new(assert_cmd::cargo::cargo_bin("img-squeeze"))
+        .args([
+            "--output-format",
+            "json",
+            "watch",
+            &input_dir.to_string_lossy(),
+            &output_dir.to_string_lossy(),
+            "-f",
+            "webp",
+            "--debounce-ms",
+            "100",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Files dropped before the watcher runs would be missed: wait for its banner
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    while !line.contains("Watching") {
+        line.clear();
+        assert!(
+            stderr.read_line(&mut line).unwrap() > 0,
+            "watch exited early"
+        );
+    }
+    // Keep draining stderr so the child never blocks on a full pipe
+    let drain = std::thread::spawn(move || std::io::copy(&mut stderr, &mut std::io::sink()));
+
+    create_real_png(&input_dir.join("export.png"));
+    let output = output_dir.join("export.webp");
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !output.exists() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    StdCommand::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let result = child.wait_with_output().unwrap();
+    drain.join().unwrap().unwrap();
+    assert!(result.status.success());
+    assert!(output.exists());
+
+    let report: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+    let files = report["files"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["format"], "webp");
+    assert!(report["failures"].as_array().unwrap().is_empty());
+}
+
+const PRESET_CONFIG: &str = r#"
+[defaults]
+quality = 85
+
+[presets.web-thumb]
+width = 64
+height = 64
+resize-mode = "fill"
+format = "webp"
+quality = 70
+
+[presets.archive]
+quality = 95
+walrus = { epochs = 200 }
+"#;
+
+/// Runs the CLI in `dir` with the user config directory pointed inside it
+fn run_json_in(dir: &std::path::Path, args: &[&str]) -> serde_json::Value {
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir.join("xdg"))
+        .args(args)
+        .args(["--output-format", "json"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    serde_json::from_slice(&output).expect("stdout should be a single JSON document")
+}
+
+#[test]
+fn test_preset_from_project_config_with_cli_override() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join("img-squeeze.toml"), PRESET_CONFIG).unwrap();
+    create_real_png(&temp_dir.path().join("source.png"));
+
+    let report = run_json_in(
+        temp_dir.path(),
+        &[
+            "compress",
+            "source.png",
+            "thumb.webp",
+            "--preset",
+            "web-thumb",
+            "-q",
+            "60",
+        ],
+    );
+    assert_eq!(report["format"], "webp");
+    assert_eq!(report["dimensions"], serde_json::json!([64, 64]));
+    assert_eq!(report["quality"], 60);
+
+    // Without a preset only [defaults] applies
+    let report = run_json_in(temp_dir.path(), &["compress", "source.png", "out.jpg"]);
+    assert_eq!(report["quality"], 85);
+    assert_eq!(report["dimensions"], serde_json::json!([256, 256]));
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.current_dir(temp_dir.path())
+        .env("XDG_CONFIG_HOME", temp_dir.path().join("xdg"))
+        .args(["compress", "source.png", "out.jpg", "--preset", "missing"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown preset"));
+}
+
+#[test]
+fn test_config_show_uses_user_config_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    let user_dir = temp_dir.path().join("xdg").join("img-squeeze");
+    std::fs::create_dir_all(&user_dir).unwrap();
+    std::fs::write(user_dir.join("img-squeeze.toml"), PRESET_CONFIG).unwrap();
+
+    let effective = run_json_in(temp_dir.path(), &["config", "show", "--preset", "archive"]);
+    assert!(effective["source"]
+        .as_str()
+        .unwrap()
+        .ends_with("img-squeeze/img-squeeze.toml"));
+    assert_eq!(
+        effective["presets"],
+        serde_json::json!(["archive", "web-thumb"])
+    );
+    assert_eq!(effective["settings"]["quality"], 95);
+    assert_eq!(effective["settings"]["metadata"], "strip");
+    assert_eq!(effective["settings"]["walrus"]["epochs"], 200);
+
+    // A project config takes precedence over the user config
+    std::fs::write(
+        temp_dir.path().join("img-squeeze.toml"),
+        "[defaults]\nquality = 42\n",
+    )
+    .unwrap();
+    let effective = run_json_in(temp_dir.path(), &["config", "show"]);
+    assert_eq!(effective["settings"]["quality"], 42);
+    assert!(effective["presets"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_compress_and_batch_renditions() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    create_real_png(&input_dir.join("hero.png"));
+
+    let output_dir = temp_dir.path().join("single");
+    let report = run_json(&[
+        "compress",
+        &input_dir.join("hero.png").to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "--renditions",
+        "64,128,1024:webp,jpeg",
+        "--rendition-template",
+        "{stem}_{width}.{format}.{ext}",
+    ]);
+    assert_eq!(report["renditions"].as_array().unwrap().len(), 4);
+    assert_eq!(report["renditions"][2]["dimensions"][0], 128);
+    assert!(output_dir.join("hero_64.jpeg.jpg").exists());
+    assert!(output_dir.join("hero_128.webp.webp").exists());
+    assert!(!output_dir.join("hero_1024.webp.webp").exists());
+
+    let output_dir = temp_dir.path().join("batch");
+    let plan = run_json(&[
+        "batch",
+        &input_dir.to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "--renditions",
+        "64,128:avif,webp",
+        "--dry-run",
+    ]);
+    assert_eq!(plan["files"][0]["format"], "avif,webp");
+    assert_eq!(plan["files"][0]["renditions"].as_array().unwrap().len(), 4);
+    assert!(!output_dir.exists());
+
+    let report = run_json(&[
+        "batch",
+        &input_dir.to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "--renditions",
+        "64,128:avif,webp",
+    ]);
+    assert_eq!(report["files"].as_array().unwrap().len(), 4);
+    assert!(output_dir.join("hero-64w.avif").exists());
+    assert!(output_dir.join("hero-128w.webp").exists());
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "compress",
+        &input_dir.join("hero.png").to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "--renditions",
+        "64",
+        "--rendition-template",
+        "{stem}.{ext}",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("{width}"));
+}
+
+#[test]
+fn test_batch_asset_manifest_and_html_snippets() {
+    let temp_dir = TempDir::new().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir_all(input_dir.join("blog")).unwrap();
+    create_real_png(&input_dir.join("blog").join("hero.png"));
+    let output_dir = temp_dir.path().join("site");
+
+    let report = run_json(&[
+        "batch",
+        &input_dir.to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "-r",
+        "--renditions",
+        "64,128:webp,jpeg",
+        "--asset-manifest",
+        "--html-snippets",
+        "--html-sizes",
+        "(max-width: 600px) 100vw, 600px",
+        "--html-base-url",
+        "/img/",
+    ]);
+    assert_eq!(report["assets"].as_array().unwrap().len(), 2);
+
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(output_dir.join("asset-manifest.json")).unwrap())
+            .unwrap();
+    let hero = &manifest["sources"]["blog/hero.png"];
+    assert_eq!(hero["html"], "blog/hero.png.html");
+    let outputs = hero["outputs"].as_array().unwrap();
+    assert_eq!(outputs.len(), 4);
+    assert_eq!(outputs[0]["path"], "blog/hero-64w.webp");
+    assert_eq!(outputs[0]["width"], 64);
+    assert_eq!(outputs[0]["height"], 64);
+    assert_eq!(outputs[0]["format"], "webp");
+    let written = std::fs::read(output_dir.join("blog/hero-64w.webp")).unwrap();
+    assert_eq!(outputs[0]["bytes"], written.len());
+    assert_eq!(outputs[0]["sha256"].as_str().unwrap().len(), 64);
+
+    let html = std::fs::read_to_string(output_dir.join("blog/hero.png.html")).unwrap();
+    assert!(html.starts_with("<picture>"));
+    assert!(html.contains(
+        "<source type=\"image/webp\" srcset=\"/img/blog/hero-64w.webp 64w, /img/blog/hero-128w.webp 128w\" sizes=\"(max-width: 600px) 100vw, 600px\">"
+    ));
+    assert!(html.contains("<img src=\"/img/blog/hero-128w.jpg\""));
+    assert!(html.contains("width=\"128\" height=\"128\""));
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args([
+        "batch",
+        &input_dir.to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "--asset-manifest",
+        "--incremental",
+    ]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_compress_stdin_to_stdout() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("input.png");
+    create_real_png(&input);
+    let png = std::fs::read(&input).unwrap();
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    let output = cmd
+        .args(["compress", "-", "-", "-f", "webp"])
+        .write_stdin(png.clone())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let (decoded, format) = img_squeeze::load_image_from_bytes(&output.stdout, false).unwrap();
+    assert_eq!(format, image::ImageFormat::WebP);
+    assert_eq!(image::GenericImageView::dimensions(&decoded), (256, 256));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Compressed"));
+
+    // The input format is sniffed, the output format comes from the file name
+    let output_file = temp_dir.path().join("from-stdin.jpg");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["compress", "-", &output_file.to_string_lossy()])
+        .write_stdin(png.clone());
+    cmd.assert().success();
+    assert_eq!(
+        image::ImageFormat::from_path(&output_file).unwrap(),
+        image::guess_format(&std::fs::read(&output_file).unwrap()).unwrap()
+    );
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["compress", "-", "-"]).write_stdin(png);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--format is required"));
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["compress", "-", "-", "-f", "png"])
+        .write_stdin(b"not an image".to_vec());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot detect the image format"));
+}
+
+/// Stands in for Walrus: serves `GET /v1/blobs/<blob_id>` with `body` like an
+/// aggregator and stores `PUT /v1/blobs` like a publisher, naming each blob
+/// `stored-<sha256 prefix>` and storing it until epoch 100 + `epochs`
+fn walrus_stand_in(blob_id: &str, body: Vec<u8>) -> String {
+    use sha2::{Digest, Sha256};
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let blob_path = format!("/v1/blobs/{}", blob_id);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            let mut chunked = false;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                let header = line.to_lowercase();
+                if let Some(value) = header.strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                chunked |= header.starts_with("transfer-encoding:") && header.contains("chunked");
+                line.clear();
+            }
+            let mut request_body = vec![0; content_length];
+            reader.read_exact(&mut request_body).unwrap();
+            // Uploads are streamed with chunked transfer encoding
+            while chunked {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let size = usize::from_str_radix(line.trim(), 16).unwrap();
+                let mut chunk = vec![0; size + 2];
+                reader.read_exact(&mut chunk).unwrap();
+                request_body.extend_from_slice(&chunk[..size]);
+                chunked = size > 0;
+            }
+
+            let (status, response) = if request_line.starts_with(&format!("GET {} ", blob_path)) {
+                ("200 OK", body.clone())
+            } else if request_line.starts_with("PUT /v1/blobs?") {
+                let epochs: u64 = request_line
+                    .split(['?', '&', ' '])
+                    .find_map(|pair| pair.strip_prefix("epochs="))
+                    .map_or(1, |epochs| epochs.parse().unwrap());
+                let hash = format!("{:x}", Sha256::digest(&request_body));
+                let stored = serde_json::json!({
+                    "newlyCreated": {
+                        "blobObject": {
+                            "id": "0x1",
+                            "registeredEpoch": 100,
+                            "blobId": format!("stored-{}", &hash[..12]),
+                            "size": request_body.len(),
+                            "encodingType": "RS2",
+                            "certifiedEpoch": 100,
+                            "storage": {
+                                "id": "0x2",
+                                "startEpoch": 100,
+                                "endEpoch": 100 + epochs,
+                                "storageSize": request_body.len()
+                            },
+                            "deletable": true
+                        },
+                        "resourceOperation": { "registerFromScratch": null },
+                        "cost": 1
+                    }
+                });
+                ("200 OK", stored.to_string().into_bytes())
+            } else {
+                ("404 Not Found", b"not found".to_vec())
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                response.len()
+            )
+            .unwrap();
+            stream.write_all(&response).unwrap();
+        }
+    });
+    url
+}
+
+#[test]
+fn test_download_from_walrus_stand_in() {
+    let temp_dir = TempDir::new().unwrap();
+    let source = temp_dir.path().join("source.png");
+    create_real_png(&source);
+    let png = std::fs::read(&source).unwrap();
+    let url = walrus_stand_in("blob-1", png.clone());
+
+    let out_dir = temp_dir.path().join("downloads");
+    std::fs::create_dir(&out_dir).unwrap();
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    let output = cmd
+        .args(["--output-format", "json", "download", "blob-1"])
+        .arg(&out_dir)
+        .args(["-a", &url])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["format"], "png");
+    assert_eq!(report["verified"], false);
+    assert_eq!(std::fs::read(out_dir.join("blob-1.png")).unwrap(), png);
+
+    // Verifying against the recorded hash
+    let sha256 = report["sha256"].as_str().unwrap().to_string();
+    let copy = temp_dir.path().join("copy.png");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["download", "blob-1"])
+        .arg(&copy)
+        .args(["-a", &url, "--sha256", &sha256]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Hash verified"));
+
+    let rejected = temp_dir.path().join("rejected.png");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["download", "blob-1"])
+        .arg(&rejected)
+        .args(["-a", &url, "--sha256", "0123"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("HashMismatch"));
+    assert!(!rejected.exists());
+}
+
+#[test]
+fn test_download_verifies_against_upload_manifest() {
+    use sha2::{Digest, Sha256};
+
+    let temp_dir = TempDir::new().unwrap();
+    let source = temp_dir.path().join("source.png");
+    create_real_png(&source);
+    let png = std::fs::read(&source).unwrap();
+    // The stand-in names stored blobs after their hash, so it can serve this one back
+    let blob_id = format!("stored-{}", &format!("{:x}", Sha256::digest(&png))[..12]);
+    let url = walrus_stand_in(&blob_id, png.clone());
+
+    let manifest = temp_dir.path().join("blobs.json");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.arg("upload")
+        .arg(temp_dir.path().join("*.png"))
+        .args(["-a", &url, "-p", &url, "--manifest"])
+        .arg(&manifest);
+    cmd.assert().success();
+
+    let copy = temp_dir.path().join("copy.png");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    let output = cmd
+        .args(["--output-format", "json", "download", &blob_id])
+        .arg(&copy)
+        .args(["-a", &url, "--manifest"])
+        .arg(&manifest)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["verified"], true);
+    assert_eq!(std::fs::read(&copy).unwrap(), png);
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.args(["download", "other-blob"])
+        .arg(temp_dir.path().join("other.png"))
+        .args(["-a", &url, "--manifest"])
+        .arg(&manifest);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("not listed"));
+}
+
+#[test]
+fn test_upload_directory_writes_blob_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let assets = temp_dir.path().join("assets");
+    std::fs::create_dir_all(assets.join("icons")).unwrap();
+    create_real_png(&assets.join("hero.png"));
+    image::RgbImage::new(16, 16)
+        .save(assets.join("icons/logo.png"))
+        .unwrap();
+    let url = walrus_stand_in("unused", Vec::new());
+    let manifest_path = temp_dir.path().join("blobs.json");
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    let output = cmd
+        .args(["--output-format", "json", "upload"])
+        .arg(&assets)
+        .args([
+            "-r",
+            "-j",
+            "2",
+            "-e",
+            "3",
+            "-a",
+            &url,
+            "-p",
+            &url,
+            "--manifest",
+        ])
+        .arg(&manifest_path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["outcome"], "success");
+    assert_eq!(report["files"].as_array().unwrap().len(), 2);
+
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&manifest_path).unwrap()).unwrap();
+    let blobs = manifest["blobs"].as_object().unwrap();
+    let keys: Vec<_> = blobs.keys().cloned().collect();
+    assert_eq!(keys, ["hero.png", "icons/logo.png"]);
+    let hero = &blobs["hero.png"];
+    assert_eq!(hero["epochs"], 3);
+    assert_eq!(hero["end_epoch"], 103);
+    assert_eq!(
+        hero["size"],
+        std::fs::metadata(assets.join("hero.png")).unwrap().len()
+    );
+    assert_eq!(
+        hero["access_url"],
+        format!("{}/v1/blobs/{}", url, hero["blob_id"].as_str().unwrap())
+    );
+    assert!(hero["blob_id"].as_str().unwrap().starts_with(&format!(
+        "stored-{}",
+        &hero["sha256"].as_str().unwrap()[..12]
+    )));
+
+    // A glob is keyed relative to the directory holding its matches
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.arg("upload")
+        .arg(assets.join("icons/*.png"))
+        .args(["-a", &url, "-p", &url, "--manifest"])
+        .arg(&manifest_path);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Blob manifest written"));
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&manifest_path).unwrap()).unwrap();
+    assert!(manifest["blobs"]["logo.png"]["blob_id"].is_string());
+
+    // Nothing accepts uploads on the publisher side
+    let dead = walrus_stand_in("unused", Vec::new());
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.arg("upload")
+        .arg(&assets)
+        .args(["-a", &dead, "-p", &format!("{}/nowhere/", dead)]);
+    cmd.assert().code(3).stdout(predicate::str::contains("❌"));
+}
+
+#[test]
+fn test_compress_and_batch_upload_outputs() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("photo.png");
+    create_real_png(&input);
+    let url = walrus_stand_in("unused", Vec::new());
+
+    // Uploaded from memory without keeping the local file
+    let output = temp_dir.path().join("photo.webp");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    let result = cmd
+        .args(["--output-format", "json", "compress"])
+        .arg(&input)
+        .arg(&output)
+        .args(["--upload", "--no-keep", "-a", &url, "-p", &url, "-e", "2"])
+        .output()
+        .unwrap();
+    assert!(result.status.success());
+    assert!(!output.exists());
+    let report: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+    assert_eq!(report["format"], "webp");
+    assert_eq!(report["blob"]["end_epoch"], 102);
+    assert_eq!(report["blob"]["kept_local"], false);
+    let blob_id = report["blob"]["blob_id"].as_str().unwrap().to_string();
+    assert_eq!(
+        blob_id,
+        format!(
+            "stored-{}",
+            &report["blob"]["sha256"].as_str().unwrap()[..12]
+        )
+    );
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.arg("compress")
+        .arg(&input)
+        .arg(&output)
+        .args(["--upload", "-a", &url, "-p", &url]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!("Blob ID: {}", blob_id)));
+    assert!(output.exists());
+
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir).unwrap();
+    create_real_png(&input_dir.join("a.png"));
+    File::create(input_dir.join("broken.png"))
+        .unwrap()
+        .write_all(b"not a png")
+        .unwrap();
+    let output_dir = temp_dir.path().join("output");
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    let result = cmd
+        .args(["--output-format", "json", "batch"])
+        .arg(&input_dir)
+        .arg(&output_dir)
+        .args(["-f", "webp", "--upload", "-a", &url, "-p", &url])
+        .output()
+        .unwrap();
+    assert_eq!(result.status.code(), Some(2));
+    let report: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+    assert_eq!(report["files"][0]["blob"]["blob_id"], blob_id);
+    assert!(output_dir.join("a.webp").exists());
+
+    let mut cmd = Command::cargo_bin("img-squeeze").unwrap();
+    cmd.arg("batch").arg(&input_dir).arg(&output_dir).args([
+        "--upload",
+        "--no-keep",
+        "--asset-manifest",
+    ]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
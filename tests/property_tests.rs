@@ -1,6 +1,8 @@
 use image::{DynamicImage, GenericImageView, ImageFormat};
 use img_squeeze::batch::is_image_file;
-use img_squeeze::processing::{determine_output_format, resize_image, CompressionOptions};
+use img_squeeze::processing::{
+    determine_output_format, resize_image, CompressionOptions, ResizeMode,
+};
 use proptest::prelude::*;
 use std::path::Path;
 
@@ -32,7 +34,9 @@ proptest! {
         prop_assume!(new_width != width); // Only test if resize is needed
 
         let mut img = DynamicImage::new_rgb8(width, height);
-        let options = CompressionOptions::new(Some(80), Some(new_width), None, None).unwrap();
+        let options = CompressionOptions::new(Some(80), Some(new_width), None, None)
+            .unwrap()
+            .with_resize_mode(ResizeMode::Exact);
 
         resize_image(&mut img, &options);
 
@@ -53,7 +57,9 @@ proptest! {
         prop_assume!(new_height != height); // Only test if resize is needed
 
         let mut img = DynamicImage::new_rgb8(width, height);
-        let options = CompressionOptions::new(Some(80), None, Some(new_height), None).unwrap();
+        let options = CompressionOptions::new(Some(80), None, Some(new_height), None)
+            .unwrap()
+            .with_resize_mode(ResizeMode::Exact);
 
         resize_image(&mut img, &options);
 
@@ -64,13 +70,39 @@ proptest! {
         assert_eq!(new_w, width);
     }
 
+    #[test]
+    fn resize_image_fit_stays_within_box_and_keeps_aspect(
+        width in 100u32..=1000u32,
+        height in 100u32..=1000u32,
+        box_width in 50u32..=800u32,
+        box_height in 50u32..=800u32
+    ) {
+        let mut img = DynamicImage::new_rgb8(width, height);
+        let options =
+            CompressionOptions::new(Some(80), Some(box_width), Some(box_height), None).unwrap();
+
+        resize_image(&mut img, &options);
+
+        let (new_w, new_h) = img.dimensions();
+
+        // Fits within the box and touches at least one edge
+        assert!(new_w <= box_width && new_h <= box_height);
+        assert!(new_w == box_width || new_h == box_height);
+
+        // Aspect ratio is preserved up to rounding
+        let original = width as f64 / height as f64;
+        let resized = new_w as f64 / new_h as f64;
+        let tolerance = original / new_h as f64 + 1.0 / new_h as f64;
+        assert!((original - resized).abs() <= tolerance);
+    }
+
     #[test]
     fn determine_output_format_property(
         filename in "[a-zA-Z0-9_-]+\\.[a-zA-Z]{3,4}",
         format_override in prop::option::weighted(0.3, "[a-zA-Z]{3,4}")
     ) {
         let path = Path::new(&filename);
-        let format_opt = format_override.as_ref().map(|s| s.as_str());
+        let format_opt = format_override.as_deref();
 
         let result = determine_output_format(path, &format_opt.map(|s| s.to_string()));
 
@@ -120,7 +152,7 @@ proptest! {
             }
             Err(_) => {
                 // Only error should be invalid quality
-                assert!(quality.map_or(false, |q| q == 0 || q > 100));
+                assert!(quality.is_some_and(|q| q == 0 || q > 100));
             }
         }
     }